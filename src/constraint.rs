@@ -1,15 +1,933 @@
+use crate::phf::PhfSet;
+use crate::rule::Env;
 use crate::status::Status;
+use crate::trie::Trie;
+use anyhow::ensure;
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Datelike, NaiveDate, TimeDelta, Utc, Weekday};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "hash")]
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+#[cfg(feature = "hash")]
+use std::fmt::Write;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Hash algorithms supported by `Constraint::HashEquals`
+#[cfg(feature = "hash")]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum HashAlg {
+    Sha256,
+}
+
+#[cfg(feature = "hash")]
+impl HashAlg {
+    /// Lowercase hex digest of `val`
+    pub fn hex_digest(self, val: &str) -> String {
+        let digest = match self {
+            HashAlg::Sha256 => Sha256::digest(val.as_bytes()),
+        };
+        digest.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{:02x}", b);
+            hex
+        })
+    }
+}
+
+/// Comparison operators of `Constraint::Compare`
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// Whether `ordering`, the value compared to the target, satisfies this operator
+    pub fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// How `Constraint::Compare` parses the value and its target
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum ValueType {
+    Int,
+    Float,
+    String,
+}
+
+impl ValueType {
+    /// Ordering of `val` relative to `target` once both are parsed as this type,
+    /// `None` if either fails to parse or floats are unordered (`NaN`)
+    pub fn compare(self, val: &str, target: &str) -> Option<Ordering> {
+        match self {
+            ValueType::Int => Some(val.parse::<isize>().ok()?.cmp(&target.parse().ok()?)),
+            ValueType::Float => val.parse::<f64>().ok()?.partial_cmp(&target.parse().ok()?),
+            ValueType::String => Some(val.cmp(target)),
+        }
+    }
+}
+
+/// Number formatting conventions understood by `Constraint::NumberAtLeast`
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum NumberLocale {
+    /// `1,234.56`
+    Us,
+    /// `1.234,56`
+    European,
+}
+
+impl NumberLocale {
+    /// Grouping and decimal separators, in that order
+    pub fn separators(self) -> (char, char) {
+        match self {
+            NumberLocale::Us => (',', '.'),
+            NumberLocale::European => ('.', ','),
+        }
+    }
+
+    /// Parses `val` written in this locale, e.g. `"1.234,56"` as `1234.56` for
+    /// `European`.
+    ///
+    /// Grouping separators are optional but, when present, must split the integer
+    /// part into groups of three digits. `None` if `val` is not such a number
+    pub fn parse(self, val: &str) -> Option<f64> {
+        let (group, decimal) = self.separators();
+        let val = val.trim();
+        let (sign, unsigned) = match val.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", val.strip_prefix('+').unwrap_or(val)),
+        };
+        let (int, fraction) = match unsigned.split_once(decimal) {
+            Some((int, fraction)) => (int, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let mut groups = int.split(group);
+        let first = groups.next()?;
+        let grouped = groups.all(|g| g.len() == 3 && is_digits(g));
+        if !is_digits(first)
+            || !grouped
+            || (int.contains(group) && first.len() > 3)
+            || !fraction.is_none_or(is_digits)
+        {
+            return None;
+        }
+        let digits: String = int.split(group).collect();
+        let normalized = match fraction {
+            Some(fraction) => format!("{}{}.{}", sign, digits, fraction),
+            None => format!("{}{}", sign, digits),
+        };
+        normalized.parse().ok()
+    }
+}
+
+/// Splits an array fact into its elements.
+///
+/// Facts are plain strings, so arrays are written as comma-separated values with
+/// surrounding whitespace trimmed from each element. An empty string is the empty array
+pub fn array_elements(val: &str) -> impl Iterator<Item = &str> {
+    let val = val.trim();
+    (!val.is_empty())
+        .then(|| val.split(',').map(str::trim))
+        .into_iter()
+        .flatten()
+}
+
+/// Value found by following a dotted `path` such as `"items.0.id"` into `json`.
+///
+/// Segments name object keys, or indices when the current value is an array.
+/// The empty path is the document itself
+#[cfg(feature = "json")]
+pub fn json_path<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+    if path.is_empty() {
+        return Some(json);
+    }
+    path.split('.').try_fold(json, |node, segment| match *node {
+        serde_json::Value::Object(ref map) => map.get(segment),
+        serde_json::Value::Array(ref items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Number of digits after the decimal point of the numeric value as written,
+/// with an exponent shifting the point, e.g. `1.25e1` has one decimal place.
+///
+/// `None` if `val` is not a finite number
+fn decimal_places(val: &str) -> Option<usize> {
+    if !val.parse::<f64>().ok()?.is_finite() {
+        return None;
+    }
+    let (mantissa, exponent) = match val.find(['e', 'E']) {
+        Some(i) => (&val[..i], val[i + 1..].parse::<i64>().ok()?),
+        None => (val, 0),
+    };
+    let fraction = mantissa.split_once('.').map_or(0, |(_, f)| f.len()) as i64;
+    Some((fraction - exponent).max(0) as usize)
+}
+
+/// Whether `expression` evaluates truthy with `val` bound to `x`, `None` if it
+/// fails to evaluate.
+///
+/// `x` is an integer or float when `val` parses as one, a string otherwise
+#[cfg(feature = "expr")]
+fn expression_holds(expression: &Expr, val: &str) -> Option<bool> {
+    use evalexpr::{ContextWithMutableVariables, DefaultNumericTypes, HashMapContext, Value};
+
+    let x = if let Ok(i) = val.parse() {
+        Value::Int(i)
+    } else if let Ok(f) = val.parse() {
+        Value::Float(f)
+    } else {
+        Value::String(val.to_owned())
+    };
+    let mut context = HashMapContext::<DefaultNumericTypes>::new();
+    context.set_value("x".into(), x).ok()?;
+    match expression.tree.eval_with_context(&context).ok()? {
+        Value::Boolean(b) => Some(b),
+        Value::Int(i) => Some(i != 0),
+        Value::Float(f) => Some(f != 0.0),
+        _ => Some(false),
+    }
+}
+
+/// Length written as a number followed by a unit, e.g. `"30cm"` or `"2 ft"`, in meters.
+///
+/// Units are `mm`, `cm`, `m`, `km`, `in`, `ft`, `yd` and `mi`. `None` for anything else
+fn length_in_meters(val: &str) -> Option<f64> {
+    let val = val.trim();
+    let split = val.find(|c: char| c.is_alphabetic())?;
+    let (number, unit) = val.split_at(split);
+    let meters_per_unit = match unit {
+        "mm" => 0.001,
+        "cm" => 0.01,
+        "m" => 1.0,
+        "km" => 1000.0,
+        "in" => 0.0254,
+        "ft" => 0.3048,
+        "yd" => 0.9144,
+        "mi" => 1609.344,
+        _ => return None,
+    };
+    Some(number.trim_end().parse::<f64>().ok()? * meters_per_unit)
+}
+
+/// Shannon entropy of the character distribution of `val` times its length, in bits.
+///
+/// With `p(c)` the share of characters equal to `c`, this is
+/// `len * -Σ p(c) * log2(p(c))`. Repeating one character gives `0`
+pub fn entropy_bits(val: &str) -> f64 {
+    let mut counts = BTreeMap::new();
+    for c in val.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = counts.values().sum::<usize>() as f64;
+    let per_char = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum::<f64>();
+    per_char * len
+}
+
+/// Value at `x` of the piecewise-linear curve through `points`, ordered by ascending
+/// x, holding the end values constant beyond the first and last point.
+///
+/// `None` if there are no points
+fn interpolate(points: &[(f64, f64)], x: f64) -> Option<f64> {
+    let (&(first_x, first_y), &(last_x, last_y)) = (points.first()?, points.last()?);
+    if x <= first_x {
+        return Some(first_y);
+    }
+    if x >= last_x {
+        return Some(last_y);
+    }
+    points.windows(2).find_map(|w| {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        (x0..=x1)
+            .contains(&x)
+            .then(|| y0 + (y1 - y0) * (x - x0) / (x1 - x0))
+    })
+}
+
+/// Mean earth radius in meters, for `Constraint::WithinRadius`
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two points given in degrees
+fn haversine_m((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let d_phi = (lat2 - lat1).to_radians();
+    let d_lambda = (lon2 - lon1).to_radians();
+    let a = (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().min(1.0).asin()
+}
+
+/// Text of the named capture `group` when `val` matches `pattern`
+#[cfg(feature = "regex")]
+fn captured<'v>(pattern: &Pattern, group: &str, val: &'v str) -> Option<&'v str> {
+    Some(pattern.0.captures(val)?.name(group)?.as_str())
+}
+
+/// Regex compiled once, when the constraint holding it is built or deserialized.
+///
+/// Serialized as its pattern string; deserializing an invalid pattern fails
+#[cfg(feature = "regex")]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(regex::Regex);
+
+#[cfg(feature = "regex")]
+impl Pattern {
+    pub fn new(pattern: &str) -> Result<Pattern, regex::Error> {
+        regex::Regex::new(pattern).map(Pattern)
+    }
+
+    /// The pattern as written
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Whether the pattern has a capture group called `name`
+    pub fn has_group(&self, name: &str) -> bool {
+        self.0.capture_names().flatten().any(|group| group == name)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Pattern::new(&pattern)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<Pattern> for String {
+    fn from(pattern: Pattern) -> String {
+        pattern.as_str().to_owned()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+#[cfg(feature = "regex")]
+impl std::fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Expression parsed once, when the constraint holding it is built or deserialized.
+///
+/// Serialized as its source string; deserializing an expression that does not
+/// parse fails
+#[cfg(feature = "expr")]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expr {
+    source: String,
+    tree: evalexpr::Node<evalexpr::DefaultNumericTypes>,
+}
+
+#[cfg(feature = "expr")]
+impl Expr {
+    pub fn new(expression: &str) -> Result<Expr, evalexpr::EvalexprError> {
+        Ok(Expr {
+            source: expression.to_owned(),
+            tree: evalexpr::build_operator_tree(expression)?,
+        })
+    }
+
+    /// The expression as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(feature = "expr")]
+impl TryFrom<String> for Expr {
+    type Error = evalexpr::EvalexprError;
+
+    fn try_from(expression: String) -> Result<Self, Self::Error> {
+        Expr::new(&expression)
+    }
+}
+
+#[cfg(feature = "expr")]
+impl From<Expr> for String {
+    fn from(expression: Expr) -> String {
+        expression.source
+    }
+}
+
+#[cfg(feature = "expr")]
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        self.source == other.source
+    }
+}
+
+#[cfg(feature = "expr")]
+impl std::fmt::Debug for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// Deserializes a constraint and rejects it unless `Constraint::validate()` passes
+pub(crate) fn deserialize_valid<'de, D>(deserializer: D) -> Result<Constraint, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let constraint = Constraint::deserialize(deserializer)?;
+    constraint.validate().map_err(serde::de::Error::custom)?;
+    Ok(constraint)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Constraint {
     StringEquals(String),
     IntEquals(isize),
     IntRange(isize, isize),
     Boolean(bool),
+    InEnumVariants(Vec<String>),
+    /// `Met` if the value is in the set.
+    ///
+    /// Lookups stay fast for large allow-lists, see `one_of_from_reader()`
+    OneOf(BTreeSet<String>),
+    /// Allow and deny lists where deny wins: `NotMet` if the value is in `deny`,
+    /// otherwise `Met` if it is in `allow`, otherwise `default`.
+    ///
+    /// A `default` of `NotMet` makes `allow` an allow-list, `Met` makes `deny` a
+    /// plain deny-list, and `Unknown` leaves unlisted values to other rules
+    AllowDeny {
+        allow: Vec<String>,
+        deny: Vec<String>,
+        default: Status,
+    },
+    /// `Met` if the value is not among the last `n` values seen for the field.
+    /// Nested in `ArrayAll` or `ArrayAny`, each element counts as a value seen.
+    ///
+    /// Needs the history kept by a [`HistoryEvaluator`][1], plain `Rule::check()` yields `Unknown`
+    ///
+    /// [1]: crate::stateful::HistoryEvaluator
+    NotRecentlySeen(usize),
+    /// `Met` if the value occurred fewer than `limit` times before in the current
+    /// time bucket of length `window`, i.e. this occurrence stays within the limit.
+    ///
+    /// Needs the counts kept by a [`RateLimitEvaluator`][1], plain `Rule::check()`
+    /// yields `Unknown`
+    ///
+    /// [1]: crate::stateful::RateLimitEvaluator
+    UnderRateLimit {
+        limit: usize,
+        window: Duration,
+    },
+    /// `Met` if any of the stored prefixes is a prefix of the value
+    PrefixInTrie(Trie),
+    /// `Met` if the value is in the set, which answers with a single hash lookup
+    /// and little memory beyond the values themselves, for very large allow-lists.
+    ///
+    /// Collect the values into a [`PhfSet`] once, e.g. when building the rule; it is
+    /// serialized with the rule, so loading a stored rule does not rebuild it
+    InPhfSet(PhfSet),
+    /// `Met` if the hex digest of the value equals `expected_hex` (case-insensitive)
+    #[cfg(feature = "hash")]
+    HashEquals {
+        algorithm: HashAlg,
+        expected_hex: String,
+    },
+    /// `Met` if the value, parsed as `typ`, compares to `target` as `op` says, e.g.
+    /// `Compare { op: Op::Ge, typ: ValueType::Int, target: "18".into() }`.
+    ///
+    /// Strings compare lexicographically. If the value or `target` can't be parsed
+    /// as `typ` the result is `NotMet`, for every operator including `Ne`
+    Compare {
+        op: Op,
+        typ: ValueType,
+        target: String,
+    },
+    /// `Met` if the value sorts between the two bounds, both inclusive, e.g. keys
+    /// from `"m"` to `"t"`. The first bound must not sort after the second.
+    ///
+    /// Strings compare byte-wise, so `"t"` is inside such a range but `"tz"` is not
+    StringBetween(String, String),
+    /// `Met` if the value matches a pattern such as `"??-####-??"`.
+    ///
+    /// * `?` matches any single letter
+    /// * `#` matches any single ASCII digit
+    /// * every other character matches itself
+    ///
+    /// The value must have exactly as many characters as the pattern
+    MatchesPattern(String),
+    /// `Met` if the value is a `#RRGGBB` hex color, or also `#RGB` when `allow_short`.
+    ///
+    /// Hex digits may be upper or lower case
+    IsHexColor {
+        allow_short: bool,
+    },
+    /// `Met` if the value reads the same backwards, e.g. `"A man, a plan, a canal: Panama"`.
+    ///
+    /// Only alphanumeric characters are compared, lowercased, so case, spaces and
+    /// punctuation are ignored. A value without any alphanumerics is `Met`
+    IsPalindrome,
+    /// `Met` if the value, a category, reaches `target` in at most `max_hops` steps
+    /// along the edges of `graph`, which maps each category to its neighbours.
+    ///
+    /// Edges are followed in the direction given, so list both directions for an
+    /// undirected graph. The target itself is zero hops away. Categories missing from
+    /// the graph are `NotMet`
+    WithinCategoryDistance {
+        graph: BTreeMap<String, Vec<String>>,
+        target: String,
+        max_hops: usize,
+    },
+    /// `Met` if the numeric value falls in the tier named `expected`.
+    ///
+    /// `thresholds` are ascending lower bounds, a value belongs to the tier of the
+    /// last bound it reaches. Values below the first bound belong to no tier
+    TierEquals {
+        thresholds: Vec<(f64, String)>,
+        expected: String,
+    },
+    /// `Met` if the weights of the keywords found in the value add up to at least
+    /// `threshold`, e.g. a crude spam score.
+    ///
+    /// Keywords are matched as case-sensitive substrings, and each keyword counts
+    /// once no matter how often it occurs
+    KeywordScore {
+        weights: Vec<(String, f64)>,
+        threshold: f64,
+    },
+    /// `Met` if the numeric value lies between `start` and `end`, each bound
+    /// inclusive or exclusive on its own, e.g. `[0, 100)`.
+    ///
+    /// Non-numeric values are `NotMet`
+    Range {
+        start: f64,
+        start_incl: bool,
+        end: f64,
+        end_incl: bool,
+    },
+    /// `Met` if the integer value has this many digits, e.g. `4` for values in the
+    /// thousands.
+    ///
+    /// The sign is ignored, so `-5000` has four digits, and `0` has one.
+    /// Non-integer values are `NotMet`
+    MagnitudeEquals(u32),
+    /// `Met` if the decimal digits of the integer value add up to this sum, e.g. `6`
+    /// for `123`.
+    ///
+    /// The sign is ignored and the value may have any number of digits, as coded
+    /// identifiers often do. Non-integer values are `NotMet`
+    DigitSumEquals(usize),
+    /// `Met` if the value, a length with a unit such as `"30cm"` or `"2 ft"`, is at
+    /// least this many meters.
+    ///
+    /// Units are `mm`, `cm`, `m`, `km`, `in`, `ft`, `yd` and `mi`. Values without a
+    /// known unit are `NotMet`
+    LengthAtLeastMeters(f64),
+    /// `Met` if the value, a number written in `locale`'s format, is at least `value`.
+    ///
+    /// See [`NumberLocale::parse`] for the accepted formats. Other values are `NotMet`
+    NumberAtLeast {
+        value: f64,
+        locale: NumberLocale,
+    },
+    /// `Met` if the numeric value is close to `reference`, i.e.
+    /// `|val - reference| <= max(rel_tol * |reference|, abs_tol)`.
+    ///
+    /// `abs_tol` matters near zero, where any relative tolerance shrinks to
+    /// nothing. Both tolerances must be non-negative. Non-numeric values and `NaN`
+    /// are `NotMet`
+    CloseTo {
+        reference: f64,
+        rel_tol: f64,
+        abs_tol: f64,
+    },
+    /// `Met` if the value carries at least this many bits of entropy, see [`entropy_bits`]
+    EntropyAtLeast(f64),
+    /// `Met` if the value is between `min_len` and `max_len` characters long and
+    /// contains a character of every required class. `min_len` must not exceed
+    /// `max_len`.
+    ///
+    /// Digits are `0` to `9`, upper and lower case follow Unicode, and a symbol is
+    /// any character that is not a letter, digit or whitespace
+    PasswordPolicy {
+        min_len: usize,
+        max_len: usize,
+        require_digit: bool,
+        require_upper: bool,
+        require_lower: bool,
+        require_symbol: bool,
+    },
+    /// `Met` if the numeric value is written with at most this many decimal places.
+    ///
+    /// Integers have none, and trailing zeros count, so `"1.50"` has two. In scientific
+    /// notation the exponent moves the decimal point: `"1e3"` has none, `"1.5e-2"` has
+    /// three. Non-numeric values, infinities and NaN are `NotMet`
+    MaxDecimalPlaces(usize),
+    /// `Met` if every element of the [array fact][1] satisfies the inner constraint.
+    ///
+    /// An empty array is `Met`, since no element fails
+    ///
+    /// [1]: array_elements
+    ArrayAll(Box<Constraint>),
+    /// `Met` if any element of the [array fact][1] satisfies the inner constraint.
+    ///
+    /// An empty array is `NotMet`, since no element passes
+    ///
+    /// [1]: array_elements
+    ArrayAny(Box<Constraint>),
+    /// `Met` if no two elements of the [array fact][1] are equal.
+    ///
+    /// Empty and single-element arrays are `Met`
+    ///
+    /// [1]: array_elements
+    ArrayUnique,
+    /// `Met` if `value` appears at least `n` times among the elements of the
+    /// [array fact][1]
+    ///
+    /// [1]: array_elements
+    CountOfValueAtLeast {
+        value: String,
+        n: usize,
+    },
+    /// `Met` if at least `percent` percent (0 to 100) of the elements of the
+    /// [array fact][1] equal `value`.
+    ///
+    /// An empty array is `NotMet`
+    ///
+    /// [1]: array_elements
+    ArrayPercentEquals {
+        value: String,
+        percent: f64,
+    },
+    /// `Met` if the value, a `YYYY-MM-DD` birth date, implies an age of at least
+    /// this many years.
+    ///
+    /// Someone born on February 29th ages on March 1st in non-leap years
+    #[cfg(feature = "chrono")]
+    AgeAtLeast(u32),
+    /// `Met` if the value, an RFC 3339 timestamp, is fresh enough: its score
+    /// `0.5^(age / half_life)` must reach `min_score`, so a timestamp exactly one
+    /// half-life old scores `0.5`. The half-life must be positive.
+    ///
+    /// Timestamps in the future score `1`. Unparseable timestamps are `NotMet`
+    #[cfg(feature = "chrono")]
+    FreshnessAtLeast {
+        half_life: TimeDelta,
+        min_score: f64,
+    },
+    /// `Met` if the value, a `YYYY-MM-DD` date, is at most `days` business days away
+    /// from today, in either direction.
+    ///
+    /// The distance counts the days after the earlier of the two dates up to and
+    /// including the later one, skipping Saturdays, Sundays and `holidays`. Today is
+    /// always within range. Unparseable dates are `NotMet`
+    #[cfg(feature = "chrono")]
+    WithinBusinessDays {
+        days: u32,
+        holidays: BTreeSet<NaiveDate>,
+    },
+    /// Status returned by the comparator registered under this name.
+    ///
+    /// Comparators are supplied to `Rule::check_with_comparators()`, so only the
+    /// name is stored and the rule serializes like any other. Without a comparator
+    /// of that name the result is `Unknown`
+    Named(String),
+    /// `Met` if the value is itself the name of a field present in the facts.
+    ///
+    /// Needs the facts, so checking a bare value with `Constraint::check()` yields `Unknown`
+    IsExistingFactKey,
+    /// `Met` if the numeric value is greater than the previous value stored in the
+    /// evaluation context under this key.
+    ///
+    /// Without a previous value the result is `Unknown`, see `Rule::check_with_context()`
+    GreaterThanPrevious(String),
+    /// `Met` if the numeric value is above the running average stored in the
+    /// evaluation context under this key.
+    ///
+    /// Without an average the result is `Unknown`, see `Rule::check_with_context()`
+    AboveContextAverage(String),
+    /// `Met` if the numeric value is above the rolling median stored in the
+    /// evaluation context under this key. Equal to the median is `NotMet`.
+    ///
+    /// Without a median the result is `Unknown`, see `Rule::check_with_context()`
+    AboveContextMedian(String),
+    /// `Met` if the numeric value is within this many standard deviations of the
+    /// field's mean, i.e. its z-score is at most the threshold in absolute value.
+    ///
+    /// The mean and standard deviation of field `f` are read from the evaluation
+    /// context under `mean_f` and `std_f`; without them the result is `Unknown`, see
+    /// `Rule::check_with_context()`. A standard deviation of zero only accepts the mean
+    ZScoreWithin(f64),
+    /// `Met` if the numeric value is at most the value of another fact `field`
+    /// multiplied by `factor`, e.g. "amount is at most half the balance".
+    ///
+    /// `Unknown` if the other fact is missing
+    LessThanFieldScaled {
+        field: String,
+        factor: f64,
+    },
+    /// `Met` if moving from the state in fact `field_from` to the value, the new
+    /// state, is listed in `transitions`, which maps each state to the states
+    /// reachable from it.
+    ///
+    /// `Unknown` if `field_from` is missing, `NotMet` for source states without an entry
+    AllowedTransition {
+        field_from: String,
+        transitions: BTreeMap<String, Vec<String>>,
+    },
+    /// `Met` if the numeric value is on or above the piecewise-linear curve through
+    /// `points`, evaluated at the numeric value of fact `x_field`.
+    ///
+    /// `points` are `(x, y)` pairs in ascending x order; beyond the first and last
+    /// point the curve stays flat. `Unknown` if `x_field` is missing, `NotMet` if either
+    /// value is not a number
+    AboveCurve {
+        points: Vec<(f64, f64)>,
+        x_field: String,
+    },
+    /// `Met` while the numeric value is "on", with separate thresholds for switching
+    /// on and off so values hovering around one boundary don't flap.
+    ///
+    /// Fact `state_field` holds the previous state as `true` or `false`:
+    ///
+    /// * at or above `on` the value is on (`Met`), whatever the previous state
+    /// * at or below `off` it is off (`NotMet`), whatever the previous state
+    /// * in between it keeps the previous state, `Unknown` if `state_field` is missing
+    ///
+    /// Non-numeric values and states other than `true` and `false` are `NotMet`
+    Hysteresis {
+        on: f64,
+        off: f64,
+        state_field: String,
+    },
+    /// `Met` if the point at the numeric facts `lat_field` and `lon_field`, in
+    /// degrees, is at most `radius_m` meters from the center, by haversine distance
+    /// on a spherical earth.
+    ///
+    /// The checked value itself is not used, so the leaf usually checks `lat_field`.
+    /// `Unknown` if either coordinate is missing, `NotMet` if either is not a number
+    WithinRadius {
+        center_lat: f64,
+        center_lon: f64,
+        radius_m: f64,
+        lat_field: String,
+        lon_field: String,
+    },
+    /// `Met` if the value is well-formed JSON
+    #[cfg(feature = "json")]
+    IsValidJson,
+    /// `Met` if the value is JSON and the value at [`path`][1] equals `expected`.
+    ///
+    /// Strings are compared by their contents, anything else against `expected`
+    /// parsed as JSON, so `expected` is `"42"` for a number and `"true"` for a
+    /// boolean. Malformed JSON and missing paths are `NotMet`
+    ///
+    /// [1]: json_path
+    #[cfg(feature = "json")]
+    JsonPathEquals {
+        path: String,
+        expected: String,
+    },
+    /// `Met` if the value is JSON and the number at [`path`][1] is within `tolerance`
+    /// of `target`, bounds included.
+    ///
+    /// A missing path is `Unknown`, since the number may simply not be reported yet.
+    /// Malformed JSON and a non-numeric value at the path are `NotMet`
+    ///
+    /// [1]: json_path
+    #[cfg(feature = "json")]
+    JsonNear {
+        path: String,
+        target: f64,
+        tolerance: f64,
+    },
+    /// `Met` if the [JSON-Logic][1] expression is truthy, its `var`s reading the facts
+    /// being checked, e.g. `{"<": [{"var": "age"}, 18]}`.
+    ///
+    /// The leaf's own field only has to be present, as usual
+    ///
+    /// [1]: crate::json_logic::evaluate
+    #[cfg(feature = "json")]
+    JsonLogic(serde_json::Value),
+    /// Matches the value against the regex `pattern` and checks the named capture
+    /// `group` against `inner`, e.g. "the `year` group is at least 2000".
+    ///
+    /// `NotMet` if the value does not match or the group did not participate
+    #[cfg(feature = "regex")]
+    RegexCapture {
+        pattern: Pattern,
+        group: String,
+        inner: Box<Constraint>,
+    },
+    /// `Met` if the value is within `max_distance` edits (Levenshtein distance) of
+    /// any of `values`, e.g. to tolerate typos.
+    ///
+    /// Each check computes the distance to every candidate whose length is close
+    /// enough, costing time proportional to the product of the string lengths. For
+    /// lists of many thousands of values consider an exact `OneOf` on normalized
+    /// input instead
+    #[cfg(feature = "fuzzy")]
+    FuzzyOneOf {
+        values: Vec<String>,
+        max_distance: usize,
+    },
+    /// `Met` if the expression, with the value bound to `x`, evaluates truthy,
+    /// e.g. `"2 * x + 1 > 10"`.
+    ///
+    /// `x` is a number when the value parses as one and a string otherwise. Booleans
+    /// count as themselves and numbers as truthy when non-zero, any other result
+    /// or an evaluation error is `NotMet`. The expression is parsed once, when the
+    /// constraint is built with [`expression`][1] or deserialized
+    ///
+    /// [1]: crate::expression
+    #[cfg(feature = "expr")]
+    Expression(Expr),
+    /// `Met` if the value is a valid cron expression.
+    ///
+    /// Expressions have six or seven fields: seconds, minutes, hours, day of month,
+    /// month, day of week and an optional year, e.g. `"0 30 9 * * Mon-Fri"`
+    #[cfg(feature = "cron")]
+    IsCron,
+    /// `Met` if the value is a cron expression (see `IsCron`) whose schedule includes
+    /// the current second.
+    ///
+    /// Use `*` as the seconds field to match the whole minute. Invalid expressions
+    /// are `NotMet`, and `Rule::check_at()` supplies another time than the system clock
+    #[cfg(feature = "cron")]
+    CronMatchesNow,
+    /// `Met` if the value is a ULID whose embedded timestamp lies between the two
+    /// bounds, inclusive. The bounds are serialized as RFC 3339 timestamps and the
+    /// first must not be after the second.
+    ///
+    /// Malformed ULIDs are `NotMet`
+    #[cfg(feature = "ulid")]
+    UlidTimestampInRange(DateTime<Utc>, DateTime<Utc>),
+    /// `Met` if every character of the value belongs to the Unicode script named by
+    /// its full or short name, e.g. `"Latin"` or `"Latn"`.
+    ///
+    /// Characters shared between scripts, such as digits, punctuation, spaces and
+    /// combining marks, are accepted too. The empty string is `Met`, having no
+    /// character outside the script, and an unknown script name is `NotMet`
+    #[cfg(feature = "unicode")]
+    OnlyScript(String),
+    /// `Met` if every character of the value is ASCII. The empty string is `Met`
+    #[cfg(feature = "unicode")]
+    IsAscii,
 }
 
 impl Constraint {
+    /// Builds an `InEnumVariants` constraint from the variant names of `E`
+    #[cfg(feature = "strum")]
+    pub fn from_enum_variants<E>() -> Constraint
+    where
+        E: strum::IntoEnumIterator + AsRef<str>,
+    {
+        Constraint::InEnumVariants(E::iter().map(|v| v.as_ref().to_owned()).collect())
+    }
+
+    /// Checks the parameters make sense, e.g. that a `Range` does not start after it
+    /// ends, including those of nested constraints.
+    ///
+    /// The constructors in the module root and deserializing a `Rule` run this, so
+    /// only constraints built by hand can be invalid
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match *self {
+            Constraint::Range { start, end, .. } => {
+                ensure!(start <= end, "range start must not be greater than its end")
+            }
+            Constraint::TierEquals { ref thresholds, .. } => ensure!(
+                thresholds.windows(2).all(|w| w[0].0 < w[1].0),
+                "tier thresholds must be strictly ascending"
+            ),
+            Constraint::AboveCurve { ref points, .. } => {
+                ensure!(!points.is_empty(), "curve needs at least one point");
+                ensure!(
+                    points.windows(2).all(|w| w[0].0 < w[1].0),
+                    "curve points must have strictly ascending x coordinates"
+                );
+            }
+            Constraint::Hysteresis { on, off, .. } => ensure!(
+                off < on,
+                "hysteresis off threshold must be below its on threshold"
+            ),
+            Constraint::StringBetween(ref start, ref end) => ensure!(
+                start <= end,
+                "string range start must not sort after its end"
+            ),
+            Constraint::ArrayPercentEquals { percent, .. } => ensure!(
+                (0.0..=100.0).contains(&percent),
+                "array percentage must be between 0 and 100"
+            ),
+            Constraint::CloseTo {
+                rel_tol, abs_tol, ..
+            } => ensure!(
+                rel_tol >= 0.0 && abs_tol >= 0.0,
+                "tolerances must be non-negative numbers"
+            ),
+            Constraint::PasswordPolicy {
+                min_len, max_len, ..
+            } => ensure!(
+                min_len <= max_len,
+                "password minimum length must not exceed its maximum length"
+            ),
+            Constraint::UnderRateLimit { window, .. } => {
+                ensure!(!window.is_zero(), "rate limit window must not be zero")
+            }
+            #[cfg(feature = "chrono")]
+            Constraint::FreshnessAtLeast { half_life, .. } => ensure!(
+                half_life > TimeDelta::zero(),
+                "freshness half-life must be positive"
+            ),
+            #[cfg(feature = "ulid")]
+            Constraint::UlidTimestampInRange(start, end) => ensure!(
+                start <= end,
+                "ULID timestamp range start must not be after its end"
+            ),
+            #[cfg(feature = "regex")]
+            Constraint::RegexCapture {
+                ref pattern,
+                ref group,
+                ref inner,
+            } => {
+                ensure!(
+                    pattern.has_group(group),
+                    "pattern has no capture group named {:?}",
+                    group
+                );
+                inner.validate()?;
+            }
+            Constraint::ArrayAll(ref inner) | Constraint::ArrayAny(ref inner) => {
+                inner.validate()?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     pub fn check(&self, val: &str) -> Status {
         match *self {
             Constraint::StringEquals(ref s) => {
@@ -51,6 +969,2089 @@ impl Constraint {
                     Status::NotMet
                 }
             }
+            Constraint::InEnumVariants(ref variants) => {
+                if variants.iter().any(|v| v == val) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::OneOf(ref values) => {
+                if values.contains(val) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::AllowDeny {
+                ref allow,
+                ref deny,
+                default,
+            } => {
+                if deny.iter().any(|d| d == val) {
+                    Status::NotMet
+                } else if allow.iter().any(|a| a == val) {
+                    Status::Met
+                } else {
+                    default
+                }
+            }
+            Constraint::NotRecentlySeen(_) | Constraint::UnderRateLimit { .. } => Status::Unknown,
+            Constraint::InPhfSet(ref set) => {
+                if set.contains(val) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::PrefixInTrie(ref trie) => {
+                if trie.longest_prefix_of(val).is_some() {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "hash")]
+            Constraint::HashEquals {
+                algorithm,
+                ref expected_hex,
+            } => {
+                if algorithm.hex_digest(val).eq_ignore_ascii_case(expected_hex) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::Compare {
+                op,
+                typ,
+                ref target,
+            } => match typ.compare(val, target) {
+                Some(ordering) if op.holds(ordering) => Status::Met,
+                _ => Status::NotMet,
+            },
+            Constraint::StringBetween(ref start, ref end) => {
+                if start.as_str() <= val && val <= end.as_str() {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::MatchesPattern(ref pattern) => {
+                let mut chars = val.chars();
+                let matches = pattern.chars().all(|p| match chars.next() {
+                    Some(c) => match p {
+                        '?' => c.is_alphabetic(),
+                        '#' => c.is_ascii_digit(),
+                        _ => c == p,
+                    },
+                    None => false,
+                });
+                if matches && chars.next().is_none() {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::WithinCategoryDistance {
+                ref graph,
+                ref target,
+                max_hops,
+            } => {
+                let mut seen = BTreeSet::from([val]);
+                let mut queue = VecDeque::from([(val, 0)]);
+                let mut found = false;
+                while let Some((category, hops)) = queue.pop_front() {
+                    if category == target {
+                        found = true;
+                        break;
+                    }
+                    if hops == max_hops {
+                        continue;
+                    }
+                    for next in graph.get(category).into_iter().flatten() {
+                        if seen.insert(next) {
+                            queue.push_back((next, hops + 1));
+                        }
+                    }
+                }
+                if found {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::IsPalindrome => {
+                let normalized = val
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<Vec<_>>();
+                if normalized.iter().eq(normalized.iter().rev()) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::IsHexColor { allow_short } => {
+                let valid = match val.strip_prefix('#') {
+                    Some(hex) => {
+                        (hex.len() == 6 || (allow_short && hex.len() == 3))
+                            && hex.chars().all(|c| c.is_ascii_hexdigit())
+                    }
+                    None => false,
+                };
+                if valid {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::TierEquals {
+                ref thresholds,
+                ref expected,
+            } => {
+                let tier = val.parse::<f64>().ok().and_then(|val| {
+                    thresholds
+                        .iter()
+                        .take_while(|(bound, _)| *bound <= val)
+                        .last()
+                });
+                match tier {
+                    Some((_, name)) if name == expected => Status::Met,
+                    _ => Status::NotMet,
+                }
+            }
+            Constraint::KeywordScore {
+                ref weights,
+                threshold,
+            } => {
+                let score = weights
+                    .iter()
+                    .filter(|(keyword, _)| val.contains(keyword.as_str()))
+                    .map(|(_, weight)| weight)
+                    .sum::<f64>();
+                if score >= threshold {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::MagnitudeEquals(digits) => {
+                let magnitude = val
+                    .parse::<isize>()
+                    .map(|val| val.unsigned_abs().checked_ilog10().map_or(1, |log| log + 1));
+                if magnitude == Ok(digits) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::DigitSumEquals(sum) => {
+                let digits = val.strip_prefix(['-', '+']).unwrap_or(val);
+                let is_integer = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit());
+                if is_integer && digits.bytes().map(|b| usize::from(b - b'0')).sum::<usize>() == sum
+                {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::LengthAtLeastMeters(min) => match length_in_meters(val) {
+                // tolerate rounding in the conversion, so 12in counts as 1ft
+                Some(meters) if meters >= min - min.abs() * 1e-9 => Status::Met,
+                _ => Status::NotMet,
+            },
+            Constraint::NumberAtLeast { value, locale } => match locale.parse(val) {
+                Some(number) if number >= value => Status::Met,
+                _ => Status::NotMet,
+            },
+            Constraint::CloseTo {
+                reference,
+                rel_tol,
+                abs_tol,
+            } => match val.parse::<f64>() {
+                Ok(number)
+                    if (number - reference).abs() <= (rel_tol * reference.abs()).max(abs_tol) =>
+                {
+                    Status::Met
+                }
+                _ => Status::NotMet,
+            },
+            Constraint::EntropyAtLeast(min) => {
+                if entropy_bits(val) >= min {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::PasswordPolicy {
+                min_len,
+                max_len,
+                require_digit,
+                require_upper,
+                require_lower,
+                require_symbol,
+            } => {
+                let len = val.chars().count();
+                let has = |class: fn(&char) -> bool| val.chars().any(|c| class(&c));
+                let valid = (min_len..=max_len).contains(&len)
+                    && (!require_digit || has(char::is_ascii_digit))
+                    && (!require_upper || has(|c| c.is_uppercase()))
+                    && (!require_lower || has(|c| c.is_lowercase()))
+                    && (!require_symbol || has(|c| !c.is_alphanumeric() && !c.is_whitespace()));
+                if valid {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::MaxDecimalPlaces(max) => match decimal_places(val) {
+                Some(places) if places <= max => Status::Met,
+                _ => Status::NotMet,
+            },
+            Constraint::Range {
+                start,
+                start_incl,
+                end,
+                end_incl,
+            } => {
+                let in_range = val.parse::<f64>().is_ok_and(|val| {
+                    let above = if start_incl {
+                        start <= val
+                    } else {
+                        start < val
+                    };
+                    let below = if end_incl { val <= end } else { val < end };
+                    above && below
+                });
+                if in_range {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::ArrayAll(ref inner) => {
+                array_elements(val).fold(Status::Met, |s, e| s & inner.check(e))
+            }
+            Constraint::ArrayAny(ref inner) => {
+                array_elements(val).fold(Status::NotMet, |s, e| s | inner.check(e))
+            }
+            Constraint::ArrayUnique => {
+                let mut seen = BTreeSet::new();
+                if array_elements(val).all(|e| seen.insert(e)) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::CountOfValueAtLeast { ref value, n } => {
+                if array_elements(val).filter(|e| e == value).count() >= n {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::ArrayPercentEquals { ref value, percent } => {
+                let (matching, total) = array_elements(val).fold((0, 0), |(matching, total), e| {
+                    (matching + usize::from(e == value), total + 1)
+                });
+                if total > 0 && matching as f64 * 100.0 >= percent * total as f64 {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "chrono")]
+            Constraint::AgeAtLeast(..)
+            | Constraint::FreshnessAtLeast { .. }
+            | Constraint::WithinBusinessDays { .. } => self.check_at(val, Utc::now()),
+            #[cfg(feature = "json")]
+            Constraint::IsValidJson => match serde_json::from_str::<serde_json::Value>(val) {
+                Ok(_) => Status::Met,
+                Err(_) => Status::NotMet,
+            },
+            #[cfg(feature = "json")]
+            Constraint::JsonPathEquals {
+                ref path,
+                ref expected,
+            } => {
+                let json = serde_json::from_str::<serde_json::Value>(val).ok();
+                let matches = match json.as_ref().and_then(|json| json_path(json, path)) {
+                    Some(serde_json::Value::String(s)) => s == expected,
+                    Some(other) => serde_json::from_str::<serde_json::Value>(expected)
+                        .is_ok_and(|expected| expected == *other),
+                    None => false,
+                };
+                if matches {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "json")]
+            Constraint::JsonNear {
+                ref path,
+                target,
+                tolerance,
+            } => {
+                let json = match serde_json::from_str::<serde_json::Value>(val) {
+                    Ok(json) => json,
+                    Err(_) => return Status::NotMet,
+                };
+                match json_path(&json, path).map(serde_json::Value::as_f64) {
+                    Some(Some(n)) if (n - target).abs() <= tolerance => Status::Met,
+                    Some(_) => Status::NotMet,
+                    None => Status::Unknown,
+                }
+            }
+            #[cfg(feature = "regex")]
+            Constraint::RegexCapture {
+                ref pattern,
+                ref group,
+                ref inner,
+            } => captured(pattern, group, val).map_or(Status::NotMet, |c| inner.check(c)),
+            #[cfg(feature = "expr")]
+            Constraint::Expression(ref expression) => {
+                if expression_holds(expression, val) == Some(true) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "cron")]
+            Constraint::IsCron => match val.parse::<cron::Schedule>() {
+                Ok(_) => Status::Met,
+                Err(_) => Status::NotMet,
+            },
+            #[cfg(feature = "cron")]
+            Constraint::CronMatchesNow => self.check_at(val, Utc::now()),
+            #[cfg(feature = "unicode")]
+            Constraint::OnlyScript(ref name) => {
+                use unicode_script::{Script, UnicodeScript};
+
+                let script = Script::from_full_name(name).or_else(|| Script::from_short_name(name));
+                let only = script.is_some_and(|script| {
+                    val.chars().all(|c| {
+                        matches!(c.script(), Script::Common | Script::Inherited)
+                            || c.script() == script
+                    })
+                });
+                if only {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "unicode")]
+            Constraint::IsAscii => {
+                if val.is_ascii() {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "ulid")]
+            Constraint::UlidTimestampInRange(start, end) => {
+                let timestamp = ulid::Ulid::from_string(val)
+                    .ok()
+                    .and_then(|ulid| i64::try_from(ulid.timestamp_ms()).ok());
+                match timestamp {
+                    Some(t) if (start.timestamp_millis()..=end.timestamp_millis()).contains(&t) => {
+                        Status::Met
+                    }
+                    _ => Status::NotMet,
+                }
+            }
+            #[cfg(feature = "fuzzy")]
+            Constraint::FuzzyOneOf {
+                ref values,
+                max_distance,
+            } => {
+                let len = val.chars().count();
+                let close = values.iter().any(|v| {
+                    // the length difference alone is a lower bound of the distance
+                    len.abs_diff(v.chars().count()) <= max_distance
+                        && strsim::levenshtein(val, v) <= max_distance
+                });
+                if close {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "json")]
+            Constraint::JsonLogic(_) => Status::Unknown,
+            Constraint::IsExistingFactKey
+            | Constraint::Named(_)
+            | Constraint::GreaterThanPrevious(_)
+            | Constraint::AboveContextAverage(_)
+            | Constraint::AboveContextMedian(_)
+            | Constraint::ZScoreWithin(_)
+            | Constraint::LessThanFieldScaled { .. }
+            | Constraint::AllowedTransition { .. }
+            | Constraint::AboveCurve { .. }
+            | Constraint::Hysteresis { .. }
+            | Constraint::WithinRadius { .. } => Status::Unknown,
+        }
+    }
+
+    /// Checks the value against constraints that depend on the current time,
+    /// using `now` in place of the system clock
+    #[cfg(feature = "chrono")]
+    pub fn check_at(&self, val: &str, now: DateTime<Utc>) -> Status {
+        match *self {
+            Constraint::AgeAtLeast(years) => {
+                let birth = match NaiveDate::parse_from_str(val, "%Y-%m-%d") {
+                    Ok(birth) => birth,
+                    Err(_) => return Status::NotMet,
+                };
+                let today = now.date_naive();
+                let mut age = today.year() - birth.year();
+                if (today.month(), today.day()) < (birth.month(), birth.day()) {
+                    age -= 1;
+                }
+                if age >= 0 && age as u32 >= years {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::FreshnessAtLeast {
+                half_life,
+                min_score,
+            } => {
+                let timestamp = match DateTime::parse_from_rfc3339(val) {
+                    Ok(timestamp) => timestamp,
+                    Err(_) => return Status::NotMet,
+                };
+                let age = (now - timestamp.with_timezone(&Utc)).max(TimeDelta::zero());
+                let half_lives = age.as_seconds_f64() / half_life.as_seconds_f64();
+                if 0.5f64.powf(half_lives) >= min_score {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::WithinBusinessDays { days, ref holidays } => {
+                let date = match NaiveDate::parse_from_str(val, "%Y-%m-%d") {
+                    Ok(date) => date,
+                    Err(_) => return Status::NotMet,
+                };
+                let today = now.date_naive();
+                let (from, to) = (today.min(date), today.max(date));
+                let business_days = from
+                    .iter_days()
+                    .skip(1)
+                    .take_while(|day| *day <= to)
+                    .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+                    .filter(|day| !holidays.contains(day))
+                    // no need to count past the limit
+                    .take(days as usize + 1)
+                    .count();
+                if business_days <= days as usize {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            #[cfg(feature = "cron")]
+            Constraint::CronMatchesNow => match val.parse::<cron::Schedule>() {
+                Ok(schedule) if schedule.includes(now) => Status::Met,
+                _ => Status::NotMet,
+            },
+            _ => self.check(val),
+        }
+    }
+
+    /// Calls `f` with every constraint and the value it is checked against, the
+    /// constraints nested in `ArrayAll`, `ArrayAny` and `RegexCapture` with each
+    /// element or the capture
+    pub(crate) fn visit_values<'v>(&self, val: &'v str, f: &mut dyn FnMut(&Constraint, &'v str)) {
+        f(self, val);
+        match *self {
+            Constraint::ArrayAll(ref inner) | Constraint::ArrayAny(ref inner) => {
+                array_elements(val).for_each(|e| inner.visit_values(e, f))
+            }
+            #[cfg(feature = "regex")]
+            Constraint::RegexCapture {
+                ref pattern,
+                ref group,
+                ref inner,
+            } => {
+                if let Some(c) = captured(pattern, group, val) {
+                    inner.visit_values(c, f)
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Other facts this constraint compares the value against, such as the `field`
+    /// of `LessThanFieldScaled`
+    pub(crate) fn other_fields(&self) -> Vec<&str> {
+        match *self {
+            Constraint::WithinRadius {
+                ref lat_field,
+                ref lon_field,
+                ..
+            } => vec![lat_field, lon_field],
+            Constraint::LessThanFieldScaled { ref field, .. }
+            | Constraint::AllowedTransition {
+                field_from: ref field,
+                ..
+            }
+            | Constraint::AboveCurve {
+                x_field: ref field, ..
+            }
+            | Constraint::Hysteresis {
+                state_field: ref field,
+                ..
+            } => vec![field],
+            _ => Vec::new(),
+        }
+    }
+
+    /// A few values satisfying this constraint on its own, see `Rule::example_facts()`.
+    ///
+    /// Empty when none can be derived, e.g. for constraints depending on context,
+    /// other facts or the current time
+    pub(crate) fn example_values(&self) -> Vec<String> {
+        let candidates = match *self {
+            Constraint::StringEquals(ref s) => vec![s.clone()],
+            Constraint::IntEquals(i) | Constraint::IntRange(i, _) => vec![i.to_string()],
+            Constraint::Boolean(b) => vec![b.to_string()],
+            Constraint::InEnumVariants(ref variants) => variants.iter().take(1).cloned().collect(),
+            Constraint::OneOf(ref values) => values.iter().take(1).cloned().collect(),
+            Constraint::AllowDeny { ref allow, .. } => allow.clone(),
+            Constraint::Compare {
+                typ, ref target, ..
+            } => match typ {
+                ValueType::Int => target
+                    .parse::<isize>()
+                    .map(|t| vec![t, t.saturating_sub(1), t.saturating_add(1)])
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+                ValueType::Float => target
+                    .parse::<f64>()
+                    .map(|t| vec![t, t - 1.0, t + 1.0])
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+                ValueType::String => vec![target.clone(), String::new(), format!("{}~", target)],
+            },
+            Constraint::StringBetween(ref start, _) => vec![start.clone()],
+            Constraint::MatchesPattern(ref pattern) => vec![pattern
+                .chars()
+                .map(|p| match p {
+                    '?' => 'a',
+                    '#' => '0',
+                    _ => p,
+                })
+                .collect()],
+            Constraint::IsHexColor { .. } => vec!["#000000".into()],
+            Constraint::IsPalindrome => vec!["a".into()],
+            Constraint::WithinCategoryDistance { ref target, .. } => vec![target.clone()],
+            Constraint::TierEquals {
+                ref thresholds,
+                ref expected,
+            } => thresholds
+                .iter()
+                .filter(|(_, name)| name == expected)
+                .map(|(bound, _)| bound.to_string())
+                .collect(),
+            Constraint::KeywordScore { ref weights, .. } => vec![weights
+                .iter()
+                .map(|(keyword, _)| keyword.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")],
+            Constraint::Range { start, end, .. } => {
+                vec![
+                    start.to_string(),
+                    end.to_string(),
+                    ((start + end) / 2.0).to_string(),
+                ]
+            }
+            Constraint::MagnitudeEquals(digits) => {
+                vec![format!(
+                    "1{}",
+                    "0".repeat(digits.saturating_sub(1) as usize)
+                )]
+            }
+            Constraint::DigitSumEquals(sum) => vec![format!("{}{}", sum % 9, "9".repeat(sum / 9))],
+            Constraint::LengthAtLeastMeters(min) => vec![format!("{}m", min)],
+            Constraint::NumberAtLeast { value, locale } => {
+                let (_, decimal) = locale.separators();
+                vec![value.to_string().replace('.', &decimal.to_string())]
+            }
+            Constraint::CloseTo { reference, .. } => vec![reference.to_string()],
+            Constraint::PasswordPolicy { min_len, .. } => {
+                vec![format!("Aa1!{}", "a".repeat(min_len.saturating_sub(4)))]
+            }
+            Constraint::MaxDecimalPlaces(_) => vec!["0".into()],
+            Constraint::ArrayAll(_) | Constraint::ArrayUnique => vec![String::new()],
+            Constraint::ArrayAny(ref inner) => inner.example_values(),
+            Constraint::CountOfValueAtLeast { ref value, n } => {
+                vec![vec![value.as_str(); n].join(",")]
+            }
+            Constraint::ArrayPercentEquals { ref value, .. } => vec![value.clone()],
+            #[cfg(feature = "json")]
+            Constraint::IsValidJson => vec!["null".into()],
+            #[cfg(feature = "unicode")]
+            Constraint::OnlyScript(_) | Constraint::IsAscii => vec![String::new()],
+            _ => Vec::new(),
+        };
+        candidates
+            .into_iter()
+            .filter(|val| self.check(val) == Status::Met)
+            .collect()
+    }
+
+    /// Describes why `val` can never satisfy this constraint because of its type,
+    /// e.g. a non-numeric value for an integer comparison
+    pub fn type_problem(&self, val: &str) -> Option<String> {
+        match *self {
+            Constraint::IntEquals(_)
+            | Constraint::IntRange(..)
+            | Constraint::MagnitudeEquals(_)
+            | Constraint::Compare {
+                typ: ValueType::Int,
+                ..
+            } => val
+                .parse::<isize>()
+                .err()
+                .map(|_| format!("expected an integer, got {:?}", val)),
+            Constraint::TierEquals { .. }
+            | Constraint::Range { .. }
+            | Constraint::Compare {
+                typ: ValueType::Float,
+                ..
+            }
+            | Constraint::MaxDecimalPlaces(_)
+            | Constraint::GreaterThanPrevious(_)
+            | Constraint::AboveContextAverage(_)
+            | Constraint::AboveContextMedian(_)
+            | Constraint::ZScoreWithin(_)
+            | Constraint::CloseTo { .. }
+            | Constraint::AboveCurve { .. }
+            | Constraint::Hysteresis { .. }
+            | Constraint::LessThanFieldScaled { .. } => val
+                .parse::<f64>()
+                .err()
+                .map(|_| format!("expected a number, got {:?}", val)),
+            Constraint::NumberAtLeast { locale, .. } => match locale.parse(val) {
+                Some(_) => None,
+                None => Some(format!(
+                    "expected a {:?} formatted number, got {:?}",
+                    locale, val
+                )),
+            },
+            Constraint::ArrayAll(ref inner) | Constraint::ArrayAny(ref inner) => {
+                array_elements(val).find_map(|e| inner.type_problem(e))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks the value of `field` with access to the evaluation state
+    pub(crate) fn evaluate(&self, field: &str, val: &str, env: &Env) -> Status {
+        match *self {
+            Constraint::NotRecentlySeen(n) => match env.history {
+                Some(history) => {
+                    let seen = history
+                        .get(field)
+                        .is_some_and(|h| h.iter().rev().take(n).any(|v| v == val));
+                    if seen {
+                        Status::NotMet
+                    } else {
+                        Status::Met
+                    }
+                }
+                None => Status::Unknown,
+            },
+            Constraint::UnderRateLimit { limit, window } => match env.rate_counters {
+                Some(counters) if counters.count(field, window, val) < limit => Status::Met,
+                Some(_) => Status::NotMet,
+                None => Status::Unknown,
+            },
+            #[cfg(feature = "json")]
+            Constraint::JsonLogic(ref logic) => {
+                if crate::json_logic::truthy(&crate::json_logic::evaluate(logic, env.facts)) {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::Named(ref name) => match env.comparators.and_then(|c| c.get(name)) {
+                Some(comparator) => comparator(val),
+                None => Status::Unknown,
+            },
+            Constraint::IsExistingFactKey => {
+                if env.fact(val).is_some() {
+                    Status::Met
+                } else {
+                    Status::NotMet
+                }
+            }
+            Constraint::GreaterThanPrevious(ref key)
+            | Constraint::AboveContextAverage(ref key)
+            | Constraint::AboveContextMedian(ref key) => match env.context(key) {
+                Some(reference) => match (val.parse::<f64>(), reference.parse::<f64>()) {
+                    (Ok(val), Ok(reference)) if val > reference => Status::Met,
+                    _ => Status::NotMet,
+                },
+                None => Status::Unknown,
+            },
+            Constraint::ZScoreWithin(threshold) => {
+                let mean = env.context(&format!("mean_{}", field));
+                let std = env.context(&format!("std_{}", field));
+                match (mean, std) {
+                    (Some(mean), Some(std)) => {
+                        let parsed = (val.parse::<f64>(), mean.parse::<f64>(), std.parse::<f64>());
+                        match parsed {
+                            // compared without dividing so a zero deviation works
+                            (Ok(val), Ok(mean), Ok(std))
+                                if (val - mean).abs() <= threshold * std.abs() =>
+                            {
+                                Status::Met
+                            }
+                            _ => Status::NotMet,
+                        }
+                    }
+                    _ => Status::Unknown,
+                }
+            }
+            Constraint::AllowedTransition {
+                ref field_from,
+                ref transitions,
+            } => match env.fact(field_from) {
+                Some(from) => match transitions.get(from.as_ref()) {
+                    Some(targets) if targets.iter().any(|t| t == val) => Status::Met,
+                    _ => Status::NotMet,
+                },
+                None => Status::Unknown,
+            },
+            Constraint::WithinRadius {
+                center_lat,
+                center_lon,
+                radius_m,
+                ref lat_field,
+                ref lon_field,
+            } => match (env.fact(lat_field), env.fact(lon_field)) {
+                (Some(lat), Some(lon)) => match (lat.parse::<f64>(), lon.parse::<f64>()) {
+                    (Ok(lat), Ok(lon)) => {
+                        let distance = haversine_m((lat, lon), (center_lat, center_lon));
+                        // tolerate rounding, so points on the circle count as inside
+                        if distance <= radius_m + radius_m.abs() * 1e-9 {
+                            Status::Met
+                        } else {
+                            Status::NotMet
+                        }
+                    }
+                    _ => Status::NotMet,
+                },
+                _ => Status::Unknown,
+            },
+            Constraint::AboveCurve {
+                ref points,
+                ref x_field,
+            } => match env.fact(x_field) {
+                Some(x) => {
+                    let threshold = x.parse::<f64>().ok().and_then(|x| interpolate(points, x));
+                    match (val.parse::<f64>(), threshold) {
+                        (Ok(y), Some(threshold)) if y >= threshold => Status::Met,
+                        _ => Status::NotMet,
+                    }
+                }
+                None => Status::Unknown,
+            },
+            Constraint::Hysteresis {
+                on,
+                off,
+                ref state_field,
+            } => match val.parse::<f64>() {
+                Ok(val) if val >= on => Status::Met,
+                Ok(val) if val <= off => Status::NotMet,
+                Ok(_) => match env.fact(state_field).map(|s| s.parse::<bool>()) {
+                    Some(Ok(true)) => Status::Met,
+                    Some(_) => Status::NotMet,
+                    None => Status::Unknown,
+                },
+                Err(_) => Status::NotMet,
+            },
+            Constraint::LessThanFieldScaled {
+                field: ref other,
+                factor,
+            } => match env.fact(other) {
+                Some(base) => match (val.parse::<f64>(), base.parse::<f64>()) {
+                    (Ok(val), Ok(base)) if val <= base * factor => Status::Met,
+                    _ => Status::NotMet,
+                },
+                None => Status::Unknown,
+            },
+            #[cfg(feature = "regex")]
+            Constraint::RegexCapture {
+                ref pattern,
+                ref group,
+                ref inner,
+            } => captured(pattern, group, val)
+                .map_or(Status::NotMet, |c| inner.evaluate(field, c, env)),
+            Constraint::ArrayAll(ref inner) => {
+                array_elements(val).fold(Status::Met, |s, e| s & inner.evaluate(field, e, env))
+            }
+            Constraint::ArrayAny(ref inner) => {
+                array_elements(val).fold(Status::NotMet, |s, e| s | inner.evaluate(field, e, env))
+            }
+            #[cfg(feature = "chrono")]
+            _ => match env.now {
+                Some(now) => self.check_at(val, now),
+                None => self.check(val),
+            },
+            #[cfg(not(feature = "chrono"))]
+            _ => self.check(val),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_enum_variants_checks_membership() {
+        let constraint = Constraint::InEnumVariants(vec!["Red".into(), "Green".into()]);
+        assert_eq!(constraint.check("Green"), Status::Met);
+        assert_eq!(constraint.check("green"), Status::NotMet);
+        assert_eq!(constraint.check("Blue"), Status::NotMet);
+    }
+
+    #[cfg(feature = "strum")]
+    #[test]
+    fn in_enum_variants_from_enum() {
+        #[derive(strum::EnumIter, strum::AsRefStr)]
+        enum Color {
+            Red,
+            Green,
+            Blue,
         }
+        let constraint = Constraint::from_enum_variants::<Color>();
+        assert_eq!(
+            constraint,
+            Constraint::InEnumVariants(vec!["Red".into(), "Green".into(), "Blue".into()])
+        );
+        assert_eq!(constraint.check("Blue"), Status::Met);
+        assert_eq!(constraint.check("Purple"), Status::NotMet);
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn hash_equals_known_digest_and_mismatch() {
+        let constraint = Constraint::HashEquals {
+            algorithm: HashAlg::Sha256,
+            expected_hex: "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD".into(),
+        };
+        assert_eq!(constraint.check("abc"), Status::Met);
+        assert_eq!(constraint.check("abd"), Status::NotMet);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_capture_pattern_round_trips_as_string() {
+        let constraint = Constraint::RegexCapture {
+            pattern: Pattern::new(r"(?<year>\d{4})-\d{2}").unwrap(),
+            group: "year".into(),
+            inner: Box::new(Constraint::IntRange(2000, 2100)),
+        };
+        let json = serde_json::to_string(&constraint).unwrap();
+        assert!(json.contains(r#""pattern":"(?<year>\\d{4})-\\d{2}""#));
+        let parsed: Constraint = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, constraint);
+        assert_eq!(parsed.check("2024-05"), Status::Met);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_capture_rejects_invalid_pattern_when_deserializing() {
+        let json =
+            r#"{"RegexCapture":{"pattern":"(unclosed","group":"g","inner":{"StringEquals":"x"}}}"#;
+        assert!(serde_json::from_str::<Constraint>(json).is_err());
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn expression_round_trips_as_string() {
+        let constraint = Constraint::Expression(Expr::new("2 * x + 1 > 10").unwrap());
+        let json = serde_json::to_string(&constraint).unwrap();
+        assert_eq!(json, r#"{"Expression":"2 * x + 1 > 10"}"#);
+        let parsed: Constraint = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, constraint);
+        assert_eq!(parsed.check("5"), Status::Met);
+        assert_eq!(parsed.check("4"), Status::NotMet);
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn expression_rejects_unparsable_source_when_deserializing() {
+        assert!(serde_json::from_str::<Constraint>(r#"{"Expression":"2 * (x"}"#).is_err());
+    }
+
+    #[test]
+    fn matches_pattern_with_placeholders_and_separators() {
+        let constraint = Constraint::MatchesPattern("??-####-??".into());
+        assert_eq!(constraint.check("AB-1234-XY"), Status::Met);
+        assert_eq!(constraint.check("ab-0000-zz"), Status::Met);
+        assert_eq!(constraint.check("A1-1234-XY"), Status::NotMet);
+        assert_eq!(constraint.check("AB-12A4-XY"), Status::NotMet);
+        assert_eq!(constraint.check("AB_1234_XY"), Status::NotMet);
+        assert_eq!(constraint.check("AB-1234-XYZ"), Status::NotMet);
+        assert_eq!(constraint.check("AB-1234-X"), Status::NotMet);
+        assert_eq!(constraint.check(""), Status::NotMet);
+
+        let literal = Constraint::MatchesPattern("v#.#/?".into());
+        assert_eq!(literal.check("v1.2/a"), Status::Met);
+        assert_eq!(literal.check("w1.2/a"), Status::NotMet);
+        assert_eq!(literal.check("v1-2/a"), Status::NotMet);
+        assert_eq!(
+            Constraint::MatchesPattern(String::new()).check(""),
+            Status::Met
+        );
+    }
+
+    #[test]
+    fn tier_equals_resolves_tiers_and_boundaries() {
+        let tier = |expected: &str| Constraint::TierEquals {
+            thresholds: vec![
+                (0.0, "bronze".into()),
+                (100.0, "silver".into()),
+                (500.0, "gold".into()),
+            ],
+            expected: expected.into(),
+        };
+        assert_eq!(tier("bronze").check("42"), Status::Met);
+        assert_eq!(tier("silver").check("250.5"), Status::Met);
+        assert_eq!(tier("gold").check("10000"), Status::Met);
+        assert_eq!(tier("gold").check("250"), Status::NotMet);
+        // lower bounds are inclusive
+        assert_eq!(tier("bronze").check("0"), Status::Met);
+        assert_eq!(tier("silver").check("100"), Status::Met);
+        assert_eq!(tier("bronze").check("99.99"), Status::Met);
+        assert_eq!(tier("gold").check("500"), Status::Met);
+        assert_eq!(tier("silver").check("499.999"), Status::Met);
+        // below the first bound there is no tier
+        assert_eq!(tier("bronze").check("-1"), Status::NotMet);
+        assert_eq!(tier("bronze").check("lots"), Status::NotMet);
+
+        assert!(tier("gold").validate().is_ok());
+        assert!(crate::tier_equals("tier", "points", &[(0.0, "a"), (0.0, "b")], "a").is_err());
+    }
+
+    #[test]
+    fn array_all_and_any_over_elements() {
+        let at_least_50 = || Box::new(Constraint::IntRange(50, 100));
+        let all = Constraint::ArrayAll(at_least_50());
+        let any = Constraint::ArrayAny(at_least_50());
+
+        assert_eq!(all.check("50,75,100"), Status::Met);
+        assert_eq!(any.check("50,75,100"), Status::Met);
+
+        assert_eq!(all.check("50,49,100"), Status::NotMet);
+        assert_eq!(any.check("50,49,100"), Status::Met);
+
+        assert_eq!(all.check("1,2"), Status::NotMet);
+        assert_eq!(any.check("1,2"), Status::NotMet);
+
+        // an empty array passes vacuously for `ArrayAll` and never for `ArrayAny`
+        assert_eq!(all.check(""), Status::Met);
+        assert_eq!(any.check(""), Status::NotMet);
+    }
+
+    #[cfg(feature = "chrono")]
+    fn utc(date: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("{}T12:00:00Z", date))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn age_at_least_on_and_before_the_birthday() {
+        let adult = Constraint::AgeAtLeast(18);
+        assert_eq!(adult.check_at("2000-06-15", utc("2018-06-15")), Status::Met);
+        assert_eq!(
+            adult.check_at("2000-06-15", utc("2018-06-14")),
+            Status::NotMet
+        );
+        assert_eq!(adult.check_at("2000-06-15", utc("2030-01-01")), Status::Met);
+        assert_eq!(
+            adult.check_at("not a date", utc("2030-01-01")),
+            Status::NotMet
+        );
+        // born in the future
+        assert_eq!(
+            Constraint::AgeAtLeast(0).check_at("2031-01-01", utc("2030-01-01")),
+            Status::NotMet
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn age_at_least_leap_day_birthday() {
+        let adult = Constraint::AgeAtLeast(18);
+        // without a 29 February, the birthday counts as passed on 1 March
+        assert_eq!(
+            adult.check_at("2004-02-29", utc("2022-02-28")),
+            Status::NotMet
+        );
+        assert_eq!(adult.check_at("2004-02-29", utc("2022-03-01")), Status::Met);
+        // in a leap year it falls on the day itself
+        assert_eq!(
+            Constraint::AgeAtLeast(20).check_at("2004-02-29", utc("2024-02-29")),
+            Status::Met
+        );
+        assert_eq!(
+            Constraint::AgeAtLeast(20).check_at("2004-02-29", utc("2024-02-28")),
+            Status::NotMet
+        );
+    }
+
+    fn facts(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn is_existing_fact_key_looks_up_the_facts() {
+        let rule = crate::is_existing_fact_key("chosen option", "chosen_option");
+        let info = facts(&[("chosen_option", "express"), ("express", "5.00")]);
+        assert_eq!(rule.check(&info).status, Status::Met);
+        let info = facts(&[("chosen_option", "overnight"), ("express", "5.00")]);
+        assert_eq!(rule.check(&info).status, Status::NotMet);
+        // on its own the constraint has no facts to look in
+        assert_eq!(
+            Constraint::IsExistingFactKey.check("express"),
+            Status::Unknown
+        );
+    }
+
+    #[test]
+    fn greater_than_previous_reads_the_context() {
+        let rule = crate::greater_than_previous("rising", "reading", "last_reading");
+        let context = facts(&[("last_reading", "10.5")]);
+        let check = |reading| {
+            rule.check_with_context(&facts(&[("reading", reading)]), &context)
+                .status
+        };
+        assert_eq!(check("11"), Status::Met);
+        assert_eq!(check("10.5"), Status::NotMet);
+        assert_eq!(check("9"), Status::NotMet);
+        assert_eq!(check("high"), Status::NotMet);
+
+        let info = facts(&[("reading", "11")]);
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+        assert_eq!(
+            rule.check_with_context(&info, &facts(&[])).status,
+            Status::Unknown
+        );
+        let garbled = facts(&[("last_reading", "n/a")]);
+        assert_eq!(
+            rule.check_with_context(&info, &garbled).status,
+            Status::NotMet
+        );
+    }
+
+    #[test]
+    fn less_than_field_scaled_against_other_field() {
+        let rule = crate::less_than_field_scaled("half the balance", "amount", "balance", 0.5);
+        let check = |pairs: &[(&str, &str)]| rule.check(&facts(pairs)).status;
+        assert_eq!(check(&[("amount", "40"), ("balance", "100")]), Status::Met);
+        assert_eq!(check(&[("amount", "50"), ("balance", "100")]), Status::Met);
+        assert_eq!(
+            check(&[("amount", "50.01"), ("balance", "100")]),
+            Status::NotMet
+        );
+        assert_eq!(check(&[("amount", "40")]), Status::Unknown);
+        assert_eq!(
+            check(&[("amount", "40"), ("balance", "lots")]),
+            Status::NotMet
+        );
+        assert_eq!(
+            check(&[("amount", "some"), ("balance", "100")]),
+            Status::NotMet
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn is_valid_json_and_json_path_equals() {
+        assert_eq!(
+            Constraint::IsValidJson.check(r#"{"a": [1, 2]}"#),
+            Status::Met
+        );
+        assert_eq!(Constraint::IsValidJson.check("42"), Status::Met);
+        assert_eq!(
+            Constraint::IsValidJson.check(r#"{"a": [1, 2}"#),
+            Status::NotMet
+        );
+        assert_eq!(Constraint::IsValidJson.check(""), Status::NotMet);
+
+        let path_equals = |path: &str, expected: &str| Constraint::JsonPathEquals {
+            path: path.into(),
+            expected: expected.into(),
+        };
+        let doc = r#"{"user": {"name": "alice", "age": 30, "tags": ["a", "b"], "admin": true}}"#;
+        assert_eq!(path_equals("user.name", "alice").check(doc), Status::Met);
+        assert_eq!(path_equals("user.age", "30").check(doc), Status::Met);
+        assert_eq!(path_equals("user.tags.1", "b").check(doc), Status::Met);
+        assert_eq!(path_equals("user.admin", "true").check(doc), Status::Met);
+        assert_eq!(path_equals("user.name", "bob").check(doc), Status::NotMet);
+        assert_eq!(
+            path_equals("user.email", "alice").check(doc),
+            Status::NotMet
+        );
+        assert_eq!(
+            path_equals("user.name", "alice").check("{not json"),
+            Status::NotMet
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_capture_applies_inner_constraint_to_group() {
+        let rule = crate::regex_capture(
+            "recent",
+            "date",
+            r"^(?<year>\d{4})-\d{2}-\d{2}$",
+            "year",
+            Constraint::IntRange(2000, 2100),
+        )
+        .unwrap();
+        let check = |date| rule.check(&facts(&[("date", date)])).status;
+        assert_eq!(check("2024-05-01"), Status::Met);
+        assert_eq!(check("1999-12-31"), Status::NotMet);
+        assert_eq!(check("yesterday"), Status::NotMet);
+
+        assert!(
+            crate::regex_capture("d", "date", r"(\d+)", "year", Constraint::IsPalindrome).is_err()
+        );
+        assert!(
+            crate::regex_capture("d", "date", r"(?<year>", "year", Constraint::IsPalindrome)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn range_bounds_inclusive_and_exclusive_per_side() {
+        let range = |start_incl, end_incl| Constraint::Range {
+            start: 0.0,
+            start_incl,
+            end: 100.0,
+            end_incl,
+        };
+        let at_bounds = |c: Constraint| (c.check("0"), c.check("100"));
+        assert_eq!(at_bounds(range(true, true)), (Status::Met, Status::Met));
+        assert_eq!(at_bounds(range(true, false)), (Status::Met, Status::NotMet));
+        assert_eq!(at_bounds(range(false, true)), (Status::NotMet, Status::Met));
+        assert_eq!(
+            at_bounds(range(false, false)),
+            (Status::NotMet, Status::NotMet)
+        );
+        for (start_incl, end_incl) in [(true, true), (true, false), (false, true), (false, false)] {
+            let c = range(start_incl, end_incl);
+            assert_eq!(c.check("0.001"), Status::Met);
+            assert_eq!(c.check("99.999"), Status::Met);
+            assert_eq!(c.check("-0.001"), Status::NotMet);
+            assert_eq!(c.check("100.001"), Status::NotMet);
+            assert_eq!(c.check("fifty"), Status::NotMet);
+        }
+
+        let point = Constraint::Range {
+            start: 5.0,
+            start_incl: true,
+            end: 5.0,
+            end_incl: true,
+        };
+        assert!(point.validate().is_ok());
+        assert_eq!(point.check("5"), Status::Met);
+        assert!(crate::range("r", "x", 1.0, true, 0.0, true).is_err());
+    }
+
+    #[test]
+    fn max_decimal_places_counts_digits_after_the_point() {
+        let two = Constraint::MaxDecimalPlaces(2);
+        assert_eq!(two.check("1.23"), Status::Met);
+        assert_eq!(two.check("1.234"), Status::NotMet);
+        assert_eq!(two.check("5"), Status::Met);
+        assert_eq!(two.check("1e3"), Status::Met);
+        // the exponent shifts the point
+        assert_eq!(two.check("1.234e1"), Status::Met);
+        assert_eq!(two.check("1.5e-2"), Status::NotMet);
+        assert_eq!(two.check("1.5e-1"), Status::Met);
+        assert_eq!(two.check("abc"), Status::NotMet);
+        assert_eq!(Constraint::MaxDecimalPlaces(0).check("5"), Status::Met);
+        assert_eq!(Constraint::MaxDecimalPlaces(0).check("5.0"), Status::NotMet);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_near_within_and_outside_tolerance() {
+        let near = Constraint::JsonNear {
+            path: "sensor.temp".into(),
+            target: 20.0,
+            tolerance: 0.5,
+        };
+        assert_eq!(near.check(r#"{"sensor": {"temp": 20.3}}"#), Status::Met);
+        assert_eq!(near.check(r#"{"sensor": {"temp": 19.5}}"#), Status::Met);
+        assert_eq!(near.check(r#"{"sensor": {"temp": 21}}"#), Status::NotMet);
+        assert_eq!(
+            near.check(r#"{"sensor": {"temp": "warm"}}"#),
+            Status::NotMet
+        );
+        assert_eq!(near.check(r#"{"sensor": {}}"#), Status::Unknown);
+        assert_eq!(near.check("{broken"), Status::NotMet);
+    }
+
+    #[test]
+    fn count_of_value_at_least_over_array() {
+        let twice = Constraint::CountOfValueAtLeast {
+            value: "a".into(),
+            n: 2,
+        };
+        assert_eq!(twice.check("a,b,a"), Status::Met);
+        assert_eq!(twice.check("a,b,c"), Status::NotMet);
+        assert_eq!(twice.check("a,a,a"), Status::Met);
+        assert_eq!(twice.check("aa,b"), Status::NotMet);
+        assert_eq!(twice.check(""), Status::NotMet);
+        let none = Constraint::CountOfValueAtLeast {
+            value: "a".into(),
+            n: 0,
+        };
+        assert_eq!(none.check(""), Status::Met);
+    }
+
+    #[test]
+    fn keyword_score_hits_and_misses_threshold() {
+        let spam = Constraint::KeywordScore {
+            weights: vec![
+                ("free".into(), 2.0),
+                ("winner".into(), 3.0),
+                ("click".into(), 1.0),
+            ],
+            threshold: 4.0,
+        };
+        assert_eq!(spam.check("click now, you are a winner"), Status::Met);
+        assert_eq!(spam.check("free winner"), Status::Met);
+        assert_eq!(spam.check("free click"), Status::NotMet);
+        // repeated keywords count once, and matching is case-sensitive
+        assert_eq!(spam.check("free free free"), Status::NotMet);
+        assert_eq!(spam.check("FREE WINNER"), Status::NotMet);
+        assert_eq!(spam.check(""), Status::NotMet);
+    }
+
+    #[test]
+    fn is_hex_color_long_and_short_forms() {
+        let long_only = Constraint::IsHexColor { allow_short: false };
+        let any = Constraint::IsHexColor { allow_short: true };
+        assert_eq!(long_only.check("#FFFFFF"), Status::Met);
+        assert_eq!(any.check("#a1B2c3"), Status::Met);
+        assert_eq!(long_only.check("#fff"), Status::NotMet);
+        assert_eq!(any.check("#fff"), Status::Met);
+        for invalid in ["#zzz", "#zzzzzz", "FFFFFF", "#FFFF", "#FFFFFFF", "#", ""] {
+            assert_eq!(any.check(invalid), Status::NotMet, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn array_percent_equals_hits_and_misses() {
+        let mostly_pass = Constraint::ArrayPercentEquals {
+            value: "pass".into(),
+            percent: 80.0,
+        };
+        assert_eq!(mostly_pass.check("pass,pass,pass,pass,fail"), Status::Met);
+        assert_eq!(
+            mostly_pass.check("pass,pass,pass,fail,fail"),
+            Status::NotMet
+        );
+        assert_eq!(mostly_pass.check("pass"), Status::Met);
+        assert_eq!(mostly_pass.check("fail"), Status::NotMet);
+        assert_eq!(mostly_pass.check(""), Status::NotMet);
+    }
+
+    #[test]
+    fn array_percent_equals_rejects_out_of_range_percentages() {
+        let build = |percent| crate::array_percent_equals("checks", "checks", "pass", percent);
+        assert!(build(0.0).is_ok());
+        assert!(build(100.0).is_ok());
+        assert!(build(-1.0).is_err());
+        assert!(build(100.5).is_err());
+        assert!(build(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn magnitude_equals_counts_digits() {
+        let thousands = Constraint::MagnitudeEquals(4);
+        assert_eq!(thousands.check("500"), Status::NotMet);
+        assert_eq!(thousands.check("5000"), Status::Met);
+        assert_eq!(thousands.check("50000"), Status::NotMet);
+        assert_eq!(thousands.check("1000"), Status::Met);
+        assert_eq!(thousands.check("9999"), Status::Met);
+        assert_eq!(thousands.check("-5000"), Status::Met);
+        assert_eq!(Constraint::MagnitudeEquals(1).check("0"), Status::Met);
+        assert_eq!(Constraint::MagnitudeEquals(0).check("0"), Status::NotMet);
+        assert_eq!(Constraint::MagnitudeEquals(3).check("500"), Status::Met);
+        assert_eq!(thousands.check("5000.5"), Status::NotMet);
+        assert_eq!(thousands.check("five"), Status::NotMet);
+    }
+
+    #[test]
+    fn compare_across_operators_and_types() {
+        let compare = |op, typ, target: &str| Constraint::Compare {
+            op,
+            typ,
+            target: target.into(),
+        };
+        let cases = [
+            (Op::Eq, ValueType::Int, "18", "18", Status::Met),
+            (Op::Eq, ValueType::Int, "18", "19", Status::NotMet),
+            (Op::Ne, ValueType::Int, "18", "19", Status::Met),
+            (Op::Lt, ValueType::Int, "18", "17", Status::Met),
+            (Op::Lt, ValueType::Int, "18", "18", Status::NotMet),
+            (Op::Le, ValueType::Int, "18", "18", Status::Met),
+            (Op::Gt, ValueType::Int, "18", "-20", Status::NotMet),
+            (Op::Ge, ValueType::Int, "18", "18", Status::Met),
+            (Op::Eq, ValueType::Float, "1.5", "1.50", Status::Met),
+            (Op::Gt, ValueType::Float, "1.5", "1.51", Status::Met),
+            (Op::Le, ValueType::Float, "1.5", "2", Status::NotMet),
+            (Op::Ne, ValueType::Float, "1.5", "NaN", Status::NotMet),
+            (Op::Eq, ValueType::String, "abc", "abc", Status::Met),
+            (Op::Lt, ValueType::String, "abc", "abb", Status::Met),
+            (Op::Gt, ValueType::String, "10", "9", Status::Met),
+            (Op::Ne, ValueType::String, "abc", "ABC", Status::Met),
+            // values that don't parse as the type never match, even for `Ne`
+            (Op::Ne, ValueType::Int, "18", "eighteen", Status::NotMet),
+            (Op::Eq, ValueType::Int, "18", "18.0", Status::NotMet),
+            (Op::Ne, ValueType::Float, "1.5", "", Status::NotMet),
+            (Op::Ne, ValueType::Int, "x", "1", Status::NotMet),
+        ];
+        for (op, typ, target, val, status) in cases {
+            assert_eq!(
+                compare(op, typ, target).check(val),
+                status,
+                "{:?} {:?} {} {}",
+                op,
+                typ,
+                target,
+                val
+            );
+        }
+    }
+
+    #[test]
+    fn string_between_includes_both_bounds() {
+        let shard = Constraint::StringBetween("m".into(), "t".into());
+        assert_eq!(shard.check("m"), Status::Met);
+        assert_eq!(shard.check("t"), Status::Met);
+        assert_eq!(shard.check("mango"), Status::Met);
+        assert_eq!(shard.check("sz"), Status::Met);
+        assert_eq!(shard.check("l"), Status::NotMet);
+        assert_eq!(shard.check("lz"), Status::NotMet);
+        assert_eq!(shard.check("tz"), Status::NotMet);
+        assert_eq!(shard.check("u"), Status::NotMet);
+        assert_eq!(shard.check(""), Status::NotMet);
+        // byte-wise ordering puts upper case before lower case
+        assert_eq!(shard.check("N"), Status::NotMet);
+    }
+
+    #[test]
+    fn string_between_rejects_inverted_bounds() {
+        assert!(crate::string_between("shard", "name", "m", "m").is_ok());
+        assert!(crate::string_between("shard", "name", "t", "m").is_err());
+        assert!(crate::string_between("shard", "name", "a", "B").is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn freshness_at_and_beyond_half_life() {
+        let half = Constraint::FreshnessAtLeast {
+            half_life: TimeDelta::days(7),
+            min_score: 0.5,
+        };
+        let now = utc("2024-03-15");
+        assert_eq!(half.check_at("2024-03-15T12:00:00Z", now), Status::Met);
+        assert_eq!(half.check_at("2024-03-08T12:00:00Z", now), Status::Met);
+        assert_eq!(half.check_at("2024-03-08T11:59:59Z", now), Status::NotMet);
+        assert_eq!(half.check_at("2024-03-01T12:00:00Z", now), Status::NotMet);
+        // offsets are honoured, and future timestamps score as fresh
+        assert_eq!(half.check_at("2024-03-08T13:00:00+01:00", now), Status::Met);
+        assert_eq!(half.check_at("2024-04-01T00:00:00Z", now), Status::Met);
+        let quarter = Constraint::FreshnessAtLeast {
+            half_life: TimeDelta::days(7),
+            min_score: 0.25,
+        };
+        assert_eq!(quarter.check_at("2024-03-01T12:00:00Z", now), Status::Met);
+        assert_eq!(
+            quarter.check_at("2024-03-01T11:00:00Z", now),
+            Status::NotMet
+        );
+        assert_eq!(half.check_at("2024-03-15", now), Status::NotMet);
+        assert_eq!(half.check_at("yesterday", now), Status::NotMet);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn freshness_rejects_non_positive_half_life() {
+        let build = |half_life| crate::freshness_at_least("fresh", "seen", half_life, 0.5);
+        assert!(build(TimeDelta::days(7)).is_ok());
+        assert!(build(TimeDelta::zero()).is_err());
+        assert!(build(TimeDelta::days(-7)).is_err());
+
+        let rule = crate::and(vec![build(TimeDelta::days(7)).unwrap()]);
+        let json = serde_json::to_string(&rule).unwrap();
+        assert_eq!(serde_json::from_str::<crate::Rule>(&json).unwrap(), rule);
+        let json = serde_json::to_string(&crate::and(vec![crate::Rule::Rule {
+            desc: "fresh".into(),
+            field: "seen".into(),
+            constraint: Constraint::FreshnessAtLeast {
+                half_life: TimeDelta::zero(),
+                min_score: 0.5,
+            },
+        }]))
+        .unwrap();
+        assert!(serde_json::from_str::<crate::Rule>(&json).is_err());
+    }
+
+    #[test]
+    fn above_context_average_above_below_and_missing() {
+        let rule = crate::above_context_average("above average", "latency", "avg_latency");
+        let context = facts(&[("avg_latency", "120")]);
+        let check = |latency| {
+            rule.check_with_context(&facts(&[("latency", latency)]), &context)
+                .status
+        };
+        assert_eq!(check("150"), Status::Met);
+        assert_eq!(check("120.5"), Status::Met);
+        assert_eq!(check("120"), Status::NotMet);
+        assert_eq!(check("80"), Status::NotMet);
+        assert_eq!(check("slow"), Status::NotMet);
+
+        let info = facts(&[("latency", "150")]);
+        assert_eq!(
+            rule.check_with_context(&info, &facts(&[("other", "1")]))
+                .status,
+            Status::Unknown
+        );
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+    }
+
+    #[cfg(feature = "fuzzy")]
+    #[test]
+    fn fuzzy_one_of_within_edit_distance() {
+        let colors = Constraint::FuzzyOneOf {
+            values: vec!["red".into(), "green".into(), "blue".into()],
+            max_distance: 1,
+        };
+        assert_eq!(colors.check("green"), Status::Met);
+        assert_eq!(colors.check("gren"), Status::Met);
+        assert_eq!(colors.check("bleu"), Status::NotMet);
+        assert_eq!(colors.check("purple"), Status::NotMet);
+        assert_eq!(colors.check(""), Status::NotMet);
+        let exact = Constraint::FuzzyOneOf {
+            values: vec!["red".into()],
+            max_distance: 0,
+        };
+        assert_eq!(exact.check("red"), Status::Met);
+        assert_eq!(exact.check("rod"), Status::NotMet);
+    }
+
+    #[cfg(feature = "cron")]
+    #[test]
+    fn is_cron_valid_and_invalid() {
+        assert_eq!(Constraint::IsCron.check("0 30 9 * * Mon-Fri"), Status::Met);
+        assert_eq!(Constraint::IsCron.check("0 0 12 * * * 2024"), Status::Met);
+        assert_eq!(Constraint::IsCron.check("0 61 9 * * *"), Status::NotMet);
+        assert_eq!(Constraint::IsCron.check("every day"), Status::NotMet);
+        assert_eq!(Constraint::IsCron.check(""), Status::NotMet);
+    }
+
+    #[cfg(feature = "cron")]
+    #[test]
+    fn cron_matches_now_with_fixed_clock() {
+        // a Friday at noon
+        let now = utc("2024-03-15");
+        let check = |schedule| Constraint::CronMatchesNow.check_at(schedule, now);
+        assert_eq!(check("0 0 12 * * Fri"), Status::Met);
+        assert_eq!(check("0 0 12 * * Mon-Fri"), Status::Met);
+        assert_eq!(check("0 0 12 * * Sat,Sun"), Status::NotMet);
+        assert_eq!(check("0 0 13 * * *"), Status::NotMet);
+        assert_eq!(check("30 0 12 * * *"), Status::NotMet);
+        assert_eq!(check("not a schedule"), Status::NotMet);
+    }
+
+    #[test]
+    fn length_at_least_meters_across_units() {
+        let same_length = ["1ft", "12in", "30.48cm", "304.8 mm", "0.3048m", " 1 ft "];
+        for val in same_length {
+            let meters = length_in_meters(val).unwrap();
+            assert!((meters - 0.3048).abs() < 1e-9, "{}", val);
+            assert_eq!(Constraint::LengthAtLeastMeters(0.3).check(val), Status::Met);
+            assert_eq!(
+                Constraint::LengthAtLeastMeters(0.31).check(val),
+                Status::NotMet
+            );
+        }
+        let long = Constraint::LengthAtLeastMeters(1000.0);
+        assert_eq!(long.check("1km"), Status::Met);
+        assert_eq!(long.check("1mi"), Status::Met);
+        assert_eq!(long.check("1000yd"), Status::NotMet);
+        for invalid in ["30", "30 furlongs", "cm", "thirty cm", "30CM", ""] {
+            assert_eq!(long.check(invalid), Status::NotMet, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn entropy_of_low_and_high_entropy_strings() {
+        assert_eq!(entropy_bits("aaaa"), 0.0);
+        assert_eq!(entropy_bits(""), 0.0);
+        // four equally likely characters carry two bits each
+        assert!((entropy_bits("abcd") - 8.0).abs() < 1e-9);
+        assert!((entropy_bits("aabb") - 4.0).abs() < 1e-9);
+
+        let strong = Constraint::EntropyAtLeast(40.0);
+        assert_eq!(strong.check("aaaa"), Status::NotMet);
+        assert_eq!(strong.check("aaaaaaaaaaaaaaaaaaaaaaaa"), Status::NotMet);
+        assert_eq!(strong.check("k9#Qz!2mV$7pL@4x"), Status::Met);
+        assert_eq!(Constraint::EntropyAtLeast(0.0).check("aaaa"), Status::Met);
+    }
+
+    #[test]
+    fn number_at_least_in_both_locales() {
+        assert_eq!(NumberLocale::Us.parse("1,234.56"), Some(1234.56));
+        assert_eq!(NumberLocale::European.parse("1.234,56"), Some(1234.56));
+        assert_eq!(NumberLocale::Us.parse("-1234"), Some(-1234.0));
+        assert_eq!(NumberLocale::European.parse("1234,5"), Some(1234.5));
+        for invalid in ["1,23.4", "12,34,567", "1234,567,890", "1.2.3", "", "abc"] {
+            assert_eq!(NumberLocale::Us.parse(invalid), None, "{}", invalid);
+        }
+
+        let us = Constraint::NumberAtLeast {
+            value: 1000.0,
+            locale: NumberLocale::Us,
+        };
+        let european = Constraint::NumberAtLeast {
+            value: 1000.0,
+            locale: NumberLocale::European,
+        };
+        assert_eq!(us.check("1,234.56"), Status::Met);
+        assert_eq!(european.check("1.234,56"), Status::Met);
+        assert_eq!(us.check("999.99"), Status::NotMet);
+        assert_eq!(european.check("999,99"), Status::NotMet);
+        // each locale misreads the other's format
+        assert_eq!(us.check("1.234,56"), Status::NotMet);
+        assert_eq!(european.check("1,234.56"), Status::NotMet);
+        assert_eq!(us.check("lots"), Status::NotMet);
+    }
+
+    #[test]
+    fn is_palindrome_ignores_case_and_punctuation() {
+        assert_eq!(Constraint::IsPalindrome.check("Racecar"), Status::Met);
+        assert_eq!(
+            Constraint::IsPalindrome.check("A man a plan a canal Panama"),
+            Status::Met
+        );
+        assert_eq!(
+            Constraint::IsPalindrome.check("A man, a plan, a canal: Panama!"),
+            Status::Met
+        );
+        assert_eq!(Constraint::IsPalindrome.check("12321"), Status::Met);
+        assert_eq!(Constraint::IsPalindrome.check("Rust"), Status::NotMet);
+        assert_eq!(Constraint::IsPalindrome.check("ab"), Status::NotMet);
+        assert_eq!(Constraint::IsPalindrome.check("!?"), Status::Met);
+        assert_eq!(Constraint::IsPalindrome.check(""), Status::Met);
+    }
+
+    #[test]
+    fn z_score_within_and_outside_band() {
+        let rule = crate::z_score_within("typical height", "height", 2.0);
+        let context = facts(&[("mean_height", "170"), ("std_height", "10")]);
+        let check = |height| {
+            rule.check_with_context(&facts(&[("height", height)]), &context)
+                .status
+        };
+        assert_eq!(check("170"), Status::Met);
+        assert_eq!(check("185"), Status::Met);
+        assert_eq!(check("150"), Status::Met);
+        assert_eq!(check("190.5"), Status::NotMet);
+        assert_eq!(check("140"), Status::NotMet);
+        assert_eq!(check("tall"), Status::NotMet);
+
+        let info = facts(&[("height", "170")]);
+        let no_std = facts(&[("mean_height", "170")]);
+        assert_eq!(
+            rule.check_with_context(&info, &no_std).status,
+            Status::Unknown
+        );
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+        // a zero deviation only accepts the mean itself
+        let constant = facts(&[("mean_height", "170"), ("std_height", "0")]);
+        assert_eq!(
+            rule.check_with_context(&info, &constant).status,
+            Status::Met
+        );
+        let off = facts(&[("height", "170.1")]);
+        assert_eq!(
+            rule.check_with_context(&off, &constant).status,
+            Status::NotMet
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn within_business_days_across_weekend_and_holiday() {
+        let within = |days, holidays: &[&str]| Constraint::WithinBusinessDays {
+            days,
+            holidays: holidays.iter().map(|d| d.parse().unwrap()).collect(),
+        };
+        // a Friday
+        let now = utc("2024-03-15");
+        let five = within(5, &[]);
+        assert_eq!(five.check_at("2024-03-15", now), Status::Met);
+        assert_eq!(five.check_at("2024-03-22", now), Status::Met);
+        assert_eq!(five.check_at("2024-03-25", now), Status::NotMet);
+        assert_eq!(five.check_at("2024-03-08", now), Status::Met);
+        assert_eq!(five.check_at("2024-03-07", now), Status::NotMet);
+        // the weekend right after is zero business days away
+        let none = within(0, &[]);
+        assert_eq!(none.check_at("2024-03-17", now), Status::Met);
+        assert_eq!(none.check_at("2024-03-18", now), Status::NotMet);
+        // a holiday on the Tuesday pushes the limit to the next Monday
+        let with_holiday = within(5, &["2024-03-19"]);
+        assert_eq!(with_holiday.check_at("2024-03-25", now), Status::Met);
+        assert_eq!(with_holiday.check_at("2024-03-26", now), Status::NotMet);
+        assert_eq!(five.check_at("15/03/2024", now), Status::NotMet);
+        assert_eq!(five.check_at("soon", now), Status::NotMet);
+    }
+
+    #[test]
+    fn allowed_transition_allowed_disallowed_and_missing_source() {
+        let rule = crate::allowed_transition(
+            "valid order transition",
+            "new_status",
+            "status",
+            &[
+                ("pending", &["paid", "cancelled"]),
+                ("paid", &["shipped", "refunded"]),
+                ("shipped", &[]),
+            ],
+        );
+        let check = |from: &str, to: &str| {
+            rule.check(&facts(&[("status", from), ("new_status", to)]))
+                .status
+        };
+        assert_eq!(check("pending", "paid"), Status::Met);
+        assert_eq!(check("paid", "refunded"), Status::Met);
+        assert_eq!(check("pending", "shipped"), Status::NotMet);
+        assert_eq!(check("shipped", "pending"), Status::NotMet);
+        assert_eq!(check("archived", "pending"), Status::NotMet);
+        assert_eq!(
+            rule.check(&facts(&[("new_status", "paid")])).status,
+            Status::Unknown
+        );
+        assert_eq!(
+            Constraint::AllowedTransition {
+                field_from: "status".into(),
+                transitions: BTreeMap::new(),
+            }
+            .check("paid"),
+            Status::Unknown
+        );
+    }
+
+    #[test]
+    fn array_unique_unique_duplicates_and_empty() {
+        assert_eq!(Constraint::ArrayUnique.check("rust,go,zig"), Status::Met);
+        assert_eq!(Constraint::ArrayUnique.check("rust"), Status::Met);
+        assert_eq!(Constraint::ArrayUnique.check(""), Status::Met);
+        assert_eq!(
+            Constraint::ArrayUnique.check("rust,go,rust"),
+            Status::NotMet
+        );
+        assert_eq!(Constraint::ArrayUnique.check("a,a"), Status::NotMet);
+        // elements compare exactly, case and all
+        assert_eq!(Constraint::ArrayUnique.check("Rust,rust"), Status::Met);
+    }
+
+    #[test]
+    fn above_curve_on_and_between_control_points() {
+        // minimum score rises from 50 at x = 0 to 90 at x = 10, then 100 at x = 20
+        let rule = crate::above_curve(
+            "score",
+            "score",
+            "x",
+            &[(0.0, 50.0), (10.0, 90.0), (20.0, 100.0)],
+        )
+        .unwrap();
+        let check = |x: &str, score: &str| rule.check(&facts(&[("x", x), ("score", score)])).status;
+        assert_eq!(check("0", "50"), Status::Met);
+        assert_eq!(check("0", "49.9"), Status::NotMet);
+        assert_eq!(check("10", "90"), Status::Met);
+        assert_eq!(check("10", "89"), Status::NotMet);
+        assert_eq!(check("5", "70"), Status::Met);
+        assert_eq!(check("5", "69.9"), Status::NotMet);
+        assert_eq!(check("15", "95"), Status::Met);
+        assert_eq!(check("15", "94"), Status::NotMet);
+        // flat beyond the ends
+        assert_eq!(check("-5", "50"), Status::Met);
+        assert_eq!(check("30", "99"), Status::NotMet);
+        assert_eq!(check("five", "100"), Status::NotMet);
+        assert_eq!(check("5", "high"), Status::NotMet);
+        assert_eq!(
+            rule.check(&facts(&[("score", "100")])).status,
+            Status::Unknown
+        );
+    }
+
+    #[test]
+    fn above_curve_requires_ascending_points() {
+        assert!(crate::above_curve("s", "s", "x", &[(10.0, 1.0), (0.0, 2.0)]).is_err());
+        assert!(crate::above_curve("s", "s", "x", &[(0.0, 1.0), (0.0, 2.0)]).is_err());
+        assert!(crate::above_curve("s", "s", "x", &[]).is_err());
+        assert!(crate::above_curve("s", "s", "x", &[(0.0, 1.0)]).is_ok());
+    }
+
+    #[test]
+    fn hysteresis_keeps_prior_state_between_thresholds() {
+        let rule = crate::hysteresis("overheated", "temp", 80.0, 70.0, "was_overheated").unwrap();
+        let check = |temp: &str, state: &str| {
+            rule.check(&facts(&[("temp", temp), ("was_overheated", state)]))
+                .status
+        };
+        assert_eq!(check("75", "true"), Status::Met);
+        assert_eq!(check("75", "false"), Status::NotMet);
+        // the thresholds themselves switch regardless of the prior state
+        assert_eq!(check("80", "false"), Status::Met);
+        assert_eq!(check("70", "true"), Status::NotMet);
+        assert_eq!(check("95", "false"), Status::Met);
+        assert_eq!(check("20", "true"), Status::NotMet);
+        assert_eq!(check("75", "maybe"), Status::NotMet);
+        assert_eq!(check("hot", "true"), Status::NotMet);
+        let no_state = facts(&[("temp", "75")]);
+        assert_eq!(rule.check(&no_state).status, Status::Unknown);
+        let clear = facts(&[("temp", "90")]);
+        assert_eq!(rule.check(&clear).status, Status::Met);
+
+        assert!(crate::hysteresis("x", "t", 70.0, 80.0, "s").is_err());
+        assert!(crate::hysteresis("x", "t", 70.0, 70.0, "s").is_err());
+    }
+
+    #[test]
+    fn password_policy_compliant_and_each_failure() {
+        let policy = Constraint::PasswordPolicy {
+            min_len: 8,
+            max_len: 64,
+            require_digit: true,
+            require_upper: true,
+            require_lower: true,
+            require_symbol: true,
+        };
+        assert_eq!(policy.check("Tr0ub4dor&3"), Status::Met);
+        let failing = [
+            ("Tr0b&3", "too short"),
+            ("Troubador&x", "no digit"),
+            ("tr0ub4dor&3", "no upper case"),
+            ("TR0UB4DOR&3", "no lower case"),
+            ("Tr0ub4dor33", "no symbol"),
+            ("Tr0 ub4dor3", "a space is no symbol"),
+        ];
+        for (password, reason) in failing {
+            assert_eq!(policy.check(password), Status::NotMet, "{}", reason);
+        }
+        assert_eq!(
+            policy.check(&format!("Aa1!{}", "x".repeat(60))),
+            Status::Met
+        );
+        assert_eq!(
+            policy.check(&format!("Aa1!{}", "x".repeat(61))),
+            Status::NotMet
+        );
+
+        let length_only = Constraint::PasswordPolicy {
+            min_len: 4,
+            max_len: 8,
+            require_digit: false,
+            require_upper: false,
+            require_lower: false,
+            require_symbol: false,
+        };
+        assert_eq!(length_only.check("aaaa"), Status::Met);
+        assert_eq!(length_only.check("aaa"), Status::NotMet);
+    }
+
+    #[test]
+    fn password_policy_rejects_inverted_lengths() {
+        assert!(crate::password_policy("password", "password", 8, 8).is_ok());
+        assert!(crate::password_policy("password", "password", 12, 8).is_err());
+    }
+
+    #[test]
+    fn within_category_distance_zero_within_and_too_far() {
+        // shoes -> footwear -> apparel -> fashion, and shoes -> sale
+        let graph = [
+            ("shoes", vec!["footwear", "sale"]),
+            ("footwear", vec!["apparel"]),
+            ("apparel", vec!["fashion"]),
+            ("fashion", vec!["apparel"]),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.into_iter().map(String::from).collect()))
+        .collect::<BTreeMap<String, Vec<String>>>();
+        let within = |target: &str, max_hops| Constraint::WithinCategoryDistance {
+            graph: graph.clone(),
+            target: target.into(),
+            max_hops,
+        };
+        assert_eq!(within("apparel", 0).check("apparel"), Status::Met);
+        assert_eq!(within("apparel", 2).check("shoes"), Status::Met);
+        assert_eq!(within("apparel", 1).check("footwear"), Status::Met);
+        assert_eq!(within("fashion", 2).check("shoes"), Status::NotMet);
+        assert_eq!(within("fashion", 3).check("shoes"), Status::Met);
+        // edges only go one way
+        assert_eq!(within("shoes", 5).check("apparel"), Status::NotMet);
+        assert_eq!(within("apparel", 5).check("sale"), Status::NotMet);
+        assert_eq!(within("apparel", 5).check("toys"), Status::NotMet);
+    }
+
+    #[test]
+    fn close_to_near_zero_and_for_large_values() {
+        let close = |reference| Constraint::CloseTo {
+            reference,
+            rel_tol: 1e-3,
+            abs_tol: 1e-6,
+        };
+        // near zero the relative tolerance vanishes and the absolute one decides
+        assert_eq!(close(0.0).check("0.0000005"), Status::Met);
+        assert_eq!(close(0.0).check("-0.000001"), Status::Met);
+        assert_eq!(close(0.0).check("0.00001"), Status::NotMet);
+        // for large values the relative tolerance dominates
+        assert_eq!(close(1e9).check("1000900000"), Status::Met);
+        assert_eq!(close(1e9).check("999000000"), Status::Met);
+        assert_eq!(close(1e9).check("1002000000"), Status::NotMet);
+        assert_eq!(close(-1e9).check("-1000900000"), Status::Met);
+        assert_eq!(close(1.0).check("NaN"), Status::NotMet);
+        assert_eq!(close(f64::NAN).check("NaN"), Status::NotMet);
+        assert_eq!(close(1.0).check("one"), Status::NotMet);
+    }
+
+    #[test]
+    fn close_to_rejects_negative_and_nan_tolerances() {
+        assert!(crate::close_to("near", "x", 1.0, 0.0, 0.0).is_ok());
+        assert!(crate::close_to("near", "x", 1.0, -1e-3, 1e-6).is_err());
+        assert!(crate::close_to("near", "x", 1.0, 1e-3, -1e-6).is_err());
+        assert!(crate::close_to("near", "x", 1.0, f64::NAN, 1e-6).is_err());
+        assert!(crate::close_to("near", "x", 1.0, 1e-3, f64::NAN).is_err());
+    }
+
+    #[cfg(feature = "ulid")]
+    #[test]
+    fn ulid_timestamp_in_and_out_of_range() {
+        let in_march = crate::ulid_timestamp_in_range(
+            "in March",
+            "id",
+            "2024-03-01T00:00:00Z",
+            "2024-03-31T23:59:59Z",
+        )
+        .unwrap();
+        let in_march = match in_march {
+            crate::Rule::Rule { constraint, .. } => constraint,
+            _ => unreachable!(),
+        };
+        let ulid_at = |time: &str| {
+            let ms = DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .timestamp_millis();
+            ulid::Ulid::from_parts(ms as u64, 42).to_string()
+        };
+        assert_eq!(
+            in_march.check(&ulid_at("2024-03-15T12:00:00Z")),
+            Status::Met
+        );
+        assert_eq!(
+            in_march.check(&ulid_at("2024-03-01T00:00:00Z")),
+            Status::Met
+        );
+        assert_eq!(
+            in_march.check(&ulid_at("2024-03-31T23:59:59Z")),
+            Status::Met
+        );
+        assert_eq!(
+            in_march.check(&ulid_at("2024-02-29T23:59:59Z")),
+            Status::NotMet
+        );
+        assert_eq!(
+            in_march.check(&ulid_at("2024-04-01T00:00:00Z")),
+            Status::NotMet
+        );
+        // offsets in the bounds are honoured
+        assert_eq!(
+            in_march.check(&ulid_at("2024-03-01T00:30:00+01:00")),
+            Status::NotMet
+        );
+        for malformed in [
+            "",
+            "not-a-ulid",
+            "01ARZ3NDEKTSV4RRFFQ69G5FA",
+            "01ARZ3NDEKTSV4RRFFQ69G5FAVU",
+        ] {
+            assert_eq!(in_march.check(malformed), Status::NotMet, "{}", malformed);
+        }
+    }
+
+    #[cfg(feature = "ulid")]
+    #[test]
+    fn ulid_timestamp_in_range_rejects_bad_bounds() {
+        let build = |start, end| crate::ulid_timestamp_in_range("id", "id", start, end);
+        assert!(build("March", "2024-04-01T00:00:00Z").is_err());
+        assert!(build("2024-03-01T00:00:00Z", "April").is_err());
+        assert!(build("2024-04-01T00:00:00Z", "2024-03-01T00:00:00Z").is_err());
+        assert!(build("2024-03-01T00:00:00Z", "2024-03-01T00:00:00Z").is_ok());
+
+        let json = |start, end| {
+            format!(
+                r#"{{"Rule":{{"desc":"id","field":"id","constraint":
+                    {{"UlidTimestampInRange":["{}","{}"]}}}}}}"#,
+                start, end
+            )
+        };
+        let parse = |start, end| serde_json::from_str::<crate::Rule>(&json(start, end));
+        assert!(parse("2024-03-01T00:00:00Z", "2024-03-31T23:59:59Z").is_ok());
+        assert!(parse("March", "April").is_err());
+        assert!(parse("2024-03-31T00:00:00Z", "2024-03-01T00:00:00Z").is_err());
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn only_script_with_mixed_script_input() {
+        let latin = Constraint::OnlyScript("Latin".into());
+        assert_eq!(latin.check("Zoë Ångström"), Status::Met);
+        assert_eq!(latin.check("Route 66, exit 4!"), Status::Met);
+        assert_eq!(latin.check("Иван"), Status::NotMet);
+        assert_eq!(latin.check("Paypal vs Pаypal"), Status::NotMet);
+        assert_eq!(latin.check(""), Status::Met);
+        let cyrillic = Constraint::OnlyScript("Cyrl".into());
+        assert_eq!(cyrillic.check("Иван Петров"), Status::Met);
+        assert_eq!(cyrillic.check("Иван Petrov"), Status::NotMet);
+        assert_eq!(
+            Constraint::OnlyScript("Klingon".into()).check("abc"),
+            Status::NotMet
+        );
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn is_ascii_rejects_non_ascii_characters() {
+        assert_eq!(Constraint::IsAscii.check("plain text 123 ~!"), Status::Met);
+        assert_eq!(Constraint::IsAscii.check(""), Status::Met);
+        assert_eq!(Constraint::IsAscii.check("café"), Status::NotMet);
+        assert_eq!(
+            Constraint::IsAscii.check("non\u{a0}breaking"),
+            Status::NotMet
+        );
+        assert_eq!(Constraint::IsAscii.check("emoji 🙂"), Status::NotMet);
+    }
+
+    #[test]
+    fn digit_sum_equals_sums_decimal_digits() {
+        assert_eq!(Constraint::DigitSumEquals(6).check("123"), Status::Met);
+        assert_eq!(Constraint::DigitSumEquals(27).check("999"), Status::Met);
+        assert_eq!(Constraint::DigitSumEquals(26).check("999"), Status::NotMet);
+        assert_eq!(Constraint::DigitSumEquals(6).check("-123"), Status::Met);
+        assert_eq!(Constraint::DigitSumEquals(0).check("0"), Status::Met);
+        // longer than any integer type
+        let long = "1".repeat(40);
+        assert_eq!(Constraint::DigitSumEquals(40).check(&long), Status::Met);
+        for invalid in ["abc", "12a", "1.5", "", "-", "1 2"] {
+            assert_eq!(
+                Constraint::DigitSumEquals(3).check(invalid),
+                Status::NotMet,
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn within_radius_inside_on_boundary_and_outside() {
+        // one degree of latitude north of the center is exactly this far
+        let one_degree = EARTH_RADIUS_M * 1f64.to_radians();
+        let fence = crate::within_radius("near", "lat", "lon", 52.0, 4.0, one_degree);
+        let check =
+            |lat: &str, lon: &str| fence.check(&facts(&[("lat", lat), ("lon", lon)])).status;
+        assert_eq!(check("52", "4"), Status::Met);
+        assert_eq!(check("52.5", "4.5"), Status::Met);
+        assert_eq!(check("53", "4"), Status::Met);
+        assert_eq!(check("51", "4"), Status::Met);
+        assert_eq!(check("53.001", "4"), Status::NotMet);
+        assert_eq!(check("52", "5.7"), Status::NotMet);
+        assert_eq!(check("-52", "-176"), Status::NotMet);
+        assert_eq!(check("north", "4"), Status::NotMet);
+        assert_eq!(
+            fence.check(&facts(&[("lat", "52")])).status,
+            Status::Unknown
+        );
+        assert_eq!(fence.check(&facts(&[("lon", "4")])).status, Status::Unknown);
+
+        // Amsterdam to Paris is about 430 km
+        let amsterdam = (52.3676, 4.9041);
+        let paris = (48.8566, 2.3522);
+        assert!((haversine_m(amsterdam, paris) - 430_000.0).abs() < 5_000.0);
+    }
+
+    #[test]
+    fn allow_deny_deny_wins_then_allow_then_default() {
+        let policy = crate::allow_deny(
+            "role",
+            "role",
+            &["admin", "editor", "guest"],
+            &["guest", "banned"],
+            Status::Unknown,
+        );
+        let check = |role: &str| policy.check(&facts(&[("role", role)])).status;
+        assert_eq!(check("guest"), Status::NotMet);
+        assert_eq!(check("banned"), Status::NotMet);
+        assert_eq!(check("admin"), Status::Met);
+        assert_eq!(check("editor"), Status::Met);
+        assert_eq!(check("viewer"), Status::Unknown);
+
+        let closed = crate::allow_deny("role", "role", &["admin"], &[], Status::NotMet);
+        assert_eq!(
+            closed.check(&facts(&[("role", "viewer")])).status,
+            Status::NotMet
+        );
+        assert_eq!(closed.check(&facts(&[])).status, Status::Unknown);
+    }
+
+    #[test]
+    fn above_context_median_above_below_equal_and_missing() {
+        let rule = crate::above_context_median("above median", "price", "median_price");
+        let context = facts(&[("median_price", "9.99")]);
+        let check = |price| {
+            rule.check_with_context(&facts(&[("price", price)]), &context)
+                .status
+        };
+        assert_eq!(check("12"), Status::Met);
+        assert_eq!(check("4.50"), Status::NotMet);
+        assert_eq!(check("9.99"), Status::NotMet);
+        assert_eq!(check("cheap"), Status::NotMet);
+
+        let info = facts(&[("price", "12")]);
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+        assert_eq!(
+            rule.check_with_context(&info, &facts(&[("median_cost", "5")]))
+                .status,
+            Status::Unknown
+        );
     }
 }
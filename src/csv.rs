@@ -0,0 +1,62 @@
+use crate::rule::{Rule, RuleResult};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Checks `rule` against every row of a CSV document, returning one result per row.
+///
+/// The header row supplies the field names, each following row becomes a facts map.
+///
+/// * Quoting follows RFC 4180, quoted values are unquoted before checking
+/// * Empty cells are present as empty strings, they do not count as missing
+/// * Rows shorter than the header simply lack the trailing fields, so rules on
+///   those fields are `Unknown`. Cells beyond the header are ignored
+pub fn check_csv_reader<R: Read>(rule: &Rule, reader: R) -> anyhow::Result<Vec<RuleResult>> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(reader);
+    let headers = reader.headers()?.clone();
+    reader
+        .records()
+        .map(|record| {
+            let facts = headers
+                .iter()
+                .zip(record?.iter())
+                .map(|(field, val)| (field.to_owned(), val.to_owned()))
+                .collect::<BTreeMap<_, _>>();
+            Ok(rule.check(&facts))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+
+    #[test]
+    fn checks_each_row_of_in_memory_csv() {
+        let rule = crate::and(vec![
+            crate::string_equals("country", "country", "NZ"),
+            crate::int_range("age", "age", 18, 65),
+        ]);
+        let csv = "name,country,age\n\
+                   alice,NZ,30\n\
+                   \"bob, jr\",NZ,12\n\
+                   carol,\"NZ\"\n\
+                   dave,,40\n";
+        let results = check_csv_reader(&rule, csv.as_bytes()).unwrap();
+        let statuses = results.iter().map(|r| r.status).collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [Status::Met, Status::NotMet, Status::Unknown, Status::NotMet]
+        );
+    }
+
+    #[test]
+    fn header_only_has_no_results() {
+        let rule = crate::string_equals("country", "country", "NZ");
+        assert!(check_csv_reader(&rule, "country\n".as_bytes())
+            .unwrap()
+            .is_empty());
+    }
+}
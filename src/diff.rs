@@ -0,0 +1,280 @@
+use crate::rule::{Rule, RuleResult};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// A single difference between two rule trees, located by its `path` of child
+/// indices from the root (see `Rule::subtree_at()`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RuleChange {
+    /// Node present only in the new tree, `path` is its position there
+    Added { path: Vec<usize>, rule: Rule },
+    /// Node present only in the old tree, `path` is its position there
+    Removed { path: Vec<usize>, rule: Rule },
+    /// Node replaced, either a changed leaf, a combinator of another kind, or a
+    /// `RuleSet` entry whose label changed
+    Modified {
+        path: Vec<usize>,
+        old: Rule,
+        new: Rule,
+    },
+}
+
+/// Structural differences between two versions of a rule tree, depth-first.
+///
+/// Children of matching combinators are paired by position, so a node inserted in
+/// the middle shows up as the following siblings being modified plus one added at
+/// the end. Combinators of different kinds, or `NumberOf` with a different `n`,
+/// are reported as one modification of the whole subtree
+pub fn diff_rules(old: &Rule, new: &Rule) -> Vec<RuleChange> {
+    let mut changes = Vec::new();
+    diff_node(old, new, &mut Vec::new(), &mut changes);
+    changes
+}
+
+fn diff_node(old: &Rule, new: &Rule, path: &mut Vec<usize>, changes: &mut Vec<RuleChange>) {
+    if old == new {
+        return;
+    }
+    let same_kind = match (old, new) {
+        (Rule::And { .. }, Rule::And { .. })
+        | (Rule::Or { .. }, Rule::Or { .. })
+        | (Rule::Majority { .. }, Rule::Majority { .. })
+        | (Rule::Chain { .. }, Rule::Chain { .. })
+        | (Rule::Coalesce { .. }, Rule::Coalesce { .. })
+        | (Rule::Not { .. }, Rule::Not { .. })
+        | (Rule::RuleSet { .. }, Rule::RuleSet { .. }) => true,
+        (Rule::NumberOf { n: a, .. }, Rule::NumberOf { n: b, .. }) => a == b,
+        (Rule::WithId { id: a, .. }, Rule::WithId { id: b, .. }) => a == b,
+        (
+            Rule::DependsOn {
+                rule_id: a,
+                expect: x,
+                ..
+            },
+            Rule::DependsOn {
+                rule_id: b,
+                expect: y,
+                ..
+            },
+        ) => a == b && x == y,
+        _ => false,
+    };
+    if !same_kind {
+        changes.push(RuleChange::Modified {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        });
+        return;
+    }
+    let (old_children, new_children) = (old.children(), new.children());
+    for i in 0..old_children.len().max(new_children.len()) {
+        path.push(i);
+        match (old_children.get(i), new_children.get(i)) {
+            (Some(&o), Some(&n)) if label(old, i) != label(new, i) => {
+                changes.push(RuleChange::Modified {
+                    path: path.clone(),
+                    old: o.clone(),
+                    new: n.clone(),
+                })
+            }
+            (Some(&o), Some(&n)) => diff_node(o, n, path, changes),
+            (Some(&o), None) => changes.push(RuleChange::Removed {
+                path: path.clone(),
+                rule: o.clone(),
+            }),
+            (None, Some(&n)) => changes.push(RuleChange::Added {
+                path: path.clone(),
+                rule: n.clone(),
+            }),
+            (None, None) => {}
+        }
+        path.pop();
+    }
+}
+
+/// Label of the `i`th entry if `rule` is a `RuleSet`
+fn label(rule: &Rule, i: usize) -> Option<&str> {
+    match *rule {
+        Rule::RuleSet { ref rules } => rules.get(i).map(|(label, _)| label.as_str()),
+        _ => None,
+    }
+}
+
+/// Renders two results of the same tree side by side, one node per line, with the
+/// status from `a` and from `b` in aligned columns.
+///
+/// Lines whose statuses differ start with `*`. Children are paired by position,
+/// a node missing on one side shows `-` there and counts as different:
+///
+/// ```text
+///   And                               Met      Met
+///     Name is John Doe                Met      Met
+///     Or                              Met      Met
+/// *     Favorite number is 10         Met      NotMet
+/// *     Fav number between 11 and 16  NotMet   Met
+/// ```
+pub fn render_side_by_side(a: &RuleResult, b: &RuleResult) -> String {
+    let mut rows = Vec::new();
+    side_by_side_rows(Some(a), Some(b), 0, &mut rows);
+    let width = rows
+        .iter()
+        .map(|(name, ..)| name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::new();
+    for (name, a, b) in rows {
+        let mark = if a == b { ' ' } else { '*' };
+        let _ = writeln!(
+            out,
+            "{} {:<width$}  {:<7}  {}",
+            mark,
+            name,
+            a,
+            b,
+            width = width
+        );
+    }
+    out
+}
+
+/// Appends `(indented name, status in a, status in b)` rows for a pair of nodes
+fn side_by_side_rows(
+    a: Option<&RuleResult>,
+    b: Option<&RuleResult>,
+    depth: usize,
+    rows: &mut Vec<(String, String, String)>,
+) {
+    let name = match a.or(b) {
+        Some(node) => &node.name,
+        None => return,
+    };
+    let status = |node: Option<&RuleResult>| node.map_or("-".into(), |n| format!("{:?}", n.status));
+    rows.push((
+        format!("{}{}", "  ".repeat(depth), name),
+        status(a),
+        status(b),
+    ));
+    let children = |node: Option<&RuleResult>| node.map_or(0, |n| n.children.len());
+    for i in 0..children(a).max(children(b)) {
+        side_by_side_rows(
+            a.and_then(|n| n.children.get(i)),
+            b.and_then(|n| n.children.get(i)),
+            depth + 1,
+            rows,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::Constraint;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn reports_changed_constraint_and_added_leaf() {
+        let old = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::or(vec![crate::int_equals("favorite", "number", 10)]),
+        ]);
+        let new = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::or(vec![
+                crate::int_equals("favorite", "number", 11),
+                crate::int_range("range", "number", 11, 16),
+            ]),
+        ]);
+        assert_eq!(
+            diff_rules(&old, &new),
+            [
+                RuleChange::Modified {
+                    path: vec![1, 0],
+                    old: crate::int_equals("favorite", "number", 10),
+                    new: crate::int_equals("favorite", "number", 11),
+                },
+                RuleChange::Added {
+                    path: vec![1, 1],
+                    rule: crate::int_range("range", "number", 11, 16),
+                },
+            ]
+        );
+        assert_eq!(
+            diff_rules(&new, &old)[1],
+            RuleChange::Removed {
+                path: vec![1, 1],
+                rule: crate::int_range("range", "number", 11, 16),
+            }
+        );
+        assert!(diff_rules(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn reports_combinator_of_another_kind_as_one_modification() {
+        let leaf = crate::boolean("verified", "verified", true);
+        let old = crate::and(vec![leaf.clone()]);
+        let new = crate::or(vec![leaf.with_constraint(Constraint::Boolean(false))]);
+        assert_eq!(
+            diff_rules(&old, &new),
+            [RuleChange::Modified {
+                path: Vec::new(),
+                old: old.clone(),
+                new: new.clone(),
+            }]
+        );
+    }
+
+    fn main_tree() -> Rule {
+        crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ])
+    }
+
+    fn facts(fav_number: &str) -> BTreeMap<String, String> {
+        [("name", "John Doe"), ("fav_number", fav_number)]
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn side_by_side_marks_differing_leaves() {
+        let tree = main_tree();
+        let rendered = render_side_by_side(&tree.check(&facts("10")), &tree.check(&facts("13")));
+        assert_eq!(
+            rendered,
+            "  And                               Met      Met\n\
+             \x20   Name is John Doe                Met      Met\n\
+             \x20   Or                              Met      Met\n\
+             *     Favorite number is 10         Met      NotMet\n\
+             *     Fav number between 11 and 16  NotMet   Met\n"
+        );
+        let same = render_side_by_side(&tree.check(&facts("10")), &tree.check(&facts("10")));
+        assert!(!same.contains('*'));
+    }
+
+    #[test]
+    fn side_by_side_shows_missing_nodes() {
+        let info = facts("10");
+        let a = crate::and(vec![crate::string_equals(
+            "Name is John Doe",
+            "name",
+            "John Doe",
+        )]);
+        let b = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::int_equals("Favorite number is 10", "fav_number", 10),
+        ]);
+        let rendered = render_side_by_side(&a.check(&info), &b.check(&info));
+        assert_eq!(
+            rendered,
+            "  And                      Met      Met\n\
+             \x20   Name is John Doe       Met      Met\n\
+             *   Favorite number is 10  -        Met\n"
+        );
+    }
+}
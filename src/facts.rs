@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+/// Anything rules can look facts up in by field name, see `Rule::check_facts()`.
+///
+/// Implemented for the usual string maps. Sources that produce values on the fly
+/// return them owned, maps hand out borrows
+pub trait FactSource {
+    /// Value of `field`, `None` if the source has no such field
+    fn fact(&self, field: &str) -> Option<Cow<'_, str>>;
+}
+
+impl FactSource for BTreeMap<String, String> {
+    fn fact(&self, field: &str) -> Option<Cow<'_, str>> {
+        self.get(field).map(|val| Cow::Borrowed(val.as_str()))
+    }
+}
+
+impl FactSource for HashMap<String, String> {
+    fn fact(&self, field: &str) -> Option<Cow<'_, str>> {
+        self.get(field).map(|val| Cow::Borrowed(val.as_str()))
+    }
+}
+
+/// A record exposing its columns by name, such as a row of a query result.
+///
+/// Implement this for the row type of whatever database library is in use, then
+/// check rules against it through [`RowFacts`] without building a map first
+pub trait Row {
+    /// Text form of the column `name`, `None` if there is no such column or it is `NULL`
+    fn column(&self, name: &str) -> Option<String>;
+}
+
+/// Adapts a [`Row`] into a [`FactSource`], with field names used as column names.
+///
+/// Only the columns the rules ask for are read
+pub struct RowFacts<'r, R: ?Sized>(pub &'r R);
+
+impl<R: Row + ?Sized> FactSource for RowFacts<'_, R> {
+    fn fact(&self, field: &str) -> Option<Cow<'_, str>> {
+        self.0.column(field).map(Cow::Owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+
+    /// Row of a query result, with `NULL` columns as `None`
+    struct MockRow {
+        columns: Vec<&'static str>,
+        values: Vec<Option<&'static str>>,
+    }
+
+    impl Row for MockRow {
+        fn column(&self, name: &str) -> Option<String> {
+            let i = self.columns.iter().position(|&c| c == name)?;
+            self.values[i].map(str::to_owned)
+        }
+    }
+
+    #[test]
+    fn rules_check_against_a_row() {
+        let row = MockRow {
+            columns: vec!["name", "age", "email"],
+            values: vec![Some("John Doe"), Some("42"), None],
+        };
+        let facts = RowFacts(&row);
+        assert_eq!(facts.fact("age").as_deref(), Some("42"));
+        assert_eq!(facts.fact("email"), None);
+        assert_eq!(facts.fact("missing"), None);
+
+        let adult = crate::and(vec![
+            crate::string_equals("name", "name", "John Doe"),
+            crate::int_range("age", "age", 18, 65),
+        ]);
+        assert_eq!(adult.check_facts(&facts).status, Status::Met);
+        let with_email = crate::string_equals("email", "email", "john@example.com");
+        assert_eq!(with_email.check_facts(&facts).status, Status::Unknown);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::facts::FactSource;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Value of the [JSON-Logic](https://jsonlogic.com) expression `logic`, with `var`
+/// reading from `facts`.
+///
+/// Supported operators:
+///
+/// * `var`: the fact named by the first argument, or the optional second argument
+///   (otherwise `null`) when there is no such fact. Facts that parse as numbers are
+///   numbers, other facts are strings
+/// * `==`, `!=` compare loosely, converting between numbers, strings and booleans,
+///   `===`, `!==` compare strictly
+/// * `<`, `<=`, `>`, `>=` compare numbers, or strings when both sides are strings.
+///   `<` and `<=` also take three arguments, checking the middle one lies between
+///   the others
+/// * `!`, `!!`, `and`, `or`, `if` with the usual truthiness: `false`, `null`, `0`,
+///   `""` and `[]` are falsy, everything else truthy
+/// * `in`: substring of a string, or member of an array
+/// * `+`, `-`, `*`, `/`, `%`, `min`, `max` on numbers, `cat` joining strings
+///
+/// Anything else, including unknown operators, evaluates to `null`
+pub fn evaluate(logic: &Value, facts: &dyn FactSource) -> Value {
+    let (op, args) = match *logic {
+        Value::Object(ref map) if map.len() == 1 => map.iter().next().expect("one entry"),
+        Value::Array(ref items) => return items.iter().map(|i| evaluate(i, facts)).collect(),
+        _ => return logic.clone(),
+    };
+    let args = match *args {
+        Value::Array(ref items) => items.iter().collect::<Vec<_>>(),
+        ref arg => vec![arg],
+    };
+    let arg = |i: usize| args.get(i).map_or(Value::Null, |a| evaluate(a, facts));
+    let values = || args.iter().map(|a| evaluate(a, facts)).collect::<Vec<_>>();
+    match op.as_str() {
+        "var" => {
+            let name = match arg(0) {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                _ => return arg(1),
+            };
+            match facts.fact(&name) {
+                Some(val) => fact_value(&val),
+                None => arg(1),
+            }
+        }
+        "==" => Value::Bool(loose_eq(&arg(0), &arg(1))),
+        "!=" => Value::Bool(!loose_eq(&arg(0), &arg(1))),
+        "===" => Value::Bool(strict_eq(&arg(0), &arg(1))),
+        "!==" => Value::Bool(!strict_eq(&arg(0), &arg(1))),
+        "<" | "<=" | ">" | ">=" => {
+            let values = values();
+            let holds = |a: &Value, b: &Value| match compare(a, b) {
+                Some(ordering) => match op.as_str() {
+                    "<" => ordering == Ordering::Less,
+                    "<=" => ordering != Ordering::Greater,
+                    ">" => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                },
+                None => false,
+            };
+            let held = match values.len() {
+                2 => holds(&values[0], &values[1]),
+                3 if op == "<" || op == "<=" => {
+                    holds(&values[0], &values[1]) && holds(&values[1], &values[2])
+                }
+                _ => false,
+            };
+            Value::Bool(held)
+        }
+        "!" => Value::Bool(!truthy(&arg(0))),
+        "!!" => Value::Bool(truthy(&arg(0))),
+        "and" | "or" => {
+            let mut last = Value::Null;
+            for a in &args {
+                last = evaluate(a, facts);
+                if truthy(&last) != (op == "and") {
+                    break;
+                }
+            }
+            last
+        }
+        "if" => {
+            let mut i = 0;
+            while i + 1 < args.len() {
+                if truthy(&arg(i)) {
+                    return arg(i + 1);
+                }
+                i += 2;
+            }
+            arg(i)
+        }
+        "in" => {
+            let found = match arg(1) {
+                Value::String(haystack) => haystack.contains(&as_string(&arg(0))),
+                Value::Array(items) => {
+                    let needle = arg(0);
+                    items.iter().any(|i| strict_eq(i, &needle))
+                }
+                _ => false,
+            };
+            Value::Bool(found)
+        }
+        "cat" => Value::String(values().iter().map(as_string).collect()),
+        "+" | "*" | "min" | "max" => {
+            let numbers = values().iter().map(as_number).collect::<Vec<_>>();
+            let result = match op.as_str() {
+                "+" => Some(numbers.iter().sum()),
+                "*" => Some(numbers.iter().product()),
+                "min" => numbers.iter().copied().reduce(f64::min),
+                _ => numbers.iter().copied().reduce(f64::max),
+            };
+            result.map_or(Value::Null, number)
+        }
+        "-" | "/" | "%" => {
+            let (a, b) = (as_number(&arg(0)), as_number(&arg(1)));
+            match (op.as_str(), args.len()) {
+                ("-", 1) => number(-a),
+                ("-", _) => number(a - b),
+                ("/", _) => number(a / b),
+                _ => number(a % b),
+            }
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Whether JSON-Logic treats `value` as true
+pub fn truthy(value: &Value) -> bool {
+    match *value {
+        Value::Null => false,
+        Value::Bool(b) => b,
+        Value::Number(ref n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(ref s) => !s.is_empty(),
+        Value::Array(ref items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+/// A fact as seen by `var`: a number when it parses as one, a string otherwise
+fn fact_value(val: &str) -> Value {
+    match val.parse::<f64>() {
+        Ok(n) if n.is_finite() => number(n),
+        _ => Value::String(val.to_owned()),
+    }
+}
+
+/// JSON number for `n`, `null` if it is not finite. Integral values stay integers
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn as_number(value: &Value) -> f64 {
+    match *value {
+        Value::Null => 0.0,
+        Value::Bool(b) => f64::from(u8::from(b)),
+        Value::Number(ref n) => n.as_f64().unwrap_or(f64::NAN),
+        Value::String(ref s) if s.trim().is_empty() => 0.0,
+        Value::String(ref s) => s.trim().parse().unwrap_or(f64::NAN),
+        Value::Array(_) | Value::Object(_) => f64::NAN,
+    }
+}
+
+fn as_string(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        Value::Number(ref n) => n.to_string(),
+        ref other => other.to_string(),
+    }
+}
+
+fn strict_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn loose_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, Value::Null) => true,
+        (Value::Null, _) | (_, Value::Null) => false,
+        (Value::String(a), Value::String(b)) => a == b,
+        (Value::Array(_) | Value::Object(_), _) | (_, Value::Array(_) | Value::Object(_)) => a == b,
+        _ => as_number(a) == as_number(b),
+    }
+}
+
+/// Ordering of two values, as strings if both are, as numbers otherwise. `None` if
+/// either is not a number
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => as_number(a).partial_cmp(&as_number(b)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn facts(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn simple_comparison() {
+        let adult = json!({ ">=": [{ "var": "age" }, 18] });
+        assert_eq!(evaluate(&adult, &facts(&[("age", "30")])), json!(true));
+        assert_eq!(evaluate(&adult, &facts(&[("age", "12")])), json!(false));
+        assert_eq!(evaluate(&adult, &facts(&[])), json!(false));
+        assert_eq!(
+            evaluate(&json!({ "var": ["age", 99] }), &facts(&[])),
+            json!(99)
+        );
+        assert_eq!(
+            evaluate(
+                &json!({ "==": [{ "var": "age" }, "30"] }),
+                &facts(&[("age", "30")])
+            ),
+            json!(true)
+        );
+        assert_eq!(
+            evaluate(
+                &json!({ "===": [{ "var": "age" }, "30"] }),
+                &facts(&[("age", "30")])
+            ),
+            json!(false)
+        );
+        assert_eq!(
+            evaluate(
+                &json!({ "<": [1, { "var": "x" }, 3] }),
+                &facts(&[("x", "2")])
+            ),
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn nested_boolean_expression() {
+        let logic = json!({
+            "and": [
+                { "in": [{ "var": "country" }, ["NL", "DE"]] },
+                { "or": [
+                    { ">": [{ "var": "total" }, 100] },
+                    { "!": { "var": "first_order" } }
+                ] }
+            ]
+        });
+        let check = |pairs: &[(&str, &str)]| truthy(&evaluate(&logic, &facts(pairs)));
+        assert!(check(&[
+            ("country", "NL"),
+            ("total", "150"),
+            ("first_order", "1")
+        ]));
+        assert!(check(&[
+            ("country", "DE"),
+            ("total", "50"),
+            ("first_order", "0")
+        ]));
+        assert!(!check(&[
+            ("country", "DE"),
+            ("total", "50"),
+            ("first_order", "1")
+        ]));
+        assert!(!check(&[
+            ("country", "US"),
+            ("total", "150"),
+            ("first_order", "0")
+        ]));
+
+        let rule = crate::json_logic("eligible", "country", logic.clone());
+        let info = facts(&[("country", "NL"), ("total", "150"), ("first_order", "1")]);
+        assert_eq!(rule.check(&info).status, Status::Met);
+        let info = facts(&[("country", "US"), ("total", "150")]);
+        assert_eq!(rule.check(&info).status, Status::NotMet);
+    }
+
+    #[test]
+    fn arithmetic_and_unknown_operators() {
+        let info = facts(&[("a", "4"), ("name", "Ada")]);
+        assert_eq!(
+            evaluate(&json!({ "+": [{ "var": "a" }, 1, "2"] }), &info),
+            json!(7)
+        );
+        assert_eq!(
+            evaluate(&json!({ "/": [{ "var": "a" }, 8] }), &info),
+            json!(0.5)
+        );
+        assert_eq!(
+            evaluate(&json!({ "cat": ["Hi ", { "var": "name" }] }), &info),
+            json!("Hi Ada")
+        );
+        assert_eq!(
+            evaluate(
+                &json!({ "if": [false, "a", { "var": "missing" }, "b", "c"] }),
+                &info
+            ),
+            json!("c")
+        );
+        assert_eq!(evaluate(&json!({ "nope": [1] }), &info), Value::Null);
+    }
+}
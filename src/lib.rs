@@ -1,9 +1,26 @@
-use crate::constraint::Constraint;
+#[cfg(feature = "hash")]
+use crate::constraint::HashAlg;
+use crate::constraint::{Constraint, NumberLocale, Op, ValueType};
 use crate::rule::Rule;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead};
 
 pub mod constraint;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod diff;
+pub mod facts;
+#[cfg(feature = "json")]
+pub mod json_logic;
+pub mod phf;
+#[cfg(feature = "prost")]
+pub mod protobuf;
 pub mod rule;
+pub mod stateful;
 pub mod status;
+pub mod trie;
+#[cfg(feature = "watch")]
+pub mod watch;
 pub use crate::status::Status;
 
 /// Creates a `Rule` where all child `Rule`s must be `Met`
@@ -33,6 +50,77 @@ pub fn n_of(n: usize, rules: Vec<Rule>) -> Rule {
     Rule::NumberOf { n, rules }
 }
 
+/// Creates a `Rule` where more than half of the child `Rule`s must be `Met`
+///
+/// * Same semantics as `n_of(rules.len() / 2 + 1, rules)`
+/// * A tie between `Met` and `NotMet` children is not a majority, the result is `NotMet`
+pub fn majority(rules: Vec<Rule>) -> Rule {
+    Rule::Majority { rules }
+}
+
+/// Creates a `Rule` of ordered steps, e.g. prerequisites of an achievement
+///
+/// * The status is the same as for `and()`
+/// * `RuleResult::progress` counts the leading steps that are `Met`, so a chain whose
+///   third step fails has a progress of 2
+pub fn chain(rules: Vec<Rule>) -> Rule {
+    Rule::Chain { rules }
+}
+
+/// Creates a `Rule` taking the status of `primary`, falling back to `fallback`
+///
+/// * If `primary` is `Met` or `NotMet`, the result is the same
+/// * If `primary` is `Unknown`, e.g. for lack of data, the result is that of `fallback`
+pub fn coalesce(primary: Rule, fallback: Rule) -> Rule {
+    Rule::Coalesce {
+        primary: Box::new(primary),
+        fallback: Box::new(fallback),
+    }
+}
+
+/// Creates a `Rule` with the opposite status of `rule`
+///
+/// * `Met` becomes `NotMet` and `NotMet` becomes `Met`
+/// * `Unknown` stays `Unknown`
+pub fn not(rule: Rule) -> Rule {
+    Rule::Not {
+        rule: Box::new(rule),
+    }
+}
+
+/// Names `rule` with `id`, so `depends_on()` rules elsewhere in the tree can refer to it
+pub fn with_id(id: &str, rule: Rule) -> Rule {
+    Rule::WithId {
+        id: id.into(),
+        rule: Box::new(rule),
+    }
+}
+
+/// Creates a `Rule` that only counts if the rule named `rule_id` has status `expect`
+///
+/// * If the named rule is `expect`, the result is that of `then`
+/// * If it is `Unknown`, the result is `Unknown`
+/// * Otherwise the result is `NotMet`
+pub fn depends_on(rule_id: &str, expect: Status, then: Rule) -> Rule {
+    Rule::DependsOn {
+        rule_id: rule_id.into(),
+        expect,
+        then: Box::new(then),
+    }
+}
+
+/// Creates a first-match table of labeled `Rule`s, see `Rule::first_match()`
+///
+/// Checked as a whole, the result is the same as for `or()`
+pub fn rule_set(rules: Vec<(&str, Rule)>) -> Rule {
+    Rule::RuleSet {
+        rules: rules
+            .into_iter()
+            .map(|(label, rule)| (label.into(), rule))
+            .collect(),
+    }
+}
+
 /// Creates a rule for string comparison
 pub fn string_equals(description: &str, field: &str, val: &str) -> Rule {
     Rule::Rule {
@@ -74,3 +162,992 @@ pub fn boolean(description: &str, field: &str, val: bool) -> Rule {
         constraint: Constraint::Boolean(val),
     }
 }
+
+/// Creates a rule checking the value is one of the given variant names.
+///
+/// The comparison is case-sensitive
+pub fn in_enum_variants(description: &str, field: &str, variants: &[&str]) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::InEnumVariants(variants.iter().map(|&v| v.into()).collect()),
+    }
+}
+
+/// Creates a rule checking the value is the name of one of the variants of `E`.
+///
+/// Variant names are taken from `AsRef<str>`, so they follow any `strum` renaming
+/// applied to the enum. The comparison is case-sensitive
+#[cfg(feature = "strum")]
+pub fn in_enum<E>(description: &str, field: &str) -> Rule
+where
+    E: strum::IntoEnumIterator + AsRef<str>,
+{
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::from_enum_variants::<E>(),
+    }
+}
+
+/// Creates a rule checking the value is one of the lines read from `reader`, e.g.
+/// an allow-list file.
+///
+/// Each line is trimmed of surrounding whitespace and empty lines are skipped, so
+/// neither trailing spaces nor a final newline can sneak in a stray candidate
+pub fn one_of_from_reader<R: BufRead>(
+    description: &str,
+    field: &str,
+    reader: R,
+) -> io::Result<Rule> {
+    let mut values = BTreeSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() {
+            values.insert(line.to_owned());
+        }
+    }
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::OneOf(values),
+    })
+}
+
+/// Creates a rule checking the value against allow and deny lists, deny winning.
+///
+/// Denied values are `NotMet`, allowed ones `Met`, and values in neither list
+/// result in `default`
+pub fn allow_deny(
+    description: &str,
+    field: &str,
+    allow: &[&str],
+    deny: &[&str],
+    default: Status,
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::AllowDeny {
+            allow: allow.iter().map(|&v| v.into()).collect(),
+            deny: deny.iter().map(|&v| v.into()).collect(),
+            default,
+        },
+    }
+}
+
+/// Creates a rule that is `Met` if the value was not seen in the last `n` checks.
+///
+/// Only meaningful when checked through a [`HistoryEvaluator`][1]
+///
+/// [1]: crate::stateful::HistoryEvaluator
+pub fn not_recently_seen(description: &str, field: &str, n: usize) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::NotRecentlySeen(n),
+    }
+}
+
+/// Creates a rule that is `Met` while the value occurred fewer than `limit` times
+/// in the current time window of length `window`.
+///
+/// Only meaningful when checked through a [`RateLimitEvaluator`][1]. Fails if
+/// `window` is zero
+///
+/// [1]: crate::stateful::RateLimitEvaluator
+pub fn under_rate_limit(
+    description: &str,
+    field: &str,
+    limit: usize,
+    window: std::time::Duration,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::UnderRateLimit { limit, window };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the value starts with one of `prefixes`.
+///
+/// The prefixes are stored in a `Trie` built once here, so checking stays fast
+/// for very large prefix lists
+pub fn prefix_in_trie(description: &str, field: &str, prefixes: &[&str]) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::PrefixInTrie(prefixes.iter().collect()),
+    }
+}
+
+/// Creates a rule checking the value is one of `values`.
+///
+/// The values are stored in a perfect-hash `PhfSet` built once here, which keeps
+/// checks fast and memory low for very large allow-lists
+pub fn in_phf_set(description: &str, field: &str, values: &[&str]) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::InPhfSet(values.iter().collect()),
+    }
+}
+
+/// Creates a rule comparing the hash of the value against `expected_hex`.
+///
+/// Lets a rule match a secret value without storing it in plain text
+#[cfg(feature = "hash")]
+pub fn hash_equals(description: &str, field: &str, algorithm: HashAlg, expected_hex: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::HashEquals {
+            algorithm,
+            expected_hex: expected_hex.into(),
+        },
+    }
+}
+
+/// Creates a rule comparing the value against `target` with `op`, both parsed as `typ`.
+///
+/// If either can't be parsed as `typ`, the result is `NotMet`
+pub fn compare(description: &str, field: &str, op: Op, typ: ValueType, target: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::Compare {
+            op,
+            typ,
+            target: target.into(),
+        },
+    }
+}
+
+/// Creates a rule checking the value sorts between `start` and `end`, both
+/// inclusive. Fails if `start` sorts after `end`
+pub fn string_between(
+    description: &str,
+    field: &str,
+    start: &str,
+    end: &str,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::StringBetween(start.into(), end.into());
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule matching the value against a simple pattern.
+///
+/// `?` stands for a letter, `#` for a digit, all other characters are literal,
+/// e.g. `"??-####-??"` matches `"AB-1234-XY"`
+pub fn matches_pattern(description: &str, field: &str, pattern: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::MatchesPattern(pattern.into()),
+    }
+}
+
+/// Creates a rule checking the value is a `#RRGGBB` hex color.
+///
+/// With `allow_short` the three-digit `#RGB` form is accepted too
+pub fn is_hex_color(description: &str, field: &str, allow_short: bool) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsHexColor { allow_short },
+    }
+}
+
+/// Creates a rule checking the value, a category, is at most `max_hops` edges away
+/// from `target` in `graph`, which lists the neighbours of each category
+pub fn within_category_distance(
+    description: &str,
+    field: &str,
+    graph: &[(&str, &[&str])],
+    target: &str,
+    max_hops: usize,
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::WithinCategoryDistance {
+            graph: graph
+                .iter()
+                .map(|&(from, to)| (from.into(), to.iter().map(|&t| t.into()).collect()))
+                .collect(),
+            target: target.into(),
+            max_hops,
+        },
+    }
+}
+
+/// Creates a rule checking the value is a palindrome, ignoring case and anything
+/// but letters and digits
+pub fn is_palindrome(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsPalindrome,
+    }
+}
+
+/// Creates a rule checking the numeric value lies between `start` and `end`.
+///
+/// Each bound is inclusive or exclusive on its own, so `[0, 100)` is
+/// `range(.., 0.0, true, 100.0, false)`. Fails if `start > end`
+pub fn range(
+    description: &str,
+    field: &str,
+    start: f64,
+    start_incl: bool,
+    end: f64,
+    end_incl: bool,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::Range {
+        start,
+        start_incl,
+        end,
+        end_incl,
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the summed weight of the `weights` keywords found in the
+/// value reaches `threshold`.
+///
+/// Keywords match as substrings and count once each, e.g. with
+/// `[("free", 2.0), ("winner", 3.0)]` and a threshold of 4 `"free winner"` is `Met`
+/// while `"free free free"` is not
+pub fn keyword_score(
+    description: &str,
+    field: &str,
+    weights: &[(&str, f64)],
+    threshold: f64,
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::KeywordScore {
+            weights: weights
+                .iter()
+                .map(|&(keyword, weight)| (keyword.into(), weight))
+                .collect(),
+            threshold,
+        },
+    }
+}
+
+/// Creates a rule checking the integer value has `digits` digits, ignoring its sign.
+///
+/// If the checked value is not convertible to an integer, the result is `NotMet`
+pub fn magnitude_equals(description: &str, field: &str, digits: u32) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::MagnitudeEquals(digits),
+    }
+}
+
+/// Creates a rule checking the decimal digits of the integer value add up to `sum`,
+/// ignoring its sign.
+///
+/// If the checked value is not an integer, the result is `NotMet`
+pub fn digit_sum_equals(description: &str, field: &str, sum: usize) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::DigitSumEquals(sum),
+    }
+}
+
+/// Creates a rule checking a length with a unit, such as `"30cm"` or `"2ft"`, is at
+/// least `meters` long.
+///
+/// If the checked value has no known unit, the result is `NotMet`
+pub fn length_at_least_meters(description: &str, field: &str, meters: f64) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::LengthAtLeastMeters(meters),
+    }
+}
+
+/// Creates a rule checking the value, a number written in `locale`'s format such as
+/// `"1.234,56"` for `NumberLocale::European`, is at least `value`.
+///
+/// If the checked value is not a number in that format, the result is `NotMet`
+pub fn number_at_least(description: &str, field: &str, value: f64, locale: NumberLocale) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::NumberAtLeast { value, locale },
+    }
+}
+
+/// Creates a rule that is `Met` if the numeric value is within
+/// `max(rel_tol * |reference|, abs_tol)` of `reference`.
+///
+/// Fails if either tolerance is negative or `NaN`
+pub fn close_to(
+    description: &str,
+    field: &str,
+    reference: f64,
+    rel_tol: f64,
+    abs_tol: f64,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::CloseTo {
+        reference,
+        rel_tol,
+        abs_tol,
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the value carries at least `bits` of entropy, e.g. for
+/// password strength.
+///
+/// See [`entropy_bits`][1] for the formula
+///
+/// [1]: crate::constraint::entropy_bits
+pub fn entropy_at_least(description: &str, field: &str, bits: f64) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::EntropyAtLeast(bits),
+    }
+}
+
+/// Creates a rule checking the value is a password of `min_len` to `max_len`
+/// characters with at least one digit, upper case letter, lower case letter and symbol.
+///
+/// To drop some of the character requirements, use `Constraint::PasswordPolicy`
+/// directly. Fails if `min_len > max_len`
+pub fn password_policy(
+    description: &str,
+    field: &str,
+    min_len: usize,
+    max_len: usize,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::PasswordPolicy {
+        min_len,
+        max_len,
+        require_digit: true,
+        require_upper: true,
+        require_lower: true,
+        require_symbol: true,
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the numeric value has at most `places` decimal places,
+/// e.g. a price with `places` of 2.
+///
+/// If the checked value is not a number, the result is `NotMet`
+pub fn max_decimal_places(description: &str, field: &str, places: usize) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::MaxDecimalPlaces(places),
+    }
+}
+
+/// Creates a rule checking the numeric value lands in the tier named `expected`.
+///
+/// `thresholds` pairs each tier name with its inclusive lower bound, e.g.
+/// `[(0.0, "bronze"), (100.0, "silver"), (500.0, "gold")]`. Values below the first
+/// bound are `NotMet`. Fails if the bounds are not strictly ascending
+pub fn tier_equals(
+    description: &str,
+    field: &str,
+    thresholds: &[(f64, &str)],
+    expected: &str,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::TierEquals {
+        thresholds: thresholds
+            .iter()
+            .map(|&(bound, name)| (bound, name.into()))
+            .collect(),
+        expected: expected.into(),
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule requiring every element of an array fact to satisfy `constraint`.
+///
+/// See [`array_elements`][1] for how arrays are written. An empty array is `Met`
+///
+/// [1]: crate::constraint::array_elements
+pub fn array_all(description: &str, field: &str, constraint: Constraint) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::ArrayAll(Box::new(constraint)),
+    }
+}
+
+/// Creates a rule requiring some element of an array fact to satisfy `constraint`.
+///
+/// See [`array_elements`][1] for how arrays are written. An empty array is `NotMet`
+///
+/// [1]: crate::constraint::array_elements
+pub fn array_any(description: &str, field: &str, constraint: Constraint) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::ArrayAny(Box::new(constraint)),
+    }
+}
+
+/// Creates a rule requiring the elements of an array fact to be distinct.
+///
+/// See [`array_elements`][1] for how arrays are written
+///
+/// [1]: crate::constraint::array_elements
+pub fn array_unique(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::ArrayUnique,
+    }
+}
+
+/// Creates a rule requiring `value` to appear at least `n` times in an array fact.
+///
+/// See [`array_elements`][1] for how arrays are written
+///
+/// [1]: crate::constraint::array_elements
+pub fn count_of_value_at_least(description: &str, field: &str, value: &str, n: usize) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::CountOfValueAtLeast {
+            value: value.into(),
+            n,
+        },
+    }
+}
+
+/// Creates a rule requiring at least `percent` percent of an array fact's elements
+/// to equal `value`, e.g. 80% of `checks` being `"pass"`.
+///
+/// See [`array_elements`][1] for how arrays are written. An empty array is `NotMet`.
+/// Fails unless `percent` is between 0 and 100
+///
+/// [1]: crate::constraint::array_elements
+pub fn array_percent_equals(
+    description: &str,
+    field: &str,
+    value: &str,
+    percent: f64,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::ArrayPercentEquals {
+        value: value.into(),
+        percent,
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking a `YYYY-MM-DD` birth date implies an age of at least `years`.
+///
+/// Age is computed against the system clock, use `Rule::check_at()` to supply
+/// another time
+#[cfg(feature = "chrono")]
+pub fn age_at_least(description: &str, field: &str, years: u32) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::AgeAtLeast(years),
+    }
+}
+
+/// Creates a rule checking a `YYYY-MM-DD` date is at most `days` business days from
+/// today, not counting weekends or any of `holidays`.
+///
+/// Today is taken from the system clock, use `Rule::check_at()` to supply another time
+#[cfg(feature = "chrono")]
+pub fn within_business_days(
+    description: &str,
+    field: &str,
+    days: u32,
+    holidays: &[chrono::NaiveDate],
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::WithinBusinessDays {
+            days,
+            holidays: holidays.iter().copied().collect(),
+        },
+    }
+}
+
+/// Creates a rule checking an RFC 3339 timestamp is fresh enough, scoring
+/// `0.5^(age / half_life)` against `min_score`.
+///
+/// Age is computed against the system clock, use `Rule::check_at()` to supply
+/// another time. Fails unless `half_life` is positive
+#[cfg(feature = "chrono")]
+pub fn freshness_at_least(
+    description: &str,
+    field: &str,
+    half_life: chrono::TimeDelta,
+    min_score: f64,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::FreshnessAtLeast {
+        half_life,
+        min_score,
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the value with the comparator registered as `name`.
+///
+/// Check it with `Rule::check_with_comparators()`, without that comparator it is `Unknown`
+pub fn named(description: &str, field: &str, name: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::Named(name.into()),
+    }
+}
+
+/// Creates a rule checking the value names another field present in the facts
+pub fn is_existing_fact_key(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsExistingFactKey,
+    }
+}
+
+/// Creates a rule checking the numeric value grew since the previous value kept in
+/// the evaluation context under `previous_key`.
+///
+/// Check it with `Rule::check_with_context()`, without a previous value it is `Unknown`
+pub fn greater_than_previous(description: &str, field: &str, previous_key: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::GreaterThanPrevious(previous_key.into()),
+    }
+}
+
+/// Creates a rule checking the numeric value is above the running average kept in
+/// the evaluation context under `average_key`.
+///
+/// Check it with `Rule::check_with_context()`, without an average it is `Unknown`
+pub fn above_context_average(description: &str, field: &str, average_key: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::AboveContextAverage(average_key.into()),
+    }
+}
+
+/// Creates a rule checking the numeric value is above the rolling median kept in
+/// the evaluation context under `median_key`.
+///
+/// Check it with `Rule::check_with_context()`, without a median it is `Unknown`
+pub fn above_context_median(description: &str, field: &str, median_key: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::AboveContextMedian(median_key.into()),
+    }
+}
+
+/// Creates a rule checking the numeric value's z-score is at most `threshold` in
+/// absolute value, with the mean and standard deviation of `field` kept in the
+/// evaluation context under `mean_<field>` and `std_<field>`.
+///
+/// Check it with `Rule::check_with_context()`, without them it is `Unknown`
+pub fn z_score_within(description: &str, field: &str, threshold: f64) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::ZScoreWithin(threshold),
+    }
+}
+
+/// Creates a rule checking the numeric value is at most `other_field * factor`,
+/// e.g. "amount <= balance * 0.5".
+///
+/// If `other_field` is missing the result is `Unknown`
+pub fn less_than_field_scaled(
+    description: &str,
+    field: &str,
+    other_field: &str,
+    factor: f64,
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::LessThanFieldScaled {
+            field: other_field.into(),
+            factor,
+        },
+    }
+}
+
+/// Creates a rule checking the value, a new state, can be reached from the state in
+/// fact `field_from` according to `transitions`, e.g. `"shipped"` from `"paid"`.
+///
+/// If `field_from` is missing the result is `Unknown`
+pub fn allowed_transition(
+    description: &str,
+    field: &str,
+    field_from: &str,
+    transitions: &[(&str, &[&str])],
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::AllowedTransition {
+            field_from: field_from.into(),
+            transitions: transitions
+                .iter()
+                .map(|&(from, to)| (from.into(), to.iter().map(|&t| t.into()).collect()))
+                .collect(),
+        },
+    }
+}
+
+/// Creates a rule checking the numeric value is on or above the piecewise-linear
+/// curve through `points`, taken at the value of `x_field`.
+///
+/// If `x_field` is missing the result is `Unknown`. Fails if `points` is empty or
+/// its x coordinates are not strictly ascending
+pub fn above_curve(
+    description: &str,
+    field: &str,
+    x_field: &str,
+    points: &[(f64, f64)],
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::AboveCurve {
+        points: points.to_vec(),
+        x_field: x_field.into(),
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the numeric value is "on": it switches on at `on` and
+/// only switches off again at `off`, keeping the previous state, `true` or `false`
+/// in fact `state_field`, in between.
+///
+/// See `Constraint::Hysteresis` for details. Fails unless `off < on`
+pub fn hysteresis(
+    description: &str,
+    field: &str,
+    on: f64,
+    off: f64,
+    state_field: &str,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::Hysteresis {
+        on,
+        off,
+        state_field: state_field.into(),
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the point at facts `lat_field` and `lon_field`, in
+/// degrees, lies within `radius_m` meters of `(center_lat, center_lon)`.
+///
+/// If either coordinate is missing the result is `Unknown`, if either is not a
+/// number it is `NotMet`
+pub fn within_radius(
+    description: &str,
+    lat_field: &str,
+    lon_field: &str,
+    center_lat: f64,
+    center_lon: f64,
+    radius_m: f64,
+) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: lat_field.into(),
+        constraint: Constraint::WithinRadius {
+            center_lat,
+            center_lon,
+            radius_m,
+            lat_field: lat_field.into(),
+            lon_field: lon_field.into(),
+        },
+    }
+}
+
+/// Creates a rule checking the value is well-formed JSON
+#[cfg(feature = "json")]
+pub fn is_valid_json(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsValidJson,
+    }
+}
+
+/// Creates a rule checking the value at a dotted `path` of a JSON fact equals `expected`.
+///
+/// See [`json_path`][1] for the path syntax
+///
+/// [1]: crate::constraint::json_path
+#[cfg(feature = "json")]
+pub fn json_path_equals(description: &str, field: &str, path: &str, expected: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::JsonPathEquals {
+            path: path.into(),
+            expected: expected.into(),
+        },
+    }
+}
+
+/// Creates a rule checking the number at a dotted `path` of a JSON fact is within
+/// `tolerance` of `target`.
+///
+/// See [`json_path`][1] for the path syntax
+///
+/// [1]: crate::constraint::json_path
+#[cfg(feature = "json")]
+pub fn json_near(description: &str, field: &str, path: &str, target: f64, tolerance: f64) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::JsonNear {
+            path: path.into(),
+            target,
+            tolerance,
+        },
+    }
+}
+
+/// Creates a rule that is `Met` if the JSON-Logic expression `logic` is truthy
+/// against the facts.
+///
+/// See [`json_logic::evaluate`][1] for the supported operators
+///
+/// [1]: crate::json_logic::evaluate
+#[cfg(feature = "json")]
+pub fn json_logic(description: &str, field: &str, logic: serde_json::Value) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::JsonLogic(logic),
+    }
+}
+
+/// Creates a rule extracting the named capture `group` of `pattern` from the value
+/// and checking it against `constraint`.
+///
+/// Fails if `pattern` is not a valid regex or has no group named `group`
+#[cfg(feature = "regex")]
+pub fn regex_capture(
+    description: &str,
+    field: &str,
+    pattern: &str,
+    group: &str,
+    constraint: Constraint,
+) -> anyhow::Result<Rule> {
+    let constraint = Constraint::RegexCapture {
+        pattern: crate::constraint::Pattern::new(pattern)?,
+        group: group.into(),
+        inner: Box::new(constraint),
+    };
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+/// Creates a rule checking the value is within `max_distance` edits of one of `values`.
+///
+/// Distance is the Levenshtein distance counted in characters
+#[cfg(feature = "fuzzy")]
+pub fn fuzzy_one_of(description: &str, field: &str, values: &[&str], max_distance: usize) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::FuzzyOneOf {
+            values: values.iter().map(|&v| v.into()).collect(),
+            max_distance,
+        },
+    }
+}
+
+/// Creates a rule checking the expression holds with the value bound to `x`, e.g.
+/// `"2 * x + 1 > 10"`.
+///
+/// Fails if `expression` does not parse
+#[cfg(feature = "expr")]
+pub fn expression(description: &str, field: &str, expression: &str) -> anyhow::Result<Rule> {
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::Expression(crate::constraint::Expr::new(expression)?),
+    })
+}
+
+/// Creates a rule checking the value is a valid cron expression, with a leading
+/// seconds field
+#[cfg(feature = "cron")]
+pub fn is_cron(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsCron,
+    }
+}
+
+/// Creates a rule checking the value is a cron schedule that includes the current time.
+///
+/// The time is taken from the system clock, use `Rule::check_at()` to supply
+/// another time
+#[cfg(feature = "cron")]
+pub fn cron_matches_now(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::CronMatchesNow,
+    }
+}
+
+/// Creates a rule checking every character of the value belongs to `script`, e.g.
+/// `"Latin"`, besides characters common to all scripts such as digits and spaces
+#[cfg(feature = "unicode")]
+pub fn only_script(description: &str, field: &str, script: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::OnlyScript(script.into()),
+    }
+}
+
+/// Creates a rule checking the value consists of ASCII characters only
+#[cfg(feature = "unicode")]
+pub fn is_ascii(description: &str, field: &str) -> Rule {
+    Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint: Constraint::IsAscii,
+    }
+}
+
+/// Creates a rule checking the value is a ULID created between `start` and `end`,
+/// two RFC 3339 timestamps, e.g. to filter events by id.
+///
+/// Fails if either bound is malformed or `start` is after `end`
+#[cfg(feature = "ulid")]
+pub fn ulid_timestamp_in_range(
+    description: &str,
+    field: &str,
+    start: &str,
+    end: &str,
+) -> anyhow::Result<Rule> {
+    let parse = |bound: &str| -> anyhow::Result<_> {
+        Ok(chrono::DateTime::parse_from_rfc3339(bound)?.with_timezone(&chrono::Utc))
+    };
+    let constraint = Constraint::UlidTimestampInRange(parse(start)?, parse(end)?);
+    constraint.validate()?;
+    Ok(Rule::Rule {
+        desc: description.into(),
+        field: field.into(),
+        constraint,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn one_of_from_reader_trims_lines_and_skips_empty_ones() {
+        let allow_list = "alice\n  bob  \n\n\t\ncarol\r\n".as_bytes();
+        let rule = one_of_from_reader("allowed user", "user", allow_list).unwrap();
+        match rule {
+            Rule::Rule { ref constraint, .. } => assert_eq!(
+                *constraint,
+                Constraint::OneOf(["alice", "bob", "carol"].map(String::from).into())
+            ),
+            _ => panic!("expected a leaf"),
+        }
+        let user = |name: &str| BTreeMap::from([("user".to_owned(), name.to_owned())]);
+        assert_eq!(rule.check(&user("bob")).status, Status::Met);
+        assert_eq!(rule.check(&user("carol")).status, Status::Met);
+        assert_eq!(rule.check(&user("  bob  ")).status, Status::NotMet);
+        assert_eq!(rule.check(&user("")).status, Status::NotMet);
+        assert_eq!(rule.check(&user("dave")).status, Status::NotMet);
+    }
+
+    #[test]
+    fn one_of_from_reader_fails_on_invalid_utf8() {
+        let bytes: &[u8] = b"alice\n\xff\n";
+        assert!(one_of_from_reader("allowed user", "user", bytes).is_err());
+    }
+
+    #[cfg(feature = "expr")]
+    #[test]
+    fn expression_satisfied_unsatisfied_and_parse_error() {
+        let rule = expression("big enough", "x", "2 * x + 1 > 10").unwrap();
+        let x = |val: &str| BTreeMap::from([("x".to_owned(), val.to_owned())]);
+        assert_eq!(rule.check(&x("5")).status, Status::Met);
+        assert_eq!(rule.check(&x("4.5")).status, Status::NotMet);
+        assert_eq!(rule.check(&x("four")).status, Status::NotMet);
+        assert_eq!(rule.check(&BTreeMap::new()).status, Status::Unknown);
+        let word = expression("is yes", "x", r#"x == "yes""#).unwrap();
+        assert_eq!(word.check(&x("yes")).status, Status::Met);
+        assert!(expression("broken", "x", "2 * (x + 1").is_err());
+        assert!(expression("unbalanced", "x", "x + 1)").is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+
+/// Average number of keys per bucket while building, trading build time for size
+const LAMBDA: usize = 5;
+
+/// Immutable set of strings behind a perfect hash function, used for membership
+/// tests against large static allow-lists.
+///
+/// Built with the hash-and-displace scheme: keys are hashed into buckets, and each
+/// bucket gets a displacement sending its keys to distinct slots of a table holding
+/// exactly one key per slot. A lookup is one hash, one displacement and one string
+/// comparison, and besides the keys only two integers per bucket are stored.
+///
+/// Building takes a while for big sets, so build once, e.g. when constructing the
+/// rule, and keep or serialize the result; the hash is stable across processes.
+/// Deserializing checks every key still hashes to its own slot, rejecting sets
+/// that were edited or truncated
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawPhfSet")]
+pub struct PhfSet {
+    seed: u64,
+    displacements: Vec<(u32, u32)>,
+    keys: Vec<String>,
+}
+
+/// `PhfSet` as serialized, before its table is checked
+#[derive(Deserialize)]
+struct RawPhfSet {
+    seed: u64,
+    displacements: Vec<(u32, u32)>,
+    keys: Vec<String>,
+}
+
+impl TryFrom<RawPhfSet> for PhfSet {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawPhfSet) -> anyhow::Result<Self> {
+        let set = PhfSet {
+            seed: raw.seed,
+            displacements: raw.displacements,
+            keys: raw.keys,
+        };
+        ensure!(
+            set.keys.is_empty() || !set.displacements.is_empty(),
+            "perfect hash set has keys but no displacements"
+        );
+        for (i, key) in set.keys.iter().enumerate() {
+            ensure!(
+                set.slot_of(key) == Some(i),
+                "perfect hash set doesn't place {:?} in its slot",
+                key
+            );
+        }
+        Ok(set)
+    }
+}
+
+impl PhfSet {
+    /// Whether `val` is one of the stored keys
+    pub fn contains(&self, val: &str) -> bool {
+        self.slot_of(val).is_some_and(|slot| self.keys[slot] == val)
+    }
+
+    /// Slot `val` would be stored in, `None` for an empty set
+    fn slot_of(&self, val: &str) -> Option<usize> {
+        if self.displacements.is_empty() || self.keys.is_empty() {
+            return None;
+        }
+        let hashes = Hashes::of(val, self.seed);
+        let (d1, d2) = self.displacements[hashes.g as usize % self.displacements.len()];
+        Some(hashes.slot(d1, d2, self.keys.len()))
+    }
+
+    /// Number of stored keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Set of the distinct `keys` for hashes seeded with `seed`, `None` if some
+    /// bucket can't be placed and another seed has to be tried
+    fn place(keys: &[String], seed: u64) -> Option<PhfSet> {
+        let n = keys.len();
+        let hashes = keys
+            .iter()
+            .map(|key| Hashes::of(key, seed))
+            .collect::<Vec<_>>();
+        let mut buckets = vec![Vec::new(); n.div_ceil(LAMBDA)];
+        let buckets_len = buckets.len();
+        for (i, hashes) in hashes.iter().enumerate() {
+            buckets[hashes.g as usize % buckets_len].push(i);
+        }
+        let mut order = (0..buckets_len).collect::<Vec<_>>();
+        order.sort_by_key(|&b| Reverse(buckets[b].len()));
+
+        let mut slots = vec![None; n];
+        let mut displacements = vec![(0, 0); buckets_len];
+        let mut placed = Vec::new();
+        'buckets: for b in order {
+            for d1 in 0..n as u32 {
+                for d2 in 0..n as u32 {
+                    placed.clear();
+                    let fits = buckets[b].iter().all(|&k| {
+                        let slot = hashes[k].slot(d1, d2, n);
+                        let free = slots[slot].is_none() && !placed.iter().any(|&(s, _)| s == slot);
+                        placed.push((slot, k));
+                        free
+                    });
+                    if fits {
+                        for &(slot, k) in &placed {
+                            slots[slot] = Some(k);
+                        }
+                        displacements[b] = (d1, d2);
+                        continue 'buckets;
+                    }
+                }
+            }
+            return None;
+        }
+        Some(PhfSet {
+            seed,
+            displacements,
+            keys: slots
+                .into_iter()
+                .flatten()
+                .map(|k| keys[k].clone())
+                .collect(),
+        })
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for PhfSet {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let keys = iter
+            .into_iter()
+            .map(|key| key.as_ref().to_owned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return PhfSet::default();
+        }
+        (0..)
+            .find_map(|seed| PhfSet::place(&keys, seed))
+            .expect("some seed places every key")
+    }
+}
+
+/// Bucket hash `g` and the two slot hashes of a key
+struct Hashes {
+    g: u32,
+    f1: u32,
+    f2: u32,
+}
+
+impl Hashes {
+    fn of(key: &str, seed: u64) -> Self {
+        // FNV-1a, then a splitmix64 round per output word
+        let mut h = 0xcbf2_9ce4_8422_2325 ^ seed;
+        for &b in key.as_bytes() {
+            h = (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        let h1 = mix(h);
+        let h2 = mix(h1);
+        Hashes {
+            g: (h1 >> 32) as u32,
+            f1: h1 as u32,
+            f2: h2 as u32,
+        }
+    }
+
+    fn slot(&self, d1: u32, d2: u32, n: usize) -> usize {
+        let slot = u64::from(self.f2) + u64::from(self.f1) * u64::from(d1) + u64::from(d2);
+        (slot % n as u64) as usize
+    }
+}
+
+fn mix(mut h: u64) -> u64 {
+    h = h.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn membership_and_non_membership() {
+        let set = (0..2000).map(|i| format!("user-{}", i)).collect::<PhfSet>();
+        assert_eq!(set.len(), 2000);
+        for i in 0..2000 {
+            assert!(set.contains(&format!("user-{}", i)), "user-{}", i);
+        }
+        for i in 2000..4000 {
+            assert!(!set.contains(&format!("user-{}", i)), "user-{}", i);
+        }
+        assert!(!set.contains(""));
+        assert!(!set.contains("USER-1"));
+    }
+
+    #[test]
+    fn duplicates_empty_and_round_trip() {
+        let set = ["b", "a", "b", "c"].into_iter().collect::<PhfSet>();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains("a") && set.contains("b") && set.contains("c"));
+        assert!(!set.contains("d"));
+
+        let empty = Vec::<String>::new().into_iter().collect::<PhfSet>();
+        assert!(empty.is_empty());
+        assert!(!empty.contains(""));
+
+        let json = serde_json::to_string(&set).unwrap();
+        let parsed: PhfSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, set);
+        assert!(parsed.contains("c"));
+    }
+
+    #[test]
+    fn in_phf_set_constraint() {
+        let rule = crate::in_phf_set("allowed country", "country", &["NL", "DE", "FR"]);
+        let country =
+            |c: &str| std::collections::BTreeMap::from([("country".to_owned(), c.to_owned())]);
+        assert_eq!(rule.check(&country("DE")).status, crate::Status::Met);
+        assert_eq!(rule.check(&country("US")).status, crate::Status::NotMet);
+        assert_eq!(rule.check(&country("de")).status, crate::Status::NotMet);
+    }
+
+    #[test]
+    fn deserializing_rejects_inconsistent_tables() {
+        let set = (0..50).map(|i| format!("user-{}", i)).collect::<PhfSet>();
+        let tampered = |edit: fn(&mut PhfSet)| {
+            let mut copy = set.clone();
+            edit(&mut copy);
+            serde_json::from_str::<PhfSet>(&serde_json::to_string(&copy).unwrap())
+        };
+        assert!(tampered(|_| {}).is_ok());
+        assert!(tampered(|s| s.seed += 1).is_err());
+        assert!(tampered(|s| s.keys.swap(0, 1)).is_err());
+        assert!(tampered(|s| s.keys.truncate(49)).is_err());
+        assert!(tampered(|s| s.keys[3] = s.keys[4].clone()).is_err());
+        assert!(tampered(|s| s.displacements.clear()).is_err());
+        assert!(tampered(|s| s.displacements[0] = (7, 11)).is_err());
+        assert!(tampered(|s| *s = PhfSet::default()).is_ok());
+    }
+}
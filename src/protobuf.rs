@@ -0,0 +1,321 @@
+use prost_reflect::{DynamicMessage, Kind, MapKey, ReflectMessage, Value};
+use std::collections::BTreeMap;
+
+/// Flattens a decoded protobuf message into a facts map keyed by field name.
+///
+/// * Scalars map to their text form, enums to the name of their value
+/// * Nested messages are flattened with dotted names, e.g. `address.city`
+/// * Repeated scalars are joined with commas, matching how [array facts][1] are
+///   written. Repeated messages are flattened per element, e.g. `items.0.sku`
+/// * Map entries become `field.key`
+/// * Fields with explicit presence (messages, `optional` scalars) are left out
+///   when unset, so rules on them are `Unknown`. Other fields are always present
+///   and carry their default value when unset
+///
+/// [1]: crate::constraint::array_elements
+pub fn facts_from_message<M: ReflectMessage>(message: &M) -> BTreeMap<String, String> {
+    let mut facts = BTreeMap::new();
+    flatten_message(&message.transcode_to_dynamic(), "", &mut facts);
+    facts
+}
+
+fn flatten_message(message: &DynamicMessage, prefix: &str, facts: &mut BTreeMap<String, String>) {
+    for field in message.descriptor().fields() {
+        if field.supports_presence() && !message.has_field(&field) {
+            continue;
+        }
+        let name = format!("{}{}", prefix, field.name());
+        flatten_value(&field.kind(), &message.get_field(&field), name, facts);
+    }
+}
+
+fn flatten_value(kind: &Kind, value: &Value, name: String, facts: &mut BTreeMap<String, String>) {
+    match *value {
+        Value::Message(ref message) => flatten_message(message, &format!("{}.", name), facts),
+        Value::List(ref items) if matches!(kind, Kind::Message(_)) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_value(kind, item, format!("{}.{}", name, i), facts);
+            }
+        }
+        Value::List(ref items) => {
+            let joined = items
+                .iter()
+                .filter_map(|item| scalar(kind, item))
+                .collect::<Vec<_>>()
+                .join(",");
+            facts.insert(name, joined);
+        }
+        Value::Map(ref entries) => {
+            let value_kind = match kind.as_message() {
+                Some(entry) => entry.map_entry_value_field().kind(),
+                None => return,
+            };
+            for (key, val) in entries {
+                flatten_value(
+                    &value_kind,
+                    val,
+                    format!("{}.{}", name, map_key(key)),
+                    facts,
+                );
+            }
+        }
+        _ => {
+            if let Some(s) = scalar(kind, value) {
+                facts.insert(name, s);
+            }
+        }
+    }
+}
+
+fn scalar(kind: &Kind, value: &Value) -> Option<String> {
+    Some(match *value {
+        Value::Bool(b) => b.to_string(),
+        Value::I32(i) => i.to_string(),
+        Value::I64(i) => i.to_string(),
+        Value::U32(u) => u.to_string(),
+        Value::U64(u) => u.to_string(),
+        Value::F32(f) => f.to_string(),
+        Value::F64(f) => f.to_string(),
+        Value::String(ref s) => s.clone(),
+        Value::Bytes(ref b) => String::from_utf8_lossy(b).into_owned(),
+        Value::EnumNumber(n) => match kind.as_enum().and_then(|e| e.get_value(n)) {
+            Some(v) => v.name().to_owned(),
+            None => n.to_string(),
+        },
+        Value::Message(_) | Value::List(_) | Value::Map(_) => return None,
+    })
+}
+
+fn map_key(key: &MapKey) -> String {
+    match *key {
+        MapKey::Bool(b) => b.to_string(),
+        MapKey::I32(i) => i.to_string(),
+        MapKey::I64(i) => i.to_string(),
+        MapKey::U32(u) => u.to_string(),
+        MapKey::U64(u) => u.to_string(),
+        MapKey::String(ref s) => s.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::Constraint;
+    use crate::status::Status;
+    use prost_reflect::prost_types::field_descriptor_proto::{Label, Type};
+    use prost_reflect::prost_types::{
+        DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+        FileDescriptorProto, MessageOptions, OneofDescriptorProto,
+    };
+    use prost_reflect::DescriptorPool;
+    use std::collections::HashMap;
+
+    fn field(name: &str, number: i32, typ: Type, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.into()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(typ as i32),
+            type_name: type_name.map(Into::into),
+            ..Default::default()
+        }
+    }
+
+    fn repeated(field: FieldDescriptorProto) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            label: Some(Label::Repeated as i32),
+            ..field
+        }
+    }
+
+    fn message(name: &str, fields: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.into()),
+            field: fields,
+            ..Default::default()
+        }
+    }
+
+    /// Descriptor pool for, in proto3:
+    ///
+    /// ```text
+    /// enum State { NEW = 0; PAID = 1; }
+    /// message Address { string city = 1; }
+    /// message Item { string sku = 1; int32 qty = 2; }
+    /// message Order {
+    ///   string id = 1;
+    ///   int64 total = 2;
+    ///   Address address = 3;
+    ///   repeated string tags = 4;
+    ///   repeated Item items = 5;
+    ///   State state = 6;
+    ///   map<string, string> labels = 7;
+    ///   optional string note = 8;
+    /// }
+    /// ```
+    fn pool() -> DescriptorPool {
+        let mut note = field("note", 8, Type::String, None);
+        note.oneof_index = Some(0);
+        note.proto3_optional = Some(true);
+        let mut labels_entry = message(
+            "LabelsEntry",
+            vec![
+                field("key", 1, Type::String, None),
+                field("value", 2, Type::String, None),
+            ],
+        );
+        labels_entry.options = Some(MessageOptions {
+            map_entry: Some(true),
+            ..Default::default()
+        });
+        let mut order = message(
+            "Order",
+            vec![
+                field("id", 1, Type::String, None),
+                field("total", 2, Type::Int64, None),
+                field("address", 3, Type::Message, Some(".shop.Address")),
+                repeated(field("tags", 4, Type::String, None)),
+                repeated(field("items", 5, Type::Message, Some(".shop.Item"))),
+                field("state", 6, Type::Enum, Some(".shop.State")),
+                repeated(field(
+                    "labels",
+                    7,
+                    Type::Message,
+                    Some(".shop.Order.LabelsEntry"),
+                )),
+                note,
+            ],
+        );
+        order.nested_type = vec![labels_entry];
+        order.oneof_decl = vec![OneofDescriptorProto {
+            name: Some("_note".into()),
+            ..Default::default()
+        }];
+        let state = EnumDescriptorProto {
+            name: Some("State".into()),
+            value: ["NEW", "PAID"]
+                .iter()
+                .enumerate()
+                .map(|(i, &name)| EnumValueDescriptorProto {
+                    name: Some(name.into()),
+                    number: Some(i as i32),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let file = FileDescriptorProto {
+            name: Some("shop.proto".into()),
+            package: Some("shop".into()),
+            syntax: Some("proto3".into()),
+            message_type: vec![
+                message("Address", vec![field("city", 1, Type::String, None)]),
+                message(
+                    "Item",
+                    vec![
+                        field("sku", 1, Type::String, None),
+                        field("qty", 2, Type::Int32, None),
+                    ],
+                ),
+                order,
+            ],
+            enum_type: vec![state],
+            ..Default::default()
+        };
+        DescriptorPool::from_file_descriptor_set(prost_reflect::prost_types::FileDescriptorSet {
+            file: vec![file],
+        })
+        .unwrap()
+    }
+
+    fn sample_order(pool: &DescriptorPool) -> DynamicMessage {
+        let mut address = DynamicMessage::new(pool.get_message_by_name("shop.Address").unwrap());
+        address.set_field_by_name("city", Value::String("Wellington".into()));
+        let item = |sku: &str, qty| {
+            let mut item = DynamicMessage::new(pool.get_message_by_name("shop.Item").unwrap());
+            item.set_field_by_name("sku", Value::String(sku.into()));
+            item.set_field_by_name("qty", Value::I32(qty));
+            Value::Message(item)
+        };
+        let mut order = DynamicMessage::new(pool.get_message_by_name("shop.Order").unwrap());
+        order.set_field_by_name("id", Value::String("o-1".into()));
+        order.set_field_by_name("total", Value::I64(4200));
+        order.set_field_by_name("address", Value::Message(address));
+        order.set_field_by_name(
+            "tags",
+            Value::List(vec![
+                Value::String("gift".into()),
+                Value::String("rush".into()),
+            ]),
+        );
+        order.set_field_by_name("items", Value::List(vec![item("A1", 2), item("B2", 1)]));
+        order.set_field_by_name("state", Value::EnumNumber(1));
+        order.set_field_by_name(
+            "labels",
+            Value::Map(HashMap::from([(
+                MapKey::String("channel".into()),
+                Value::String("web".into()),
+            )])),
+        );
+        order
+    }
+
+    #[test]
+    fn flattens_nested_repeated_and_map_fields() {
+        let pool = pool();
+        let facts = facts_from_message(&sample_order(&pool));
+        let expected = [
+            ("address.city", "Wellington"),
+            ("id", "o-1"),
+            ("items.0.qty", "2"),
+            ("items.0.sku", "A1"),
+            ("items.1.qty", "1"),
+            ("items.1.sku", "B2"),
+            ("labels.channel", "web"),
+            ("state", "PAID"),
+            ("tags", "gift,rush"),
+            ("total", "4200"),
+        ]
+        .iter()
+        .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+        .collect::<BTreeMap<_, _>>();
+        assert_eq!(facts, expected);
+
+        // unset fields without presence carry their defaults, unset messages are left out
+        let empty = DynamicMessage::new(pool.get_message_by_name("shop.Order").unwrap());
+        let facts = facts_from_message(&empty);
+        assert_eq!(facts.get("total").map(String::as_str), Some("0"));
+        assert_eq!(facts.get("state").map(String::as_str), Some("NEW"));
+        assert!(!facts.contains_key("address.city"));
+        assert!(!facts.contains_key("note"));
+    }
+
+    #[test]
+    fn rule_tree_evaluates_against_message() {
+        let rule = crate::and(vec![
+            crate::string_equals("paid", "state", "PAID"),
+            crate::string_equals("local", "address.city", "Wellington"),
+            crate::int_range("big enough", "total", 1000, 100_000),
+            crate::array_any("rush", "tags", Constraint::StringEquals("rush".into())),
+            crate::int_range("first item", "items.0.qty", 1, 10),
+        ]);
+        let pool = pool();
+        let mut order = sample_order(&pool);
+        assert_eq!(rule.check(&facts_from_message(&order)).status, Status::Met);
+
+        order.set_field_by_name("total", Value::I64(5));
+        assert_eq!(
+            rule.check(&facts_from_message(&order)).status,
+            Status::NotMet
+        );
+
+        order.clear_field_by_name("address");
+        let note = crate::string_equals("note", "note", "leave at door");
+        assert_eq!(
+            note.check(&facts_from_message(&order)).status,
+            Status::Unknown
+        );
+        order.set_field_by_name("note", Value::String("leave at door".into()));
+        assert_eq!(note.check(&facts_from_message(&order)).status, Status::Met);
+    }
+}
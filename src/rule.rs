@@ -1,7 +1,19 @@
+use crate::facts::FactSource;
+use crate::stateful::RateCounters;
 use crate::status::Status;
 use crate::Constraint;
+use anyhow::{bail, ensure};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::hash::Hasher;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 /// Representation of a node in the rules tree
 ///
@@ -9,7 +21,7 @@ use std::collections::BTreeMap;
 /// to construct the rules tree use the [convenience functions][1] in the module root.
 ///
 /// [1]: index.html#functions
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Rule {
     And {
         rules: Vec<Rule>,
@@ -21,10 +33,61 @@ pub enum Rule {
         n: usize,
         rules: Vec<Rule>,
     },
+    /// `NumberOf` with `n` being more than half of the children, i.e. `rules.len() / 2 + 1`
+    Majority {
+        rules: Vec<Rule>,
+    },
+    /// Ordered, labeled rules where the first `Met` one wins, see `Rule::first_match()`.
+    ///
+    /// Checked as a whole it behaves like `Or`
+    RuleSet {
+        rules: Vec<(String, Rule)>,
+    },
+    /// Ordered steps, e.g. of an achievement, checked like `And`.
+    ///
+    /// The result also records in `RuleResult::progress` how many leading steps are
+    /// `Met` before the first one that isn't
+    Chain {
+        rules: Vec<Rule>,
+    },
+    /// The status of `primary`, or of `fallback` when `primary` is `Unknown`, e.g. to
+    /// apply a default policy when data is missing
+    Coalesce {
+        primary: Box<Rule>,
+        fallback: Box<Rule>,
+    },
+    /// `Met` if `rule` is `NotMet` and the other way around, `Unknown` stays `Unknown`
+    Not {
+        rule: Box<Rule>,
+    },
+    /// Names `rule` so a `DependsOn` elsewhere in the tree can refer to it.
+    ///
+    /// Checked as a whole it has the status of `rule`
+    WithId {
+        id: String,
+        rule: Box<Rule>,
+    },
+    /// `then`, gated on the status of the rule named `rule_id` by a `WithId` in the
+    /// same tree.
+    ///
+    /// The referenced rule is evaluated first. If its status is `expect`, the result
+    /// is that of `then`; if it is `Unknown` the result is `Unknown`, otherwise
+    /// `NotMet`. A missing id, or one whose rule depends on itself, is `Unknown`, see
+    /// `Rule::check_ids()`
+    DependsOn {
+        rule_id: String,
+        expect: Status,
+        then: Box<Rule>,
+    },
+    /// Constant that is always `Met`, see `Rule::partial_eval()`
+    Always,
+    /// Constant that is never `Met`, see `Rule::partial_eval()`
+    Never,
     // Rule(Description, Field, Constraint)
     Rule {
         desc: String,
         field: String,
+        #[serde(deserialize_with = "crate::constraint::deserialize_valid")]
         constraint: Constraint,
     },
 }
@@ -33,94 +96,3935 @@ impl Rule {
     /// Starting at this node, recursively check (depth-first) any child nodes and
     /// aggregate the results
     pub fn check(&self, info: &BTreeMap<String, String>) -> RuleResult {
+        self.eval(&Env::new(self, info))
+    }
+
+    /// Like `check()`, but looks facts up in any [`FactSource`], such as a database
+    /// row wrapped in [`RowFacts`][1].
+    ///
+    /// [1]: crate::facts::RowFacts
+    pub fn check_facts(&self, facts: &dyn FactSource) -> RuleResult {
+        self.eval(&Env::new(self, facts))
+    }
+
+    /// Like `check()`, with `context` holding values that are not facts about the
+    /// subject, such as previous readings used by `Constraint::GreaterThanPrevious`
+    pub fn check_with_context(
+        &self,
+        info: &BTreeMap<String, String>,
+        context: &BTreeMap<String, String>,
+    ) -> RuleResult {
+        let env = Env {
+            context: Some(context),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, attaching `metadata` such as a correlation id to the root
+    /// result's `RuleResult::metadata` for downstream consumers
+    pub fn check_with_metadata(
+        &self,
+        info: &BTreeMap<String, String>,
+        metadata: BTreeMap<String, String>,
+    ) -> RuleResult {
+        RuleResult {
+            metadata: Some(metadata),
+            ..self.check(info)
+        }
+    }
+
+    /// Like `check()`, but only leaves checking one of `fields` are evaluated, the
+    /// others are `Unknown`.
+    ///
+    /// Handy for re-checking just the part of a tree affected by changed facts
+    pub fn check_fields(
+        &self,
+        info: &BTreeMap<String, String>,
+        fields: &BTreeSet<String>,
+    ) -> RuleResult {
+        let env = Env {
+            fields: Some(fields),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, with `comparators` supplying the logic of
+    /// `Constraint::Named` leaves by name
+    pub fn check_with_comparators(
+        &self,
+        info: &BTreeMap<String, String>,
+        comparators: &BTreeMap<String, Comparator>,
+    ) -> RuleResult {
+        let env = Env {
+            comparators: Some(comparators),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, but gives up once `cancel` is set.
+    ///
+    /// The flag is polled before each node is evaluated. After it is set, all
+    /// remaining nodes resolve to `Unknown` without being evaluated, which keeps
+    /// long evaluations responsive to shutdowns and timeouts
+    pub fn check_cancellable(
+        &self,
+        info: &BTreeMap<String, String>,
+        cancel: &AtomicBool,
+    ) -> RuleResult {
+        let env = Env {
+            cancel: Some(cancel),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, but identical subtrees are only evaluated once per call.
+    ///
+    /// Identical subtrees are found by a single bottom-up pass over the tree before
+    /// checking, so this only pays off when the same sub-rule appears more than once
+    /// in the tree; otherwise that pass is pure overhead and `check()` should be
+    /// preferred
+    pub fn check_memoized(&self, info: &BTreeMap<String, String>) -> RuleResult {
+        let env = Env {
+            memo: Some(Memo::new(self)),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, but each combinator's result explains its status in
+    /// `RuleResult::reason`
+    pub fn check_verbose(&self, info: &BTreeMap<String, String>) -> RuleResult {
+        let env = Env {
+            verbose: true,
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, but records how long each node took in `RuleResult::elapsed`
+    pub fn check_profiled(&self, info: &BTreeMap<String, String>) -> RuleResult {
+        let env = Env {
+            profile: true,
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Like `check()`, but every node is evaluated inside a `tracing` span named
+    /// `rule`, nested like the tree, with the node's `name` and `kind` and the
+    /// resulting `status` as fields.
+    ///
+    /// Install a subscriber such as `tracing-opentelemetry` to export the spans to
+    /// OpenTelemetry
+    #[cfg(feature = "otel")]
+    pub fn check_instrumented(&self, info: &BTreeMap<String, String>) -> RuleResult {
+        let env = Env {
+            spans: true,
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Checks the direct children of this node concurrently on Tokio's blocking
+    /// thread pool, streaming `(path, status)` for every node as it resolves, `path`
+    /// being the child indices from this node.
+    ///
+    /// The nodes of each child's subtree arrive together once that subtree is done,
+    /// children before their parent, and this node comes last. The children are
+    /// independent so they arrive in any order. A `DependsOn` or leaf at the top is
+    /// checked as a whole. Must be called from within a Tokio runtime
+    #[cfg(feature = "tokio")]
+    pub fn check_streaming(
+        &self,
+        info: &BTreeMap<String, String>,
+    ) -> impl tokio_stream::Stream<Item = (Vec<usize>, Status)> {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let root = std::sync::Arc::new(self.clone());
+        let info = std::sync::Arc::new(info.clone());
+        let send_subtree = |path: Vec<usize>| {
+            let (root, info, sender) = (root.clone(), info.clone(), sender.clone());
+            tokio::task::spawn_blocking(move || {
+                let node = root.subtree_at(&path).expect("path of an existing child");
+                let result = node.eval(&Env::new(&root, &*info));
+                let mut trace = Vec::new();
+                result.trace(&mut path.clone(), &mut trace);
+                for event in trace {
+                    // a dropped receiver just doesn't care anymore
+                    let _ = sender.send((event.path, event.status));
+                }
+                result.status
+            })
+        };
+        let children = match self.combine(&[]) {
+            Some(_) => (0..self.children().len())
+                .map(|i| send_subtree(vec![i]))
+                .collect::<Vec<_>>(),
+            None => vec![send_subtree(Vec::new())],
+        };
+        let root = root.clone();
+        tokio::spawn(async move {
+            let mut statuses = Vec::new();
+            for child in children {
+                statuses.push(child.await.expect("rule evaluation panicked"));
+            }
+            if let Some(status) = root.combine(&statuses) {
+                let _ = sender.send((Vec::new(), status));
+            }
+        });
+        tokio_stream::wrappers::UnboundedReceiverStream::new(receiver)
+    }
+
+    /// Like `check()`, also returning a [`TraceEvent`] for every node in the order
+    /// evaluation finished, i.e. children before their parent
+    pub fn check_traced(&self, info: &BTreeMap<String, String>) -> (RuleResult, Vec<TraceEvent>) {
+        let result = self.check(info);
+        let mut trace = Vec::new();
+        result.trace(&mut Vec::new(), &mut trace);
+        (result, trace)
+    }
+
+    /// Checks every fact map in `facts` and counts the top-level outcomes.
+    ///
+    /// Only statuses are computed, see `compile()`, so this is much cheaper than
+    /// collecting a `RuleResult` per record
+    pub fn check_batch_summary(&self, facts: &[BTreeMap<String, String>]) -> BatchSummary {
+        let check = self.compile();
+        let mut summary = BatchSummary::default();
+        for info in facts {
+            match check(info) {
+                Status::Met => summary.met += 1,
+                Status::NotMet => summary.not_met += 1,
+                Status::Unknown => summary.unknown += 1,
+            }
+        }
+        summary
+    }
+
+    /// Index of the earliest of the time-ordered `snapshots` for which this tree is
+    /// `Met`, e.g. to measure the time to compliance. `None` if it never is
+    pub fn first_satisfied_index(&self, snapshots: &[BTreeMap<String, String>]) -> Option<usize> {
+        let check = self.compile();
+        snapshots.iter().position(|info| check(info) == Status::Met)
+    }
+
+    /// Fraction of the fact maps in `batch` for which each leaf is `Met`, keyed by
+    /// the leaf's description, e.g. to spot leaves that always pass or always fail.
+    ///
+    /// `Unknown` counts as not passing. Leaves sharing a description are pooled, and
+    /// an empty batch yields an empty map
+    pub fn leaf_pass_rates(&self, batch: &[BTreeMap<String, String>]) -> BTreeMap<String, f64> {
+        let mut counts = BTreeMap::<String, (usize, usize)>::new();
+        for info in batch {
+            for (name, status) in self.leaf_outcomes(info) {
+                let (met, total) = counts.entry(name).or_default();
+                *met += usize::from(status == Status::Met);
+                *total += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(name, (met, total))| (name, met as f64 / total as f64))
+            .collect()
+    }
+
+    /// Like `check()`, but the value of each field in `transforms` is first passed
+    /// through its transform, e.g. to trim whitespace or strip a currency symbol.
+    ///
+    /// Constraints reading other fields see the transformed values as well
+    pub fn check_with_transforms(
+        &self,
+        info: &BTreeMap<String, String>,
+        transforms: &BTreeMap<String, Transform>,
+    ) -> RuleResult {
+        let info = info
+            .iter()
+            .map(|(field, val)| match transforms.get(field) {
+                Some(transform) => (field.clone(), transform(val)),
+                None => (field.clone(), val.clone()),
+            })
+            .collect();
+        self.check(&info)
+    }
+
+    /// Like `check()`, but time-dependent constraints treat `now` as the current time
+    #[cfg(feature = "chrono")]
+    pub fn check_at(&self, info: &BTreeMap<String, String>, now: DateTime<Utc>) -> RuleResult {
+        let env = Env {
+            now: Some(now),
+            ..Env::new(self, info)
+        };
+        self.eval(&env)
+    }
+
+    /// Hash of this subtree's content.
+    ///
+    /// Computed with FNV-1a over the serialized JSON, so it is the same across
+    /// processes, platforms and Rust versions for as long as the serialized form of
+    /// the tree stays the same
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::default();
+        serde_json::to_writer(&mut hasher, self).expect("rules serialize to JSON");
+        hasher.finish()
+    }
+
+    /// Key identifying the result of checking this tree against `info`, for caching.
+    ///
+    /// Combines `content_hash()` with the values of the fields the tree references,
+    /// so facts the tree never reads don't change the key. Like `content_hash()` it is
+    /// stable across processes, so it can key a shared or persistent cache
+    pub fn cache_key(&self, info: &BTreeMap<String, String>) -> String {
+        let mut hasher = Fnv1a::default();
+        for field in self.referenced_fields() {
+            serde_json::to_writer(&mut hasher, &(field, info.get(field)))
+                .expect("strings serialize to JSON");
+        }
+        format!("{:016x}-{:016x}", self.content_hash(), hasher.finish())
+    }
+
+    /// Compiles the tree into a closure computing the same status as `check()`.
+    ///
+    /// The tree is walked once up front, so each call of the closure skips matching
+    /// on node types and building `RuleResult`s, and stops evaluating children as
+    /// soon as a combinator's status is settled. Worth it when the same tree is
+    /// checked many times and only the status is needed
+    pub fn compile(&self) -> impl Fn(&BTreeMap<String, String>) -> Status + '_ {
+        self.compile_node(self)
+    }
+
+    /// Compiles this subtree, `root` being the tree `compile()` was called on
+    fn compile_node<'a>(&'a self, root: &'a Rule) -> Compiled<'a> {
         match *self {
-            Rule::And { ref rules } => {
-                let mut status = Status::Met;
-                let children = rules
+            Rule::And { ref rules } | Rule::Chain { ref rules } => {
+                let rules = rules
                     .iter()
-                    .map(|c| c.check(info))
-                    .inspect(|r| status = status & r.status)
+                    .map(|r| r.compile_node(root))
                     .collect::<Vec<_>>();
-                RuleResult {
-                    name: "And".into(),
-                    status,
-                    children,
-                }
+                Box::new(move |info| {
+                    let mut status = Status::Met;
+                    for rule in &rules {
+                        status = status & rule(info);
+                        if status == Status::NotMet {
+                            break;
+                        }
+                    }
+                    status
+                })
             }
-            Rule::Or { ref rules } => {
-                let mut status = Status::NotMet;
-                let children = rules
-                    .iter()
-                    .map(|c| c.check(info))
-                    .inspect(|r| status = status | r.status)
+            Rule::Or { .. } | Rule::RuleSet { .. } => {
+                let rules = self
+                    .children()
+                    .into_iter()
+                    .map(|r| r.compile_node(root))
                     .collect::<Vec<_>>();
-                RuleResult {
-                    name: "Or".into(),
-                    status,
-                    children,
+                Box::new(move |info| {
+                    let mut status = Status::NotMet;
+                    for rule in &rules {
+                        status = status | rule(info);
+                        if status == Status::Met {
+                            break;
+                        }
+                    }
+                    status
+                })
+            }
+            Rule::Coalesce {
+                ref primary,
+                ref fallback,
+            } => {
+                let (primary, fallback) = (primary.compile_node(root), fallback.compile_node(root));
+                Box::new(move |info| match primary(info) {
+                    Status::Unknown => fallback(info),
+                    status => status,
+                })
+            }
+            Rule::Not { ref rule } => {
+                let rule = rule.compile_node(root);
+                Box::new(move |info| !rule(info))
+            }
+            Rule::WithId { ref rule, .. } => rule.compile_node(root),
+            // dependencies live elsewhere in the tree, evaluate the regular way
+            Rule::DependsOn { .. } => Box::new(move |info| self.eval(&Env::new(root, info)).status),
+            Rule::NumberOf { n, ref rules } => Rule::compile_number_of(n, rules, root),
+            Rule::Majority { ref rules } => Rule::compile_number_of(majority(rules), rules, root),
+            Rule::Always => Box::new(|_| Status::Met),
+            Rule::Never => Box::new(|_| Status::NotMet),
+            Rule::Rule {
+                ref field,
+                ref constraint,
+                ..
+            } => Box::new(move |info| match info.get(field) {
+                Some(s) => constraint.evaluate(field, s, &Env::new(root, info)),
+                None => Status::Unknown,
+            }),
+        }
+    }
+
+    fn compile_number_of<'a>(n: usize, rules: &'a [Rule], root: &'a Rule) -> Compiled<'a> {
+        let rules = rules
+            .iter()
+            .map(|r| r.compile_node(root))
+            .collect::<Vec<_>>();
+        Box::new(move |info| {
+            let mut met_count = 0;
+            let mut failed_count = 0;
+            for rule in &rules {
+                match rule(info) {
+                    Status::Met => met_count += 1,
+                    Status::NotMet => failed_count += 1,
+                    Status::Unknown => {}
                 }
             }
-            Rule::NumberOf {
-                n: count,
-                ref rules,
+            number_of_status(n, met_count, failed_count, rules.len())
+        })
+    }
+
+    /// Copy of this leaf with its constraint replaced, keeping `desc` and `field`.
+    ///
+    /// Handy for stamping out variants of a template rule. Combinator nodes have no
+    /// constraint, for those this is a plain clone
+    pub fn with_constraint(&self, constraint: Constraint) -> Rule {
+        match *self {
+            Rule::Rule {
+                ref desc,
+                ref field,
+                ..
+            } => Rule::Rule {
+                desc: desc.clone(),
+                field: field.clone(),
+                constraint,
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// `And` of this rule and the negated `exception`, i.e. `Met` only when this rule
+    /// is `Met` and the exception is `NotMet`, e.g. to carve out special cases
+    pub fn unless(self, exception: Rule) -> Rule {
+        Rule::And {
+            rules: vec![
+                self,
+                Rule::Not {
+                    rule: Box::new(exception),
+                },
+            ],
+        }
+    }
+
+    /// Direct children of this node, empty for leaves
+    pub fn children(&self) -> Vec<&Rule> {
+        match *self {
+            Rule::And { ref rules }
+            | Rule::Or { ref rules }
+            | Rule::NumberOf { ref rules, .. }
+            | Rule::Majority { ref rules }
+            | Rule::Chain { ref rules } => rules.iter().collect(),
+            Rule::RuleSet { ref rules } => rules.iter().map(|(_, r)| r).collect(),
+            Rule::Coalesce {
+                ref primary,
+                ref fallback,
+            } => vec![primary, fallback],
+            Rule::Not { ref rule } | Rule::WithId { ref rule, .. } => vec![rule],
+            Rule::DependsOn { ref then, .. } => vec![then],
+            Rule::Always | Rule::Never | Rule::Rule { .. } => Vec::new(),
+        }
+    }
+
+    /// Calls `f` on every node of this subtree, children before their parent.
+    ///
+    /// Useful for bulk edits such as renaming fields or scrubbing descriptions
+    pub fn walk_mut(&mut self, f: &mut dyn FnMut(&mut Rule)) {
+        match *self {
+            Rule::And { ref mut rules }
+            | Rule::Or { ref mut rules }
+            | Rule::NumberOf { ref mut rules, .. }
+            | Rule::Majority { ref mut rules }
+            | Rule::Chain { ref mut rules } => rules.iter_mut().for_each(|r| r.walk_mut(f)),
+            Rule::RuleSet { ref mut rules } => rules.iter_mut().for_each(|(_, r)| r.walk_mut(f)),
+            Rule::Coalesce {
+                ref mut primary,
+                ref mut fallback,
             } => {
-                let mut met_count = 0;
-                let mut failed_count = 0;
-                let children = rules
-                    .iter()
-                    .map(|c| c.check(info))
-                    .inspect(|r| {
-                        if r.status == Status::Met {
-                            met_count += 1;
-                        } else if r.status == Status::NotMet {
-                            failed_count += 1;
-                        }
-                    })
+                primary.walk_mut(f);
+                fallback.walk_mut(f);
+            }
+            Rule::Not { ref mut rule } | Rule::WithId { ref mut rule, .. } => rule.walk_mut(f),
+            Rule::DependsOn { ref mut then, .. } => then.walk_mut(f),
+            Rule::Always | Rule::Never | Rule::Rule { .. } => {}
+        }
+        f(self)
+    }
+
+    /// Splits a top-level `And` into shards reading disjoint sets of fields, e.g. to
+    /// evaluate them on different machines.
+    ///
+    /// Children sharing a field, directly or through other children, end up in the
+    /// same shard, an `And` of them unless there is just one. The statuses of the
+    /// shards combined with `&` equal the status of the whole tree. Anything but an
+    /// `And`, or a tree using `DependsOn`, is a single shard
+    pub fn shard_by_fields(&self) -> Vec<Rule> {
+        let rules = match *self {
+            Rule::And { ref rules } if !self.has_dependencies() => rules,
+            _ => return vec![self.clone()],
+        };
+        let mut shards: Vec<(BTreeSet<&str>, BTreeSet<usize>)> = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            let mut fields = rule.referenced_fields();
+            let mut members = BTreeSet::new();
+            // merge every shard sharing a field with this rule
+            let mut i = 0;
+            while i < shards.len() {
+                if shards[i].0.is_disjoint(&fields) {
+                    i += 1;
+                } else {
+                    let (shard_fields, shard_members) = shards.remove(i);
+                    fields.extend(shard_fields);
+                    members.extend(shard_members);
+                }
+            }
+            members.insert(index);
+            shards.push((fields, members));
+        }
+        shards
+            .into_iter()
+            .map(|(_, members)| match members.len() {
+                1 => rules[*members.first().expect("shards are never empty")].clone(),
+                _ => Rule::And {
+                    rules: members.into_iter().map(|i| rules[i].clone()).collect(),
+                },
+            })
+            .collect()
+    }
+
+    /// Whether any node of this subtree is a `DependsOn`
+    fn has_dependencies(&self) -> bool {
+        matches!(*self, Rule::DependsOn { .. })
+            || self.children().into_iter().any(Rule::has_dependencies)
+    }
+
+    /// Size metrics of this subtree, for flagging rules too complex to review
+    pub fn complexity(&self) -> Complexity {
+        let mut kinds = HashSet::new();
+        self.visit_leaves(&mut |_, constraint| {
+            kinds.insert(std::mem::discriminant(constraint));
+        });
+        Complexity {
+            nodes: self.node_count(),
+            depth: self.depth(),
+            distinct_fields: self.referenced_fields().len(),
+            distinct_constraint_kinds: kinds.len(),
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(Rule::node_count)
+            .sum::<usize>()
+    }
+
+    fn depth(&self) -> usize {
+        1 + self
+            .children()
+            .into_iter()
+            .map(Rule::depth)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Calls `f` with the field and constraint of every leaf, depth-first
+    pub(crate) fn visit_leaves<'a>(&'a self, f: &mut dyn FnMut(&'a str, &'a Constraint)) {
+        match *self {
+            Rule::Rule {
+                ref field,
+                ref constraint,
+                ..
+            } => f(field, constraint),
+            _ => self.children().into_iter().for_each(|r| r.visit_leaves(f)),
+        }
+    }
+
+    /// Finds supplied facts that can never satisfy the constraints referencing them
+    /// because of their type, e.g. a non-numeric value for an `IntEquals` field.
+    ///
+    /// Returns `(field, problem)` pairs in depth-first order. Missing facts are not
+    /// reported, they just make the affected rules `Unknown`
+    pub fn typecheck_facts(&self, info: &BTreeMap<String, String>) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        self.visit_leaves(&mut |field, constraint| {
+            let problem = info
+                .get(field)
+                .and_then(|val| constraint.type_problem(val))
+                .map(|problem| (field.to_owned(), problem));
+            if let Some(problem) = problem {
+                if !problems.contains(&problem) {
+                    problems.push(problem);
+                }
+            }
+        });
+        problems
+    }
+
+    /// Name and status of every leaf in depth-first order, the same as the
+    /// `leaves()` of `check()`'s result.
+    ///
+    /// Leaves are evaluated one at a time as the iterator advances, and no
+    /// `RuleResult` tree is built
+    pub fn leaf_outcomes<'a>(
+        &'a self,
+        info: &'a BTreeMap<String, String>,
+    ) -> impl Iterator<Item = (String, Status)> + 'a {
+        let env = Env::new(self, info);
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                if node.kind() == NodeKind::Leaf {
+                    let result = node.eval(&env);
+                    return Some((result.name, result.status));
+                }
+                stack.extend(node.children().into_iter().rev());
+            }
+            None
+        })
+    }
+
+    /// Risk score of the facts: `met_weight` for every `Met` leaf plus
+    /// `unknown_penalty` for every `Unknown` one, `NotMet` leaves adding nothing.
+    ///
+    /// Meant for trees whose leaves are risk signals, where missing data is itself
+    /// suspicious and a threshold on the score decides
+    pub fn risk_score(
+        &self,
+        info: &BTreeMap<String, String>,
+        met_weight: f64,
+        unknown_penalty: f64,
+    ) -> f64 {
+        self.leaf_outcomes(info)
+            .map(|(_, status)| match status {
+                Status::Met => met_weight,
+                Status::NotMet => 0.0,
+                Status::Unknown => unknown_penalty,
+            })
+            .sum()
+    }
+
+    /// Name and status of every leaf, in depth-first order, from statuses packed by
+    /// `RuleResult::pack_leaves()` after checking this tree.
+    ///
+    /// Fails if `packed` is too short for the leaves of this tree or holds an
+    /// invalid status
+    pub fn unpack_leaves(&self, packed: &[u8]) -> anyhow::Result<Vec<(String, Status)>> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.kind() != NodeKind::Leaf {
+                stack.extend(node.children().into_iter().rev());
+                continue;
+            }
+            let i = leaves.len();
+            let byte = match packed.get(i / 4) {
+                Some(byte) => byte,
+                None => bail!("packed statuses end before leaf {}", i),
+            };
+            let status = match byte >> (i % 4 * 2) & 0b11 {
+                0 => Status::Unknown,
+                1 => Status::Met,
+                2 => Status::NotMet,
+                code => bail!("invalid status code {} for leaf {}", code, i),
+            };
+            leaves.push((node.name(), status));
+        }
+        Ok(leaves)
+    }
+
+    /// Label and result of the first rule of a `RuleSet` that is `Met`.
+    ///
+    /// Rules after the match are not evaluated. Returns `None` if no rule matches
+    /// or this node is not a `RuleSet`
+    pub fn first_match(&self, info: &BTreeMap<String, String>) -> Option<(String, RuleResult)> {
+        let rules = match *self {
+            Rule::RuleSet { ref rules } => rules,
+            _ => return None,
+        };
+        let env = Env::new(self, info);
+        rules.iter().find_map(|(label, rule)| {
+            let result = rule.eval(&env);
+            (result.status == Status::Met).then(|| (label.clone(), result))
+        })
+    }
+
+    /// Node reached by following `path`, a list of child indices starting at this node.
+    ///
+    /// An empty path is this node itself, an out-of-bounds index yields `None`
+    pub fn subtree_at(&self, path: &[usize]) -> Option<&Rule> {
+        path.iter()
+            .try_fold(self, |node, &i| node.children().get(i).copied())
+    }
+
+    /// Fails if any combinator in this subtree has more than `max_children` direct
+    /// children, guarding against pathologically wide trees from untrusted sources.
+    ///
+    /// The error names the path of the first offending node, see `subtree_at()`
+    pub fn validate_breadth(&self, max_children: usize) -> anyhow::Result<()> {
+        self.validate_breadth_at(max_children, &mut Vec::new())
+    }
+
+    fn validate_breadth_at(
+        &self,
+        max_children: usize,
+        path: &mut Vec<usize>,
+    ) -> anyhow::Result<()> {
+        let children = self.children();
+        ensure!(
+            children.len() <= max_children,
+            "{} node at path {:?} has {} children, more than the limit of {}",
+            self.name(),
+            path,
+            children.len(),
+            max_children
+        );
+        for (i, child) in children.into_iter().enumerate() {
+            path.push(i);
+            child.validate_breadth_at(max_children, path)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// Fails if two `WithId` nodes share an id, or if a `DependsOn` refers to an id
+    /// that no `WithId` in this tree has
+    pub fn check_ids(&self) -> anyhow::Result<()> {
+        let mut ids = BTreeSet::new();
+        let mut references = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            match *node {
+                Rule::WithId { ref id, .. } => {
+                    ensure!(ids.insert(id), "duplicate rule id {:?}", id)
+                }
+                Rule::DependsOn { ref rule_id, .. } => references.push(rule_id),
+                _ => {}
+            }
+            stack.extend(node.children());
+        }
+        for id in references {
+            ensure!(ids.contains(id), "dependency on unknown rule id {:?}", id);
+        }
+        Ok(())
+    }
+
+    /// Rule named `id` by a `WithId` in this subtree
+    fn find_id(&self, id: &str) -> Option<&Rule> {
+        match *self {
+            Rule::WithId {
+                id: ref own,
+                ref rule,
+            } if own == id => Some(rule),
+            _ => self.children().into_iter().find_map(|r| r.find_id(id)),
+        }
+    }
+
+    /// Serializes this subtree to JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("rules always serialize to JSON")
+    }
+
+    /// Serializes this subtree to the versioned interchange format,
+    /// `{"version": 1, "root": ...}`.
+    ///
+    /// `root` uses the same schema as `to_json()`: each node is an object with the
+    /// variant name as its only key, e.g. `{"And": {"rules": [...]}}` or
+    /// `{"Rule": {"desc": ..., "field": ..., "constraint": {"IntEquals": 10}}}`.
+    /// [`IR_VERSION`] is bumped whenever that schema changes incompatibly
+    pub fn to_ir_json(&self) -> String {
+        let ir = serde_json::json!({ "version": IR_VERSION, "root": self });
+        ir.to_string()
+    }
+
+    /// Parses the output of `to_ir_json()`, failing on any version other than
+    /// [`IR_VERSION`]
+    pub fn from_ir_json(json: &str) -> anyhow::Result<Rule> {
+        #[derive(Deserialize)]
+        struct Envelope {
+            version: u32,
+            root: serde_json::Value,
+        }
+        let ir = serde_json::from_str::<Envelope>(json)?;
+        ensure!(
+            ir.version == IR_VERSION,
+            "unsupported rule IR version {}, expected {}",
+            ir.version,
+            IR_VERSION
+        );
+        Ok(serde_json::from_value(ir.root)?)
+    }
+
+    /// Assembles a tree from a flat table, e.g. one authored in a spreadsheet, where
+    /// combinator rows reference their children by row index.
+    ///
+    /// Row 0 is the root and rows it doesn't reach are ignored. A row may be the
+    /// child of several others. Fails on references to rows that don't exist and on
+    /// cycles
+    pub fn from_flat(rows: Vec<FlatRow>) -> anyhow::Result<Rule> {
+        ensure!(!rows.is_empty(), "no rows to assemble");
+        let mut built = vec![None; rows.len()];
+        Rule::assemble(&rows, 0, &mut built, &mut Vec::new())
+    }
+
+    /// Rule for row `i`, with `built` caching finished rows and `path` holding the
+    /// rows currently being assembled
+    fn assemble(
+        rows: &[FlatRow],
+        i: usize,
+        built: &mut [Option<Rule>],
+        path: &mut Vec<usize>,
+    ) -> anyhow::Result<Rule> {
+        if let Some(rule) = built[i].clone() {
+            return Ok(rule);
+        }
+        if path.contains(&i) {
+            bail!("cycle through rows {:?}", path);
+        }
+        path.push(i);
+        let mut children = |children: &[usize]| {
+            children
+                .iter()
+                .map(|&child| {
+                    ensure!(
+                        child < rows.len(),
+                        "row {} references row {}, but there are only {} rows",
+                        i,
+                        child,
+                        rows.len()
+                    );
+                    Rule::assemble(rows, child, built, path)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let rule = match rows[i] {
+            FlatRow::Leaf(ref rule) => rule.clone(),
+            FlatRow::And(ref rules) => Rule::And {
+                rules: children(rules)?,
+            },
+            FlatRow::Or(ref rules) => Rule::Or {
+                rules: children(rules)?,
+            },
+            FlatRow::NumberOf { n, ref rules } => Rule::NumberOf {
+                n,
+                rules: children(rules)?,
+            },
+            FlatRow::Majority(ref rules) => Rule::Majority {
+                rules: children(rules)?,
+            },
+        };
+        path.pop();
+        built[i] = Some(rule.clone());
+        Ok(rule)
+    }
+
+    /// Scans the leaves depth-first and returns the result of the first one that
+    /// is `NotMet`, or `None` if no leaf fails.
+    ///
+    /// Combinator semantics are ignored entirely, a failing leaf under a satisfied
+    /// `Or` is still reported. Evaluation stops at the first failure
+    pub fn first_failure(&self, info: &BTreeMap<String, String>) -> Option<RuleResult> {
+        self.find_first_failure(&Env::new(self, info))
+    }
+
+    /// Plugs in the facts known so far and returns the residual rule over the
+    /// remaining fields.
+    ///
+    /// Leaves whose fact is present are replaced by `Always` or `Never` according to
+    /// their status, then combinators are simplified, e.g. an `And` drops its
+    /// `Always` children and collapses to `Never` if any child is `Never`. Leaves
+    /// that stay `Unknown` despite their fact, such as ones needing history, are kept
+    pub fn partial_eval(self, info: &BTreeMap<String, String>) -> Rule {
+        self.partial_eval_in(info)
+    }
+
+    fn partial_eval_in(self, info: &BTreeMap<String, String>) -> Rule {
+        match self {
+            Rule::And { rules } => {
+                let mut rest = Vec::new();
+                for rule in rules.into_iter().map(|r| r.partial_eval_in(info)) {
+                    match rule {
+                        Rule::Always => {}
+                        Rule::Never => return Rule::Never,
+                        rule => rest.push(rule),
+                    }
+                }
+                match rest.len() {
+                    0 => Rule::Always,
+                    1 => rest.remove(0),
+                    _ => Rule::And { rules: rest },
+                }
+            }
+            Rule::Or { rules } => {
+                let mut rest = Vec::new();
+                for rule in rules.into_iter().map(|r| r.partial_eval_in(info)) {
+                    match rule {
+                        Rule::Always => return Rule::Always,
+                        Rule::Never => {}
+                        rule => rest.push(rule),
+                    }
+                }
+                match rest.len() {
+                    0 => Rule::Never,
+                    1 => rest.remove(0),
+                    _ => Rule::Or { rules: rest },
+                }
+            }
+            Rule::Chain { rules } => {
+                // constant steps are kept in place, they still count towards progress
+                let rules = rules
+                    .into_iter()
+                    .map(|r| r.partial_eval_in(info))
                     .collect::<Vec<_>>();
-                let status = if met_count >= count {
-                    Status::Met
-                } else if failed_count > children.len() - count {
-                    Status::NotMet
+                if rules.contains(&Rule::Never) {
+                    Rule::Never
+                } else if rules.iter().all(|r| *r == Rule::Always) {
+                    Rule::Always
                 } else {
-                    Status::Unknown
-                };
-                RuleResult {
-                    name: format!("At least {} of", count),
-                    status,
-                    children,
+                    Rule::Chain { rules }
+                }
+            }
+            Rule::Coalesce { primary, fallback } => match primary.partial_eval_in(info) {
+                primary @ (Rule::Always | Rule::Never) => primary,
+                primary => Rule::Coalesce {
+                    primary: Box::new(primary),
+                    fallback: Box::new(fallback.partial_eval_in(info)),
+                },
+            },
+            Rule::Not { rule } => match rule.partial_eval_in(info) {
+                Rule::Always => Rule::Never,
+                Rule::Never => Rule::Always,
+                rule => Rule::Not {
+                    rule: Box::new(rule),
+                },
+            },
+            // the wrapper stays, constant or not, so dependencies can still find it
+            Rule::WithId { id, rule } => Rule::WithId {
+                id,
+                rule: Box::new(rule.partial_eval_in(info)),
+            },
+            Rule::DependsOn {
+                rule_id,
+                expect,
+                then,
+            } => Rule::DependsOn {
+                rule_id,
+                expect,
+                then: Box::new(then.partial_eval_in(info)),
+            },
+            Rule::NumberOf { n, rules } => Rule::partial_eval_number_of(n, rules, info),
+            Rule::Majority { rules } => Rule::partial_eval_number_of(majority(&rules), rules, info),
+            Rule::RuleSet { rules } => {
+                let mut rest = Vec::new();
+                for (label, rule) in rules {
+                    match rule.partial_eval_in(info) {
+                        Rule::Never => {}
+                        Rule::Always => {
+                            // later rules can never be the first match
+                            rest.push((label, Rule::Always));
+                            break;
+                        }
+                        rule => rest.push((label, rule)),
+                    }
+                }
+                if rest.is_empty() {
+                    Rule::Never
+                } else {
+                    Rule::RuleSet { rules: rest }
                 }
             }
+            Rule::Rule { ref field, .. } if info.contains_key(field) => {
+                match self.check(info).status {
+                    Status::Met => Rule::Always,
+                    Status::NotMet => Rule::Never,
+                    Status::Unknown => self,
+                }
+            }
+            rule => rule,
+        }
+    }
+
+    /// Simplifies the tree around its `Always` and `Never` constants, e.g. an `And`
+    /// with a `Never` child becomes `Never` and an `Or` with an `Always` child
+    /// becomes `Always`.
+    ///
+    /// This is `partial_eval()` without any facts, so the folded tree has the same
+    /// status as the original for every set of facts
+    pub fn fold_constants(self) -> Rule {
+        self.partial_eval_in(&BTreeMap::new())
+    }
+
+    /// Residual of a node needing `n` of `rules`, with the children that became
+    /// constants dropped and `n` lowered by the number of `Always` ones
+    fn partial_eval_number_of(n: usize, rules: Vec<Rule>, info: &BTreeMap<String, String>) -> Rule {
+        let mut n = n;
+        let mut rest = Vec::new();
+        for rule in rules.into_iter().map(|r| r.partial_eval_in(info)) {
+            match rule {
+                Rule::Always => n = n.saturating_sub(1),
+                Rule::Never => {}
+                rule => rest.push(rule),
+            }
+        }
+        if n == 0 {
+            Rule::Always
+        } else if n > rest.len() {
+            Rule::Never
+        } else {
+            Rule::NumberOf { n, rules: rest }
+        }
+    }
+
+    /// Every combination of values from `domains` for which this tree is `Met`.
+    ///
+    /// Each field in `domains` takes each of its values in turn, fields without a
+    /// domain stay absent. Meant for small trees and test generation: fails if there
+    /// are more than [`MAX_ASSIGNMENTS`] combinations to try
+    pub fn satisfying_assignments(
+        &self,
+        domains: &BTreeMap<String, Vec<String>>,
+    ) -> anyhow::Result<Vec<BTreeMap<String, String>>> {
+        let check = self.compile();
+        Ok(Rule::assignments(domains)?
+            .filter(|info| check(info) == Status::Met)
+            .collect())
+    }
+
+    /// Every combination of values from `domains`, failing if there are more than
+    /// [`MAX_ASSIGNMENTS`]
+    fn assignments(
+        domains: &BTreeMap<String, Vec<String>>,
+    ) -> anyhow::Result<impl Iterator<Item = BTreeMap<String, String>> + '_> {
+        let total = domains
+            .values()
+            .try_fold(1usize, |total, values| total.checked_mul(values.len()))
+            .filter(|&total| total <= MAX_ASSIGNMENTS);
+        let total = match total {
+            Some(total) => total,
+            None => bail!(
+                "more than {} assignments to search, narrow the domains",
+                MAX_ASSIGNMENTS
+            ),
+        };
+        Ok((0..total).map(move |mut i| {
+            let mut info = BTreeMap::new();
+            for (field, values) in domains {
+                info.insert(field.clone(), values[i % values.len()].clone());
+                i /= values.len();
+            }
+            info
+        }))
+    }
+
+    /// Sample facts for which this tree is `Met`, e.g. for documentation or tests.
+    ///
+    /// Each leaf suggests a few values satisfying its constraint on its own, like
+    /// `"John"` for `StringEquals("John")` or the start of an `IntRange`, and the
+    /// combinations of those values are searched for one making the whole tree
+    /// `Met`. `None` if there is none, e.g. because the tree relies on constraints no
+    /// values can be derived for, such as regexes or context lookups
+    pub fn example_facts(&self) -> Option<BTreeMap<String, String>> {
+        let mut domains = BTreeMap::<String, Vec<String>>::new();
+        self.visit_leaves(&mut |field, constraint| {
+            for val in constraint.example_values() {
+                let values = domains.entry(field.to_owned()).or_default();
+                if !values.contains(&val) {
+                    values.push(val);
+                }
+            }
+        });
+        let check = self.compile();
+        let found = Rule::assignments(&domains)
+            .ok()?
+            .find(|info| check(info) == Status::Met);
+        found
+    }
+
+    /// A small set of missing fields whose values could settle a result that is
+    /// still `Unknown`, i.e. what to fetch next when loading facts lazily.
+    ///
+    /// An `And` can be settled as `NotMet` by a single child, an `Or` as `Met`, so
+    /// usually only one unsettled child's fields are needed; a `NumberOf` needs as
+    /// many children as the chosen outcome requires. Earlier children are preferred
+    /// and each leaf with a missing field is assumed able to take any status, even
+    /// when several leaves share the field. The set is empty if the result is
+    /// already decided, or if no missing fact can settle it (e.g. it waits on history)
+    pub fn decisive_fields(&self, info: &BTreeMap<String, String>) -> BTreeSet<String> {
+        let result = self.check(info);
+        [Status::NotMet, Status::Met]
+            .into_iter()
+            .filter_map(|target| self.settling_fields(&result, target, info))
+            .min_by_key(BTreeSet::len)
+            .unwrap_or_default()
+    }
+
+    /// Missing fields that, once supplied, make a result that is still `Unknown`
+    /// definite whatever their values turn out to be.
+    ///
+    /// Unlike `decisive_fields()`, which finds fields that *could* settle the result,
+    /// every `Unknown` child of an `And`, `Or` or `NumberOf` has to be resolved here,
+    /// since any of them may turn out to be the one that matters. A `Coalesce` only
+    /// needs its primary or its fallback, whichever takes fewer fields. The set is
+    /// empty if the result is already decided, or if missing facts alone can't
+    /// resolve it (e.g. it waits on history)
+    pub fn fields_to_resolve(&self, info: &BTreeMap<String, String>) -> BTreeSet<String> {
+        self.resolving_fields(&self.check(info), info)
+            .unwrap_or_default()
+    }
+
+    /// Missing fields guaranteeing the node behind `result` becomes definite, `None`
+    /// if that's impossible
+    fn resolving_fields(
+        &self,
+        result: &RuleResult,
+        info: &BTreeMap<String, String>,
+    ) -> Option<BTreeSet<String>> {
+        if result.status != Status::Unknown {
+            return Some(BTreeSet::new());
+        }
+        match *self {
             Rule::Rule {
-                desc: ref name,
                 ref field,
                 ref constraint,
+                ..
             } => {
-                let status = if let Some(s) = info.get(field) {
-                    constraint.check(s)
-                } else {
-                    Status::Unknown
-                };
-                RuleResult {
-                    name: name.to_owned(),
-                    status,
-                    children: Vec::new(),
+                let missing = std::iter::once(field.as_str())
+                    .chain(constraint.other_fields())
+                    .filter(|field| !info.contains_key(*field))
+                    .map(str::to_owned)
+                    .collect::<BTreeSet<_>>();
+                (!missing.is_empty()).then_some(missing)
+            }
+            Rule::Coalesce {
+                ref primary,
+                ref fallback,
+            } => {
+                // an unresolved primary stays unknown, leaving the decision to the fallback
+                [primary, fallback]
+                    .into_iter()
+                    .zip(&result.children)
+                    .filter_map(|(rule, result)| rule.resolving_fields(result, info))
+                    .min_by_key(BTreeSet::len)
+            }
+            // resolving it may take fields from the dependency, which lives elsewhere
+            Rule::DependsOn { .. } | Rule::Always | Rule::Never => None,
+            _ => self
+                .children()
+                .into_iter()
+                .zip(&result.children)
+                .map(|(rule, result)| rule.resolving_fields(result, info))
+                .try_fold(BTreeSet::new(), |mut fields, child| {
+                    fields.extend(child?);
+                    Some(fields)
+                }),
+        }
+    }
+
+    /// Fields referenced by this tree whose values can no longer change the result,
+    /// e.g. those only used in the other branches of an `Or` that is already `Met`.
+    ///
+    /// A field stays relevant while some leaf using it is `Unknown` and all of that
+    /// leaf's ancestors are `Unknown` too. Supplied fields whose leaves are decided
+    /// are irrelevant as well, there is nothing left to gather for them
+    pub fn irrelevant_fields(&self, info: &BTreeMap<String, String>) -> BTreeSet<String> {
+        let mut relevant = BTreeSet::new();
+        self.collect_relevant_fields(&self.check(info), &mut relevant);
+        self.referenced_fields()
+            .into_iter()
+            .filter(|field| !relevant.contains(*field))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Every field read by a leaf of this tree, including the fields that
+    /// constraints such as `LessThanFieldScaled` compare against
+    fn referenced_fields(&self) -> BTreeSet<&str> {
+        let mut fields = BTreeSet::new();
+        self.visit_leaves(&mut |field, constraint| {
+            fields.insert(field);
+            fields.extend(constraint.other_fields());
+        });
+        fields
+    }
+
+    fn collect_relevant_fields(&self, result: &RuleResult, relevant: &mut BTreeSet<String>) {
+        if result.status != Status::Unknown {
+            return;
+        }
+        match *self {
+            Rule::Rule {
+                ref field,
+                ref constraint,
+                ..
+            } => {
+                relevant.insert(field.clone());
+                relevant.extend(constraint.other_fields().into_iter().map(str::to_owned));
+            }
+            _ => {
+                for (child, result) in self.children().into_iter().zip(&result.children) {
+                    child.collect_relevant_fields(result, relevant);
                 }
             }
         }
     }
-}
 
-// ***********************************************************************
-// Rule RESULT
-// **********************************************************************
+    /// Missing fields that could turn the `Unknown` node behind `result` into `target`,
+    /// `None` if that's impossible
+    fn settling_fields(
+        &self,
+        result: &RuleResult,
+        target: Status,
+        info: &BTreeMap<String, String>,
+    ) -> Option<BTreeSet<String>> {
+        let n = match *self {
+            Rule::Rule {
+                ref field,
+                ref constraint,
+                ..
+            } => {
+                let missing = std::iter::once(field.as_str())
+                    .chain(constraint.other_fields())
+                    .filter(|field| !info.contains_key(*field))
+                    .map(str::to_owned)
+                    .collect::<BTreeSet<_>>();
+                return (!missing.is_empty()).then_some(missing);
+            }
+            Rule::And { ref rules } | Rule::Chain { ref rules } => rules.len(),
+            Rule::Or { .. } | Rule::RuleSet { .. } | Rule::WithId { .. } => 1,
+            Rule::Coalesce {
+                ref primary,
+                ref fallback,
+            } => {
+                if result.status != Status::Unknown {
+                    return (result.status == target).then(BTreeSet::new);
+                }
+                // either settles it: the fallback is only consulted while the primary is unknown
+                return [primary, fallback]
+                    .into_iter()
+                    .zip(&result.children)
+                    .filter_map(|(rule, result)| rule.settling_fields(result, target, info))
+                    .min_by_key(BTreeSet::len);
+            }
+            Rule::Not { ref rule } => {
+                return rule.settling_fields(&result.children[0], !target, info);
+            }
+            Rule::NumberOf { n, .. } => n,
+            Rule::Majority { ref rules } => majority(rules),
+            // settling it may take fields from the dependency, which lives elsewhere
+            Rule::Always | Rule::Never | Rule::DependsOn { .. } => return None,
+        };
+        let count = |status| {
+            result
+                .children
+                .iter()
+                .filter(|c| c.status == status)
+                .count()
+        };
+        let needed = if target == Status::Met {
+            n.saturating_sub(count(Status::Met))
+        } else {
+            (result.children.len() + 1).saturating_sub(n + count(Status::NotMet))
+        };
+        let candidates = self
+            .children()
+            .into_iter()
+            .zip(&result.children)
+            .filter(|(_, result)| result.status == Status::Unknown)
+            .filter_map(|(rule, result)| rule.settling_fields(result, target, info))
+            .take(needed)
+            .collect::<Vec<_>>();
+        (candidates.len() == needed).then(|| candidates.into_iter().flatten().collect())
+    }
 
-/// Result of checking a rules tree.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct RuleResult {
-    /// Human-friendly description of the rule
+    fn find_first_failure<'a>(&'a self, env: &Env<'a>) -> Option<RuleResult> {
+        match *self {
+            Rule::Always | Rule::Never | Rule::Rule { .. } => {
+                Some(self.eval(env)).filter(|r| r.status == Status::NotMet)
+            }
+            _ => self
+                .children()
+                .into_iter()
+                .find_map(|r| r.find_first_failure(env)),
+        }
+    }
+
+    /// Name given to this node's `RuleResult`
+    fn name(&self) -> String {
+        match *self {
+            Rule::And { .. } => "And".into(),
+            Rule::Or { .. } => "Or".into(),
+            Rule::NumberOf { n, .. } => format!("At least {} of", n),
+            Rule::Majority { .. } => "Majority of".into(),
+            Rule::Chain { .. } => "Chain".into(),
+            Rule::Coalesce { .. } => "Coalesce".into(),
+            Rule::Not { .. } => "Not".into(),
+            Rule::WithId { ref id, .. } => id.clone(),
+            Rule::DependsOn { ref rule_id, .. } => format!("Depends on {}", rule_id),
+            Rule::RuleSet { .. } => "First match of".into(),
+            Rule::Always => "Always".into(),
+            Rule::Never => "Never".into(),
+            Rule::Rule { ref desc, .. } => desc.clone(),
+        }
+    }
+
+    /// Kind of node reported in this node's `RuleResult`
+    fn kind(&self) -> NodeKind {
+        match *self {
+            Rule::And { .. } | Rule::Chain { .. } => NodeKind::And,
+            Rule::Or { .. } => NodeKind::Or,
+            Rule::NumberOf { n, .. } => NodeKind::NumberOf(n),
+            Rule::Majority { ref rules } => NodeKind::NumberOf(majority(rules)),
+            Rule::RuleSet { .. } => NodeKind::RuleSet,
+            Rule::Coalesce { .. } => NodeKind::Coalesce,
+            Rule::Not { .. } => NodeKind::Not,
+            Rule::WithId { .. } => NodeKind::And,
+            Rule::DependsOn { .. } => NodeKind::DependsOn,
+            Rule::Always | Rule::Never | Rule::Rule { .. } => NodeKind::Leaf,
+        }
+    }
+
+    fn result(&self, status: Status, children: Vec<RuleResult>) -> RuleResult {
+        RuleResult {
+            name: self.name(),
+            field: match *self {
+                Rule::Rule { ref field, .. } => Some(field.clone()),
+                _ => None,
+            },
+            kind: self.kind(),
+            status,
+            children,
+            elapsed: None,
+            reason: None,
+            progress: None,
+            metadata: None,
+        }
+    }
+
+    /// Result of this subtree with every node `Unknown`, without evaluating anything
+    fn unknown_result(&self) -> RuleResult {
+        let children = self
+            .children()
+            .into_iter()
+            .map(Rule::unknown_result)
+            .collect();
+        self.result(Status::Unknown, children)
+    }
+
+    pub(crate) fn eval<'a>(&'a self, env: &Env<'a>) -> RuleResult {
+        if env.cancel.is_some_and(|c| c.load(Ordering::Relaxed)) {
+            return self.unknown_result();
+        }
+        #[cfg(feature = "otel")]
+        let span = env.spans.then(|| {
+            tracing::info_span!(
+                "rule",
+                name = %self.name(),
+                kind = ?self.kind(),
+                status = tracing::field::Empty
+            )
+        });
+        #[cfg(feature = "otel")]
+        let _entered = span.as_ref().map(tracing::Span::enter);
+        let start = env.profile.then(Instant::now);
+        let mut result = self.eval_memoized(env);
+        #[cfg(feature = "otel")]
+        if let Some(ref span) = span {
+            span.record("status", tracing::field::debug(result.status));
+        }
+        result.elapsed = start.map(|start| start.elapsed());
+        if env.verbose {
+            result.reason = self.reason(&result.children);
+        }
+        result
+    }
+
+    /// Summary of how this combinator's children turned out, `None` for leaves
+    fn reason(&self, children: &[RuleResult]) -> Option<String> {
+        let needs = match *self {
+            Rule::And { .. } => "needs all".to_owned(),
+            Rule::Chain { .. } => "needs all, in order".to_owned(),
+            Rule::Coalesce { .. } => "needs the first one that is known".to_owned(),
+            Rule::Not { .. } => "needs none".to_owned(),
+            Rule::WithId { .. } => "needs all".to_owned(),
+            Rule::DependsOn {
+                ref rule_id,
+                expect,
+                ..
+            } => format!("needs all, if {} is {:?}", rule_id, expect),
+            Rule::Or { .. } | Rule::RuleSet { .. } => "needs one".to_owned(),
+            Rule::NumberOf { n, .. } => format!("needs {}", n),
+            Rule::Majority { ref rules } => format!("needs {}", majority(rules)),
+            Rule::Always | Rule::Never | Rule::Rule { .. } => return None,
+        };
+        let met = children.iter().filter(|c| c.status == Status::Met).count();
+        Some(format!(
+            "{} of {} children met ({})",
+            met,
+            children.len(),
+            needs
+        ))
+    }
+
+    fn eval_memoized<'a>(&'a self, env: &Env<'a>) -> RuleResult {
+        let (memo, id) = match env.memo {
+            Some(ref memo) => match memo.ids.get(&std::ptr::from_ref(self)) {
+                Some(&id) => (memo, id),
+                None => return self.eval_node(env),
+            },
+            None => return self.eval_node(env),
+        };
+        if let Some(result) = memo.results.borrow().get(&id) {
+            return result.clone();
+        }
+        let result = self.eval_node(env);
+        memo.results.borrow_mut().insert(id, result.clone());
+        result
+    }
+
+    /// This node's own content, leaving out its children, for telling apart nodes
+    /// of the same variant in `Memo`
+    fn own_content(&self) -> String {
+        match *self {
+            Rule::NumberOf { n, .. } => n.to_string(),
+            Rule::RuleSet { ref rules } => {
+                let names = rules.iter().map(|(name, _)| name).collect::<Vec<_>>();
+                serde_json::to_string(&names).expect("strings serialize to JSON")
+            }
+            Rule::WithId { ref id, .. } => id.clone(),
+            Rule::DependsOn {
+                ref rule_id,
+                expect,
+                ..
+            } => format!("{:?} {}", expect, rule_id),
+            Rule::Rule { .. } => serde_json::to_string(self).expect("rules serialize to JSON"),
+            Rule::And { .. }
+            | Rule::Or { .. }
+            | Rule::Majority { .. }
+            | Rule::Chain { .. }
+            | Rule::Coalesce { .. }
+            | Rule::Not { .. }
+            | Rule::Always
+            | Rule::Never => String::new(),
+        }
+    }
+
+    fn eval_node<'a>(&'a self, env: &Env<'a>) -> RuleResult {
+        match *self {
+            Rule::And { ref rules } => {
+                let mut status = Status::Met;
+                let children = rules
+                    .iter()
+                    .map(|c| c.eval(env))
+                    .inspect(|r| status = status & r.status)
+                    .collect::<Vec<_>>();
+                self.result(status, children)
+            }
+            Rule::Or { ref rules } => {
+                let mut status = Status::NotMet;
+                let children = rules
+                    .iter()
+                    .map(|c| c.eval(env))
+                    .inspect(|r| status = status | r.status)
+                    .collect::<Vec<_>>();
+                self.result(status, children)
+            }
+            Rule::RuleSet { ref rules } => {
+                let mut status = Status::NotMet;
+                let children = rules
+                    .iter()
+                    .map(|(_, c)| c.eval(env))
+                    .inspect(|r| status = status | r.status)
+                    .collect::<Vec<_>>();
+                self.result(status, children)
+            }
+            Rule::Chain { ref rules } => {
+                let mut status = Status::Met;
+                let children = rules
+                    .iter()
+                    .map(|c| c.eval(env))
+                    .inspect(|r| status = status & r.status)
+                    .collect::<Vec<_>>();
+                let progress = children
+                    .iter()
+                    .take_while(|r| r.status == Status::Met)
+                    .count();
+                RuleResult {
+                    progress: Some(progress),
+                    ..self.result(status, children)
+                }
+            }
+            Rule::Coalesce {
+                ref primary,
+                ref fallback,
+            } => {
+                let children = vec![primary.eval(env), fallback.eval(env)];
+                let status = match children[0].status {
+                    Status::Unknown => children[1].status,
+                    status => status,
+                };
+                self.result(status, children)
+            }
+            Rule::Not { ref rule } => {
+                let child = rule.eval(env);
+                self.result(!child.status, vec![child])
+            }
+            Rule::WithId { ref rule, .. } => {
+                let child = rule.eval(env);
+                self.result(child.status, vec![child])
+            }
+            Rule::DependsOn {
+                ref rule_id,
+                expect,
+                ref then,
+            } => {
+                let child = then.eval(env);
+                let status = match self.dependency_status(rule_id, env) {
+                    dependency if dependency == expect => child.status,
+                    Status::Unknown => Status::Unknown,
+                    _ => Status::NotMet,
+                };
+                self.result(status, vec![child])
+            }
+            Rule::NumberOf { n, ref rules } => self.eval_number_of(n, rules, env),
+            Rule::Majority { ref rules } => self.eval_number_of(majority(rules), rules, env),
+            Rule::Always => self.result(Status::Met, Vec::new()),
+            Rule::Never => self.result(Status::NotMet, Vec::new()),
+            Rule::Rule {
+                ref field,
+                ref constraint,
+                ..
+            } => {
+                let skipped = env.fields.is_some_and(|fields| !fields.contains(field));
+                let status = match env.fact(field) {
+                    Some(s) if !skipped => constraint.evaluate(field, &s, env),
+                    _ => Status::Unknown,
+                };
+                self.result(status, Vec::new())
+            }
+        }
+    }
+
+    /// Status of this combinator given the statuses of its children, `None` for
+    /// leaves and for `DependsOn`, which also needs its dependency
+    #[cfg(feature = "tokio")]
+    fn combine(&self, children: &[Status]) -> Option<Status> {
+        let count = |status| children.iter().filter(|&&s| s == status).count();
+        let status = match *self {
+            Rule::And { .. } | Rule::Chain { .. } | Rule::WithId { .. } => {
+                children.iter().fold(Status::Met, |acc, &s| acc & s)
+            }
+            Rule::Or { .. } | Rule::RuleSet { .. } => {
+                children.iter().fold(Status::NotMet, |acc, &s| acc | s)
+            }
+            Rule::NumberOf { n, .. } => {
+                number_of_status(n, count(Status::Met), count(Status::NotMet), children.len())
+            }
+            Rule::Majority { ref rules } => number_of_status(
+                majority(rules),
+                count(Status::Met),
+                count(Status::NotMet),
+                children.len(),
+            ),
+            Rule::Coalesce { .. } => match children.first() {
+                Some(Status::Unknown) => children.get(1).copied().unwrap_or(Status::Unknown),
+                primary => primary.copied().unwrap_or(Status::Unknown),
+            },
+            Rule::Not { .. } => !children.first().copied().unwrap_or(Status::Unknown),
+            Rule::DependsOn { .. } | Rule::Always | Rule::Never | Rule::Rule { .. } => return None,
+        };
+        Some(status)
+    }
+
+    /// Status of the rule named `id` in the tree being checked, `Unknown` if there is
+    /// none or it is already being resolved, i.e. depends on itself
+    fn dependency_status<'a>(&self, id: &'a str, env: &Env<'a>) -> Status {
+        let rule = match env.root.find_id(id) {
+            Some(rule) if !env.resolving.borrow().contains(&id) => rule,
+            _ => return Status::Unknown,
+        };
+        env.resolving.borrow_mut().push(id);
+        let status = rule.eval(env).status;
+        env.resolving.borrow_mut().pop();
+        status
+    }
+
+    fn eval_number_of<'a>(&'a self, count: usize, rules: &'a [Rule], env: &Env<'a>) -> RuleResult {
+        let mut met_count = 0;
+        let mut failed_count = 0;
+        let children = rules
+            .iter()
+            .map(|c| c.eval(env))
+            .inspect(|r| {
+                if r.status == Status::Met {
+                    met_count += 1;
+                } else if r.status == Status::NotMet {
+                    failed_count += 1;
+                }
+            })
+            .collect::<Vec<_>>();
+        let status = number_of_status(count, met_count, failed_count, children.len());
+        self.result(status, children)
+    }
+}
+
+/// Version of the format written by `Rule::to_ir_json()`
+pub const IR_VERSION: u32 = 1;
+
+/// Largest number of fact combinations `Rule::satisfying_assignments()` will try
+pub const MAX_ASSIGNMENTS: usize = 1 << 20;
+
+/// Number of children a `Majority` node needs to be `Met`
+fn majority(rules: &[Rule]) -> usize {
+    rules.len() / 2 + 1
+}
+
+/// Status of a node needing `n` of its `len` children to be `Met`
+fn number_of_status(n: usize, met_count: usize, failed_count: usize, len: usize) -> Status {
+    if met_count >= n {
+        Status::Met
+    } else if failed_count + n > len {
+        Status::NotMet
+    } else {
+        Status::Unknown
+    }
+}
+
+/// Row of the flat table assembled by `Rule::from_flat()`, combinators referencing
+/// their children by row index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FlatRow {
+    /// A complete rule, usually a `Rule::Rule` leaf
+    Leaf(Rule),
+    And(Vec<usize>),
+    Or(Vec<usize>),
+    NumberOf {
+        n: usize,
+        rules: Vec<usize>,
+    },
+    Majority(Vec<usize>),
+}
+
+/// Custom check of a value, referenced by name from `Constraint::Named`, see
+/// `Rule::check_with_comparators()`
+pub type Comparator = fn(&str) -> Status;
+
+/// Rewrites a fact value before it is checked, see `Rule::check_with_transforms()`
+pub type Transform = Box<dyn Fn(&str) -> String>;
+
+/// Node of the closure graph built by `Rule::compile()`
+type Compiled<'a> = Box<dyn Fn(&BTreeMap<String, String>) -> Status + 'a>;
+
+/// Results of already evaluated subtrees, see `Rule::check_memoized()`
+pub(crate) struct Memo {
+    /// Id of every node of the tree, shared by identical subtrees
+    ids: HashMap<*const Rule, usize>,
+    results: RefCell<HashMap<usize, RuleResult>>,
+}
+
+/// A node as `Memo` tells them apart: its variant, own content and child ids
+type NodeKey = (std::mem::Discriminant<Rule>, String, Vec<usize>);
+
+impl Memo {
+    pub(crate) fn new(root: &Rule) -> Self {
+        let mut memo = Memo {
+            ids: HashMap::new(),
+            results: RefCell::new(HashMap::new()),
+        };
+        memo.assign_ids(root, &mut HashMap::new());
+        memo
+    }
+
+    /// Gives `rule` and its descendants ids, children first, so each node's key
+    /// only takes its own content and its children's ids rather than the whole
+    /// subtree
+    fn assign_ids(&mut self, rule: &Rule, keys: &mut HashMap<NodeKey, usize>) -> usize {
+        let children = rule
+            .children()
+            .into_iter()
+            .map(|child| self.assign_ids(child, keys))
+            .collect();
+        let key = (std::mem::discriminant(rule), rule.own_content(), children);
+        let next = keys.len();
+        let id = *keys.entry(key).or_insert(next);
+        self.ids.insert(std::ptr::from_ref(rule), id);
+        id
+    }
+}
+
+/// State shared by every node of a single evaluation
+pub(crate) struct Env<'a> {
+    /// Tree being checked, where `DependsOn` looks up rule ids
+    pub(crate) root: &'a Rule,
+    pub(crate) facts: &'a dyn FactSource,
+    pub(crate) context: Option<&'a BTreeMap<String, String>>,
+    pub(crate) comparators: Option<&'a BTreeMap<String, Comparator>>,
+    pub(crate) memo: Option<Memo>,
+    pub(crate) history: Option<&'a BTreeMap<String, VecDeque<String>>>,
+    pub(crate) rate_counters: Option<&'a RateCounters>,
+    pub(crate) profile: bool,
+    pub(crate) verbose: bool,
+    pub(crate) cancel: Option<&'a AtomicBool>,
+    /// Whether to open a `tracing` span per node, see `Rule::check_instrumented()`
+    #[cfg(feature = "otel")]
+    pub(crate) spans: bool,
+    /// Only leaves checking these fields are evaluated, see `Rule::check_fields()`
+    pub(crate) fields: Option<&'a BTreeSet<String>>,
+    /// Ids of the `DependsOn` targets currently being evaluated
+    pub(crate) resolving: RefCell<Vec<&'a str>>,
+    #[cfg(feature = "chrono")]
+    pub(crate) now: Option<DateTime<Utc>>,
+}
+
+impl<'a> Env<'a> {
+    pub(crate) fn new(root: &'a Rule, facts: &'a dyn FactSource) -> Self {
+        Env {
+            root,
+            facts,
+            context: None,
+            comparators: None,
+            memo: None,
+            history: None,
+            rate_counters: None,
+            profile: false,
+            verbose: false,
+            cancel: None,
+            #[cfg(feature = "otel")]
+            spans: false,
+            fields: None,
+            resolving: RefCell::new(Vec::new()),
+            #[cfg(feature = "chrono")]
+            now: None,
+        }
+    }
+
+    /// Value of `field` in the facts being checked
+    pub(crate) fn fact(&self, field: &str) -> Option<Cow<'a, str>> {
+        self.facts.fact(field)
+    }
+
+    /// Value stored under `key` in the evaluation context
+    pub(crate) fn context(&self, key: &str) -> Option<&'a str> {
+        self.context?.get(key).map(String::as_str)
+    }
+}
+
+/// 64-bit FNV-1a, whose output depends only on the bytes written, unlike
+/// `DefaultHasher` which may change between Rust versions.
+///
+/// Also an `io::Write`, so serialized output can be fed into it without allocating
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl std::io::Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Hasher::write(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// ***********************************************************************
+// Rule RESULT
+// **********************************************************************
+
+/// Kind of node a `RuleResult` was produced by, which determines how the
+/// statuses of its children were combined
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Default, Serialize, Deserialize)]
+pub enum NodeKind {
+    And,
+    Or,
+    /// At least this many children must be `Met`
+    NumberOf(usize),
+    RuleSet,
+    /// The first child's status, or the second's when the first is `Unknown`
+    Coalesce,
+    /// The child's status, gated on the status of another rule
+    DependsOn,
+    /// The opposite of the child's status
+    Not,
+    #[default]
+    Leaf,
+}
+
+/// Result of checking a rules tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleResult {
+    /// Human-friendly description of the rule
     pub name: String,
+    /// Field checked by a leaf, `None` for other nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    /// Kind of node that produced this result
+    #[serde(default)]
+    pub kind: NodeKind,
     /// top-level status of this result
     pub status: Status,
     /// Results of any sub-rules
     pub children: Vec<RuleResult>,
+    /// Time spent evaluating this node, including its children.
+    ///
+    /// Only recorded by `Rule::check_profiled()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed: Option<Duration>,
+    /// How the children of a combinator add up to its status, e.g.
+    /// `"1 of 2 children met (needs all)"`.
+    ///
+    /// Only recorded by `Rule::check_verbose()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Number of leading children that are `Met` before the first one that isn't.
+    ///
+    /// Only recorded for `Rule::Chain` nodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<usize>,
+    /// Caller-supplied data about the evaluation, e.g. a correlation id.
+    ///
+    /// Only recorded on the root by `Rule::check_with_metadata()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+/// Counts of top-level outcomes over many records, see `Rule::check_batch_summary()`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchSummary {
+    pub met: usize,
+    pub not_met: usize,
+    pub unknown: usize,
+}
+
+/// Size metrics of a rules tree, see `Rule::complexity()`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Complexity {
+    /// Number of nodes, leaves included
+    pub nodes: usize,
+    /// Number of nodes on the longest path from the root to a leaf
+    pub depth: usize,
+    /// Number of different fields read by the leaves
+    pub distinct_fields: usize,
+    /// Number of different `Constraint` variants used by the leaves
+    pub distinct_constraint_kinds: usize,
+}
+
+/// Record of one node's evaluation, see `Rule::check_traced()`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    /// Child indices leading from the root to the node
+    pub path: Vec<usize>,
+    pub name: String,
+    pub status: Status,
+}
+
+impl RuleResult {
+    /// Appends the events of this subtree in post-order, `path` being this node's path
+    fn trace(&self, path: &mut Vec<usize>, trace: &mut Vec<TraceEvent>) {
+        for (i, child) in self.children.iter().enumerate() {
+            path.push(i);
+            child.trace(path, trace);
+            path.pop();
+        }
+        trace.push(TraceEvent {
+            path: path.clone(),
+            name: self.name.clone(),
+            status: self.status,
+        });
+    }
+
+    /// Whether each direct child is `Met`, in order
+    pub fn child_met_mask(&self) -> Vec<bool> {
+        self.children
+            .iter()
+            .map(|c| c.status == Status::Met)
+            .collect()
+    }
+
+    /// Indices of the children that decided this node's status.
+    ///
+    /// * `And`: the first `NotMet` child when `NotMet`, otherwise all children
+    /// * `Or` and `RuleSet`: the first `Met` child when `Met`, otherwise all children
+    /// * `Coalesce`: the primary when it is known, otherwise both children
+    /// * `NumberOf(n)`: the first `n` `Met` children when `Met`, the first
+    ///   `len - n + 1` `NotMet` children when `NotMet`, otherwise all children
+    pub fn decisive_children(&self) -> Vec<usize> {
+        let indices_with = |status: Status, limit: usize| {
+            self.children
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.status == status)
+                .map(|(i, _)| i)
+                .take(limit)
+                .collect()
+        };
+        match (self.kind, self.status) {
+            (NodeKind::Leaf, _) => Vec::new(),
+            (NodeKind::And, Status::NotMet) => indices_with(Status::NotMet, 1),
+            (NodeKind::Or | NodeKind::RuleSet, Status::Met) => indices_with(Status::Met, 1),
+            (NodeKind::Coalesce, _) if self.children[0].status != Status::Unknown => vec![0],
+            (NodeKind::NumberOf(n), Status::Met) => indices_with(Status::Met, n),
+            (NodeKind::NumberOf(n), Status::NotMet) => {
+                indices_with(Status::NotMet, (self.children.len() + 1).saturating_sub(n))
+            }
+            _ => (0..self.children.len()).collect(),
+        }
+    }
+
+    /// One-line description of the route from this node to its first decisive leaf,
+    /// following `decisive_children()`, e.g. `And -> Or -> "Favorite number is 10"(Met)`.
+    ///
+    /// Meant for logs where the full tree is too verbose. A combinator without
+    /// children ends the path with its own status, e.g. `And(Met)`
+    pub fn decision_path_string(&self) -> String {
+        let mut path = String::new();
+        let mut node = self;
+        while node.kind != NodeKind::Leaf {
+            match node.decisive_children().first() {
+                Some(&i) => {
+                    let _ = write!(path, "{} -> ", node.name);
+                    node = &node.children[i];
+                }
+                None => break,
+            }
+        }
+        if node.kind == NodeKind::Leaf {
+            let _ = write!(path, "{:?}({:?})", node.name, node.status);
+        } else {
+            let _ = write!(path, "{}({:?})", node.name, node.status);
+        }
+        path
+    }
+
+    /// Graphviz DOT graph of only the nodes that decided the outcome, i.e. this
+    /// node and, recursively, its `decisive_children()`.
+    ///
+    /// Nodes are labelled with their name and filled by status: green for `Met`,
+    /// red for `NotMet` and grey for `Unknown`
+    pub fn decisive_path_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n    node [style=filled];\n");
+        let mut next = 0;
+        let mut stack = vec![(self, next)];
+        while let Some((node, id)) = stack.pop() {
+            let color = match node.status {
+                Status::Met => "palegreen",
+                Status::NotMet => "lightcoral",
+                Status::Unknown => "lightgrey",
+            };
+            let _ = writeln!(dot, "    n{id} [label={:?}, fillcolor={color}];", node.name);
+            for i in node.decisive_children() {
+                next += 1;
+                let _ = writeln!(dot, "    n{id} -> n{next};");
+                stack.push((&node.children[i], next));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Leaf results of this tree in depth-first order
+    pub fn leaves(&self) -> Vec<&RuleResult> {
+        if self.kind == NodeKind::Leaf {
+            return vec![self];
+        }
+        self.children.iter().flat_map(RuleResult::leaves).collect()
+    }
+
+    /// Statuses of `leaves()` packed two bits each, four to a byte starting at the
+    /// low bits: `0` for `Unknown`, `1` for `Met` and `2` for `NotMet`.
+    ///
+    /// Compact storage for large numbers of results, read back with
+    /// `Rule::unpack_leaves()` on the tree that was checked
+    pub fn pack_leaves(&self) -> Vec<u8> {
+        let leaves = self.leaves();
+        let mut packed = vec![0; leaves.len().div_ceil(4)];
+        for (i, leaf) in leaves.iter().enumerate() {
+            let code = match leaf.status {
+                Status::Unknown => 0,
+                Status::Met => 1,
+                Status::NotMet => 2,
+            };
+            packed[i / 4] |= code << (i % 4 * 2);
+        }
+        packed
+    }
+
+    /// Leaf results grouped by the field they checked, each group in depth-first order
+    pub fn by_field(&self) -> BTreeMap<String, Vec<&RuleResult>> {
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for leaf in self.leaves() {
+            if let Some(ref field) = leaf.field {
+                groups.entry(field.clone()).or_default().push(leaf);
+            }
+        }
+        groups
+    }
+
+    /// Fraction of the decided leaves that are `Met`, i.e. `met / (met + not_met)`.
+    ///
+    /// `Unknown` leaves are ignored, so this stays informative when much of the data
+    /// is missing. `NaN` when no leaf is decided
+    pub fn confidence(&self) -> f64 {
+        let (met, not_met) =
+            self.leaves()
+                .iter()
+                .fold((0, 0), |(met, not_met), leaf| match leaf.status {
+                    Status::Met => (met + 1, not_met),
+                    Status::NotMet => (met, not_met + 1),
+                    Status::Unknown => (met, not_met),
+                });
+        met as f64 / (met + not_met) as f64
+    }
+
+    /// `(name, met_fraction)` of every combinator node in depth-first order, the
+    /// fraction of its direct children that are `Met`.
+    ///
+    /// Shows which combinators pass or fail by a narrow margin. `NaN` for a
+    /// combinator without children
+    pub fn satisfaction_ratios(&self) -> Vec<(String, f64)> {
+        let mut ratios = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if node.kind == NodeKind::Leaf {
+                continue;
+            }
+            let met = node.child_met_mask().into_iter().filter(|&met| met).count();
+            ratios.push((node.name.clone(), met as f64 / node.children.len() as f64));
+            stack.extend(node.children.iter().rev());
+        }
+        ratios
+    }
+
+    /// Estimated number of leaves that still have to become `Met` for this node to
+    /// be `Met`, e.g. for progress bars.
+    ///
+    /// A `Met` node needs none and any other leaf needs one. An `And` needs the sum
+    /// of its children, an `Or` or `RuleSet` only its cheapest child, a `NumberOf`
+    /// the cheapest children making up its shortfall, and a `Coalesce` its primary
+    /// once that is known, otherwise its cheapest child. A `Not` is counted as one,
+    /// though making its child `NotMet` may take more. This is a
+    /// heuristic: leaves shared between branches are counted once per branch, and
+    /// a leaf that can never flip still counts as one
+    pub fn distance_to_met(&self) -> usize {
+        if self.status == Status::Met {
+            return 0;
+        }
+        if self.children.is_empty() {
+            return 1;
+        }
+        let mut distances = self
+            .children
+            .iter()
+            .map(RuleResult::distance_to_met)
+            .collect::<Vec<_>>();
+        match self.kind {
+            NodeKind::Or | NodeKind::RuleSet => distances.into_iter().min().unwrap_or(1),
+            NodeKind::Coalesce if self.children[0].status != Status::Unknown => distances[0],
+            NodeKind::Coalesce => distances.into_iter().min().unwrap_or(1),
+            // some leaf below has to flip, however many it takes
+            NodeKind::Not => 1,
+            // a met child can still be held back by its dependency
+            NodeKind::DependsOn => distances[0].max(1),
+            NodeKind::NumberOf(n) => {
+                distances.sort_unstable();
+                let met = distances.iter().take_while(|&&d| d == 0).count();
+                distances[met..].iter().take(n.saturating_sub(met)).sum()
+            }
+            NodeKind::And | NodeKind::Leaf => distances.into_iter().sum(),
+        }
+    }
+
+    /// Every leaf as `(name, status, contributed)` in depth-first order, where
+    /// `contributed` tells whether the leaf lies on a decisive path from the root,
+    /// see `decisive_children()`
+    pub fn leaf_contributions(&self) -> Vec<(String, Status, bool)> {
+        let mut leaves = Vec::new();
+        self.collect_contributions(true, &mut leaves);
+        leaves
+    }
+
+    fn collect_contributions(&self, decisive: bool, leaves: &mut Vec<(String, Status, bool)>) {
+        if self.kind == NodeKind::Leaf {
+            leaves.push((self.name.clone(), self.status, decisive));
+            return;
+        }
+        let decisive_children = self.decisive_children();
+        for (i, child) in self.children.iter().enumerate() {
+            child.collect_contributions(decisive && decisive_children.contains(&i), leaves);
+        }
+    }
+
+    /// Time spent in this node itself, excluding its children
+    pub fn self_time(&self) -> Option<Duration> {
+        let children = self
+            .children
+            .iter()
+            .filter_map(|c| c.elapsed)
+            .sum::<Duration>();
+        self.elapsed.map(|e| e.saturating_sub(children))
+    }
+
+    /// The `k` nodes with the largest self time, slowest first.
+    ///
+    /// Only nodes with a recorded `elapsed` are considered, see `Rule::check_profiled()`
+    pub fn slowest_nodes(&self, k: usize) -> Vec<(&str, Duration)> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            if let Some(time) = node.self_time() {
+                nodes.push((node.name.as_str(), time));
+            }
+            stack.extend(&node.children);
+        }
+        nodes.sort_by_key(|&(_, time)| Reverse(time));
+        nodes.truncate(k);
+        nodes
+    }
+
+    /// Child indices leading to the shallowest `Met` node without `Met` children,
+    /// walking only through `Met` nodes.
+    ///
+    /// That node is usually a leaf, or a `Not` whose child is `NotMet`. Returns `None`
+    /// if this node isn't `Met`
+    pub fn shortest_met_path(&self) -> Option<Vec<usize>> {
+        let mut queue = VecDeque::from([(self, Vec::new())]);
+        while let Some((node, path)) = queue.pop_front() {
+            if node.status != Status::Met {
+                continue;
+            }
+            if node.children.iter().all(|c| c.status != Status::Met) {
+                return Some(path);
+            }
+            for (i, child) in node.children.iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                queue.push_back((child, child_path));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn facts(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    /// Fact source counting lookups, standing in for leaves that are expensive to evaluate
+    struct CountingFacts {
+        facts: BTreeMap<String, String>,
+        lookups: Cell<usize>,
+    }
+
+    impl FactSource for CountingFacts {
+        fn fact(&self, field: &str) -> Option<Cow<'_, str>> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.facts.fact(field)
+        }
+    }
+
+    #[test]
+    fn memoized_check_evaluates_duplicated_subtree_once() {
+        let shared = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::int_range("age", "age", 18, 65),
+            crate::boolean("verified", "verified", true),
+        ]);
+        let rule = crate::and(vec![shared.clone(), crate::or(vec![shared])]);
+        let source = CountingFacts {
+            facts: facts(&[("name", "alice"), ("age", "30"), ("verified", "true")]),
+            lookups: Cell::new(0),
+        };
+
+        let plain = rule.eval(&Env::new(&rule, &source));
+        assert_eq!(source.lookups.replace(0), 6);
+
+        let env = Env {
+            memo: Some(Memo::new(&rule)),
+            ..Env::new(&rule, &source)
+        };
+        let memoized = rule.eval(&env);
+        assert_eq!(source.lookups.get(), 3);
+        assert_eq!(memoized.status, Status::Met);
+        let json = |r: &RuleResult| serde_json::to_string(r).unwrap();
+        assert_eq!(json(&memoized), json(&plain));
+        assert_eq!(json(&rule.check_memoized(&source.facts)), json(&plain));
+    }
+
+    #[test]
+    fn memo_shares_ids_only_between_identical_subtrees() {
+        let name = || crate::string_equals("name", "name", "alice");
+        let age = || crate::int_range("age", "age", 18, 65);
+        let rule = crate::and(vec![
+            crate::and(vec![name(), age()]),
+            crate::or(vec![name(), age()]),
+            crate::and(vec![name(), age()]),
+            crate::and(vec![age(), name()]),
+            crate::string_equals("other name", "name", "alice"),
+        ]);
+        let memo = Memo::new(&rule);
+        let id = |rule: &Rule| memo.ids[&std::ptr::from_ref(rule)];
+        let branches = rule.children();
+        assert_eq!(id(branches[0]), id(branches[2]));
+        assert_ne!(id(branches[0]), id(branches[1]));
+        assert_ne!(id(branches[0]), id(branches[3]));
+        assert_eq!(id(branches[0].children()[0]), id(branches[3].children()[1]));
+        assert_ne!(id(branches[0].children()[0]), id(branches[4]));
+        // one id per distinct subtree: name, age, the four branches' shapes, the
+        // differently described leaf and the root
+        assert_eq!(memo.ids.values().collect::<BTreeSet<_>>().len(), 7);
+
+        let info = facts(&[("name", "alice"), ("age", "70")]);
+        let json = |r: &RuleResult| serde_json::to_string(r).unwrap();
+        assert_eq!(json(&rule.check_memoized(&info)), json(&rule.check(&info)));
+    }
+
+    fn leaf(constraint: Constraint) -> Rule {
+        Rule::Rule {
+            desc: "leaf".into(),
+            field: "x".into(),
+            constraint,
+        }
+    }
+
+    #[test]
+    fn deserializing_rejects_invalid_constraints() {
+        let invalid = [
+            Constraint::Range {
+                start: 10.0,
+                start_incl: true,
+                end: 0.0,
+                end_incl: true,
+            },
+            Constraint::TierEquals {
+                thresholds: vec![(100.0, "silver".into()), (0.0, "bronze".into())],
+                expected: "silver".into(),
+            },
+            Constraint::AboveCurve {
+                points: Vec::new(),
+                x_field: "age".into(),
+            },
+            Constraint::AboveCurve {
+                points: vec![(1.0, 0.0), (1.0, 5.0)],
+                x_field: "age".into(),
+            },
+            Constraint::Hysteresis {
+                on: 10.0,
+                off: 20.0,
+                state_field: "on".into(),
+            },
+            Constraint::ArrayAll(Box::new(Constraint::Hysteresis {
+                on: 1.0,
+                off: 1.0,
+                state_field: "on".into(),
+            })),
+            Constraint::UnderRateLimit {
+                limit: 2,
+                window: std::time::Duration::ZERO,
+            },
+            Constraint::StringBetween("t".into(), "m".into()),
+            Constraint::ArrayPercentEquals {
+                value: "pass".into(),
+                percent: 120.0,
+            },
+            Constraint::CloseTo {
+                reference: 1.0,
+                rel_tol: -0.1,
+                abs_tol: 0.0,
+            },
+            Constraint::CloseTo {
+                reference: 1.0,
+                rel_tol: 0.0,
+                abs_tol: -1e-6,
+            },
+            Constraint::PasswordPolicy {
+                min_len: 12,
+                max_len: 8,
+                require_digit: false,
+                require_upper: false,
+                require_lower: false,
+                require_symbol: false,
+            },
+        ];
+        for constraint in invalid {
+            assert!(constraint.validate().is_err(), "{:?}", constraint);
+            let rule = crate::and(vec![leaf(constraint)]);
+            let json = serde_json::to_string(&rule).unwrap();
+            assert!(serde_json::from_str::<Rule>(&json).is_err(), "{}", json);
+            assert!(Rule::from_ir_json(&rule.to_ir_json()).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn deserializing_accepts_valid_constraints() {
+        let rule = crate::and(vec![
+            crate::range("score", "x", 0.0, true, 10.0, false).unwrap(),
+            crate::hysteresis("heater", "x", 20.0, 10.0, "on").unwrap(),
+        ]);
+        let json = serde_json::to_string(&rule).unwrap();
+        assert_eq!(serde_json::from_str::<Rule>(&json).unwrap(), rule);
+        assert_eq!(Rule::from_ir_json(&rule.to_ir_json()).unwrap(), rule);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn deserializing_rejects_regex_capture_without_the_group() {
+        let json = r#"{"Rule":{"desc":"year","field":"date","constraint":
+            {"RegexCapture":{"pattern":"(?<y>\\d{4})","group":"year","inner":"IsPalindrome"}}}}"#;
+        assert!(serde_json::from_str::<Rule>(json).is_err());
+    }
+
+    #[test]
+    fn content_hash_is_stable() {
+        let rule = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::int_range("age", "age", 18, 65),
+        ]);
+        // pinned, so a change to the hash or the serialized form is noticed
+        assert_eq!(rule.content_hash(), 18329509073874602593);
+        assert_eq!(rule.content_hash(), rule.clone().content_hash());
+        assert_ne!(
+            rule.content_hash(),
+            crate::int_range("age", "age", 18, 65).content_hash()
+        );
+    }
+
+    #[test]
+    fn cache_key_only_depends_on_referenced_facts() {
+        let rule = crate::string_equals("name", "name", "alice");
+        let key = rule.cache_key(&facts(&[("name", "alice")]));
+        assert_eq!(
+            key,
+            rule.cache_key(&facts(&[("name", "alice"), ("age", "30")]))
+        );
+        assert_ne!(key, rule.cache_key(&facts(&[("name", "bob")])));
+        assert_ne!(key, rule.cache_key(&facts(&[])));
+        // the same facts under a different rule get a different key
+        let other = crate::string_equals("name", "name", "bob");
+        assert_ne!(key, other.cache_key(&facts(&[("name", "alice")])));
+    }
+
+    #[test]
+    fn shortest_met_path_ends_at_not() {
+        let mut facts = BTreeMap::new();
+        facts.insert("a".to_owned(), "x".to_owned());
+        let rule = crate::and(vec![crate::not(crate::string_equals("d", "a", "y"))]);
+        let result = rule.check(&facts);
+        assert_eq!(result.status, Status::Met);
+        assert_eq!(result.shortest_met_path(), Some(vec![0]));
+
+        let rule = crate::or(vec![
+            crate::and(vec![crate::string_equals("d", "a", "x")]),
+            crate::string_equals("d", "a", "x"),
+        ]);
+        assert_eq!(rule.check(&facts).shortest_met_path(), Some(vec![1]));
+        facts.insert("a".to_owned(), "z".to_owned());
+        assert_eq!(rule.check(&facts).shortest_met_path(), None);
+    }
+
+    #[test]
+    fn compiled_closure_agrees_with_check() {
+        let rule = crate::and(vec![
+            crate::or(vec![
+                crate::string_equals("admin", "role", "admin"),
+                crate::n_of(
+                    2,
+                    vec![
+                        crate::int_range("adult", "age", 18, 120),
+                        crate::boolean("verified", "verified", true),
+                        crate::string_equals("invited", "role", "guest"),
+                    ],
+                ),
+            ]),
+            crate::not(crate::boolean("banned", "banned", true)),
+            crate::coalesce(
+                crate::int_equals("override", "override", 1),
+                crate::majority(vec![
+                    crate::int_range("adult", "age", 18, 120),
+                    crate::boolean("verified", "verified", true),
+                    crate::with_id("guest", crate::string_equals("guest", "role", "guest")),
+                ]),
+            ),
+            crate::depends_on(
+                "guest",
+                Status::NotMet,
+                crate::boolean("verified", "verified", true),
+            ),
+        ]);
+        let compiled = rule.compile();
+
+        let choices: [(&str, &[&str]); 5] = [
+            ("role", &["admin", "guest", "user"]),
+            ("age", &["12", "30", "x"]),
+            ("verified", &["true", "false"]),
+            ("banned", &["true", "false"]),
+            ("override", &["1", "0"]),
+        ];
+        // every combination, with each field also left out
+        let mut fact_sets = vec![BTreeMap::new()];
+        for (field, values) in choices {
+            fact_sets = fact_sets
+                .into_iter()
+                .flat_map(|info: BTreeMap<String, String>| {
+                    let mut variants = vec![info.clone()];
+                    for &val in values {
+                        let mut with = info.clone();
+                        with.insert(field.to_owned(), val.to_owned());
+                        variants.push(with);
+                    }
+                    variants
+                })
+                .collect();
+        }
+        assert_eq!(fact_sets.len(), 4 * 4 * 3 * 3 * 3);
+        let mut seen = Vec::new();
+        for info in &fact_sets {
+            let status = rule.check(info).status;
+            assert_eq!(compiled(info), status, "{:?}", info);
+            seen.push(status);
+        }
+        // the fact sets exercise every outcome
+        for status in [Status::Met, Status::NotMet, Status::Unknown] {
+            assert!(seen.contains(&status));
+        }
+    }
+
+    #[test]
+    fn slowest_nodes_puts_slow_constraint_first() {
+        fn slow(_: &str) -> Status {
+            std::thread::sleep(Duration::from_millis(20));
+            Status::Met
+        }
+        let rule = crate::and(vec![
+            crate::string_equals("fast", "x", "1"),
+            crate::or(vec![
+                crate::named("slow", "x", "slow"),
+                crate::int_equals("fast too", "x", 1),
+            ]),
+        ]);
+        let info = facts(&[("x", "1")]);
+        let comparators = BTreeMap::from([("slow".to_owned(), slow as Comparator)]);
+        let env = Env {
+            profile: true,
+            comparators: Some(&comparators),
+            ..Env::new(&rule, &info)
+        };
+        let result = rule.eval(&env);
+
+        let slowest = result.slowest_nodes(2);
+        assert_eq!(slowest.len(), 2);
+        assert_eq!(slowest[0].0, "slow");
+        assert!(slowest[0].1 >= Duration::from_millis(20));
+        assert!(slowest[0].1 >= slowest[1].1);
+        assert_eq!(result.slowest_nodes(10).len(), 5);
+        assert!(rule.check(&info).slowest_nodes(3).is_empty());
+    }
+
+    #[test]
+    fn first_failure_returns_first_failing_leaf_in_traversal_order() {
+        let rule = crate::or(vec![
+            crate::and(vec![
+                crate::string_equals("name", "name", "alice"),
+                crate::int_range("age", "age", 18, 65),
+            ]),
+            crate::boolean("verified", "verified", true),
+            crate::string_equals("role", "role", "admin"),
+        ]);
+        let info = facts(&[
+            ("name", "alice"),
+            ("age", "70"),
+            ("verified", "true"),
+            ("role", "user"),
+        ]);
+        assert_eq!(rule.check(&info).status, Status::Met);
+        let failure = rule.first_failure(&info).unwrap();
+        assert_eq!(failure.name, "age");
+        assert_eq!(failure.status, Status::NotMet);
+
+        let info = facts(&[("name", "alice"), ("verified", "false"), ("role", "user")]);
+        assert_eq!(rule.first_failure(&info).unwrap().name, "verified");
+
+        let info = facts(&[("name", "alice"), ("age", "30"), ("verified", "true")]);
+        assert!(rule.first_failure(&info).is_none());
+    }
+
+    #[test]
+    fn with_constraint_swaps_leaf_constraint() {
+        let template = crate::string_equals("plan", "plan", "basic");
+        let premium = template.with_constraint(Constraint::StringEquals("premium".into()));
+        assert_eq!(premium, crate::string_equals("plan", "plan", "premium"));
+        assert_eq!(template, crate::string_equals("plan", "plan", "basic"));
+        let result = premium.check(&facts(&[("plan", "premium")]));
+        assert_eq!(result.name, "plan");
+        assert_eq!(result.status, Status::Met);
+
+        let combinator = crate::and(vec![template]);
+        assert_eq!(
+            combinator.with_constraint(Constraint::IsPalindrome),
+            combinator
+        );
+    }
+
+    #[test]
+    fn typecheck_facts_reports_non_numeric_value_for_int_constraint() {
+        let rule = crate::and(vec![
+            crate::int_equals("count", "count", 3),
+            crate::or(vec![
+                crate::int_range("count range", "count", 0, 10),
+                crate::string_equals("name", "name", "alice"),
+            ]),
+            crate::int_range("age", "age", 18, 65),
+        ]);
+        let info = facts(&[("count", "three"), ("name", "bob")]);
+        assert_eq!(
+            rule.typecheck_facts(&info),
+            [(
+                "count".to_owned(),
+                "expected an integer, got \"three\"".to_owned()
+            )]
+        );
+        assert!(rule
+            .typecheck_facts(&facts(&[("count", "3"), ("age", "40")]))
+            .is_empty());
+    }
+
+    #[test]
+    fn subtree_at_extracts_and_serializes_nested_subtree() {
+        let nested = crate::or(vec![
+            crate::string_equals("admin", "role", "admin"),
+            crate::int_range("adult", "age", 18, 120),
+        ]);
+        let rule = crate::and(vec![
+            crate::boolean("verified", "verified", true),
+            crate::not(nested.clone()),
+        ]);
+        assert_eq!(rule.subtree_at(&[]), Some(&rule));
+        assert_eq!(rule.subtree_at(&[1, 0]), Some(&nested));
+        let leaf = rule.subtree_at(&[1, 0, 1]).unwrap();
+        assert_eq!(
+            leaf.to_json(),
+            r#"{"Rule":{"desc":"adult","field":"age","constraint":{"IntRange":[18,120]}}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Rule>(&leaf.to_json()).unwrap(),
+            *leaf
+        );
+
+        assert_eq!(rule.subtree_at(&[2]), None);
+        assert_eq!(rule.subtree_at(&[1, 1]), None);
+        assert_eq!(rule.subtree_at(&[0, 0]), None);
+    }
+
+    #[test]
+    fn first_match_returns_second_matching_rule() {
+        let table = crate::rule_set(vec![
+            ("vip", crate::string_equals("vip", "tier", "gold")),
+            ("adult", crate::int_range("adult", "age", 18, 120)),
+            ("anyone", Rule::Always),
+        ]);
+        let (label, result) = table
+            .first_match(&facts(&[("tier", "silver"), ("age", "30")]))
+            .unwrap();
+        assert_eq!(label, "adult");
+        assert_eq!(result.status, Status::Met);
+        assert_eq!(result.name, "adult");
+
+        let (label, _) = table.first_match(&facts(&[("age", "3")])).unwrap();
+        assert_eq!(label, "anyone");
+        assert!(crate::rule_set(vec![("never", Rule::Never)])
+            .first_match(&facts(&[]))
+            .is_none());
+        assert!(crate::and(vec![Rule::Always])
+            .first_match(&facts(&[]))
+            .is_none());
+    }
+
+    /// Fact source raising `cancel` once `trigger` is looked up, as if another
+    /// thread cancelled the evaluation at that point
+    struct CancelOn<'a> {
+        facts: BTreeMap<String, String>,
+        trigger: &'a str,
+        cancel: &'a AtomicBool,
+    }
+
+    impl FactSource for CancelOn<'_> {
+        fn fact(&self, field: &str) -> Option<Cow<'_, str>> {
+            if field == self.trigger {
+                self.cancel.store(true, Ordering::Relaxed);
+            }
+            self.facts.fact(field)
+        }
+    }
+
+    #[test]
+    fn cancelling_mid_evaluation_leaves_later_leaves_unknown() {
+        let rule = crate::and(vec![
+            crate::string_equals("a", "a", "1"),
+            crate::string_equals("b", "b", "1"),
+            crate::or(vec![
+                crate::string_equals("c", "c", "1"),
+                crate::string_equals("d", "d", "1"),
+            ]),
+        ]);
+        let info = facts(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+        let cancel = AtomicBool::new(false);
+        assert_eq!(rule.check_cancellable(&info, &cancel).status, Status::Met);
+
+        let source = CancelOn {
+            facts: info,
+            trigger: "b",
+            cancel: &cancel,
+        };
+        let env = Env {
+            cancel: Some(&cancel),
+            ..Env::new(&rule, &source)
+        };
+        let result = rule.eval(&env);
+        let leaves = result
+            .leaves()
+            .iter()
+            .map(|l| (l.name.as_str(), l.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            leaves,
+            [
+                ("a", Status::Met),
+                ("b", Status::Met),
+                ("c", Status::Unknown),
+                ("d", Status::Unknown)
+            ]
+        );
+        assert_eq!(result.status, Status::Unknown);
+
+        let result = rule.check_cancellable(&source.facts, &cancel);
+        assert!(result.leaves().iter().all(|l| l.status == Status::Unknown));
+    }
+
+    #[test]
+    fn leaf_contributions_marks_only_first_met_leaf_of_or() {
+        let rule = crate::or(vec![
+            crate::string_equals("a", "a", "1"),
+            crate::string_equals("b", "b", "1"),
+            crate::string_equals("c", "c", "1"),
+            crate::string_equals("d", "d", "1"),
+        ]);
+        let info = facts(&[("a", "0"), ("b", "1"), ("c", "1")]);
+        assert_eq!(
+            rule.check(&info).leaf_contributions(),
+            [
+                ("a".to_owned(), Status::NotMet, false),
+                ("b".to_owned(), Status::Met, true),
+                ("c".to_owned(), Status::Met, false),
+                ("d".to_owned(), Status::Unknown, false),
+            ]
+        );
+
+        // a failing `Or` is decided by all of its children
+        let info = facts(&[("a", "0"), ("b", "0"), ("c", "0"), ("d", "0")]);
+        let contributions = rule.check(&info).leaf_contributions();
+        assert!(contributions.iter().all(|&(_, _, contributed)| contributed));
+    }
+
+    #[test]
+    fn majority_with_odd_and_even_child_counts() {
+        let votes = |n: usize| {
+            crate::majority(
+                (0..n)
+                    .map(|i| crate::boolean("vote", &format!("v{}", i), true))
+                    .collect(),
+            )
+        };
+        let ballot = |votes: &[&str]| {
+            votes
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| !v.is_empty())
+                .map(|(i, v)| (format!("v{}", i), v.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let three = votes(3);
+        assert_eq!(
+            three.check(&ballot(&["true", "true", "false"])).status,
+            Status::Met
+        );
+        assert_eq!(
+            three.check(&ballot(&["true", "false", "false"])).status,
+            Status::NotMet
+        );
+        assert_eq!(
+            three.check(&ballot(&["true", "", "false"])).status,
+            Status::Unknown
+        );
+
+        // four children need three, so a tie is not a majority
+        let four = votes(4);
+        assert_eq!(
+            four.check(&ballot(&["true", "true", "true", "false"]))
+                .status,
+            Status::Met
+        );
+        assert_eq!(
+            four.check(&ballot(&["true", "true", "false", "false"]))
+                .status,
+            Status::NotMet
+        );
+        assert_eq!(
+            four.check(&ballot(&["true", "true", "", "false"])).status,
+            Status::Unknown
+        );
+        assert_eq!(
+            four.check(&ballot(&["true", "true", "", ""])).status,
+            Status::Unknown
+        );
+
+        assert_eq!(votes(1).check(&ballot(&["true"])).status, Status::Met);
+        assert_eq!(votes(1).check(&ballot(&["false"])).status, Status::NotMet);
+    }
+
+    #[test]
+    fn confidence_over_decisive_leaves() {
+        let rule = crate::and(vec![
+            crate::string_equals("a", "a", "1"),
+            crate::string_equals("b", "b", "1"),
+            crate::or(vec![
+                crate::string_equals("c", "c", "1"),
+                crate::string_equals("d", "d", "1"),
+            ]),
+        ]);
+        let all_met = facts(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+        assert_eq!(rule.check(&all_met).confidence(), 1.0);
+
+        let mixed = facts(&[("a", "1"), ("b", "0"), ("c", "1"), ("d", "1")]);
+        assert_eq!(rule.check(&mixed).confidence(), 0.75);
+        // unknown leaves are left out
+        let partial = facts(&[("a", "1"), ("b", "0")]);
+        assert_eq!(rule.check(&partial).confidence(), 0.5);
+
+        assert!(rule.check(&facts(&[])).confidence().is_nan());
+    }
+
+    #[test]
+    fn shortest_met_path_prefers_shallower_met_leaf() {
+        let rule = crate::or(vec![
+            crate::or(vec![
+                crate::string_equals("a", "a", "0"),
+                crate::and(vec![crate::string_equals("b", "b", "1")]),
+            ]),
+            crate::string_equals("c", "c", "0"),
+            crate::and(vec![crate::string_equals("d", "d", "1")]),
+        ]);
+        let info = facts(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+        assert_eq!(rule.check(&info).shortest_met_path(), Some(vec![2, 0]));
+
+        let info = facts(&[("a", "1"), ("b", "1")]);
+        assert_eq!(rule.check(&info).shortest_met_path(), Some(vec![0, 1, 0]));
+        assert_eq!(rule.check(&facts(&[])).shortest_met_path(), None);
+    }
+
+    #[test]
+    fn partial_eval_simplifies_and_keeps_the_rest() {
+        let rest = crate::or(vec![
+            crate::int_range("adult", "age", 18, 120),
+            crate::boolean("consent", "consent", true),
+        ]);
+        let rule = crate::and(vec![
+            crate::string_equals("country", "country", "NZ"),
+            rest.clone(),
+        ]);
+
+        let residual = rule.clone().partial_eval(&facts(&[("country", "NZ")]));
+        assert_eq!(residual, rest);
+        assert_eq!(
+            residual.check(&facts(&[("age", "30")])).status,
+            rule.check(&facts(&[("country", "NZ"), ("age", "30")]))
+                .status
+        );
+
+        assert_eq!(
+            rule.clone().partial_eval(&facts(&[("country", "AU")])),
+            Rule::Never
+        );
+        assert_eq!(rule.clone().partial_eval(&facts(&[])), rule);
+    }
+
+    #[test]
+    fn satisfying_assignments_of_small_boolean_tree() {
+        // a && (b || c)
+        let rule = crate::and(vec![
+            crate::boolean("a", "a", true),
+            crate::or(vec![
+                crate::boolean("b", "b", true),
+                crate::boolean("c", "c", true),
+            ]),
+        ]);
+        let domains = ["a", "b", "c"]
+            .iter()
+            .map(|&f| (f.to_owned(), vec!["false".to_owned(), "true".to_owned()]))
+            .collect::<BTreeMap<_, _>>();
+        let assignments = rule.satisfying_assignments(&domains).unwrap();
+        let mut found = assignments
+            .iter()
+            .map(|info| (info["a"].as_str(), info["b"].as_str(), info["c"].as_str()))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        assert_eq!(
+            found,
+            [
+                ("true", "false", "true"),
+                ("true", "true", "false"),
+                ("true", "true", "true")
+            ]
+        );
+
+        let too_big = (0..64)
+            .map(|i| (format!("f{}", i), vec!["0".to_owned(), "1".to_owned()]))
+            .collect::<BTreeMap<_, _>>();
+        assert!(rule.satisfying_assignments(&too_big).is_err());
+    }
+
+    #[test]
+    fn walk_mut_rewrites_field_names() {
+        let mut rule = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::not(crate::coalesce(
+                crate::int_equals("age", "age", 3),
+                crate::boolean("banned", "banned", true),
+            )),
+        ]);
+        let mut order = Vec::new();
+        rule.walk_mut(&mut |node| {
+            if let Rule::Rule { ref mut field, .. } = *node {
+                *field = format!("user.{}", field);
+            }
+            order.push(node.name());
+        });
+        assert_eq!(
+            rule.referenced_fields().into_iter().collect::<Vec<_>>(),
+            ["user.age", "user.banned", "user.name"]
+        );
+        assert_eq!(order, ["name", "age", "banned", "Coalesce", "Not", "And"]);
+        let info = facts(&[
+            ("user.name", "alice"),
+            ("user.age", "4"),
+            ("user.banned", "false"),
+        ]);
+        assert_eq!(rule.check(&info).status, Status::Met);
+    }
+
+    #[test]
+    fn transforms_apply_before_constraints() {
+        let rule = crate::and(vec![
+            crate::int_equals("price", "price", 10),
+            crate::less_than_field_scaled("discount", "discount", "price", 0.5),
+        ]);
+        let info = facts(&[("price", "$10"), ("discount", "5")]);
+        assert_eq!(rule.check(&info).status, Status::NotMet);
+
+        let strip_dollar: Transform = Box::new(|val| val.trim_start_matches('$').to_owned());
+        let transforms = BTreeMap::from([("price".to_owned(), strip_dollar)]);
+        assert_eq!(
+            rule.check_with_transforms(&info, &transforms).status,
+            Status::Met
+        );
+    }
+
+    #[test]
+    fn distance_to_met_for_and_or_and_number_of() {
+        let leaves = |names: &[&str]| {
+            names
+                .iter()
+                .map(|&n| crate::boolean(n, n, true))
+                .collect::<Vec<_>>()
+        };
+        let info = facts(&[
+            ("a", "true"),
+            ("b", "false"),
+            ("c", "false"),
+            ("d", "false"),
+        ]);
+
+        let and = crate::and(leaves(&["a", "b", "c", "d"]));
+        assert_eq!(and.check(&info).distance_to_met(), 3);
+        let or = crate::or(leaves(&["b", "c", "d"]));
+        assert_eq!(or.check(&info).distance_to_met(), 1);
+        assert_eq!(
+            crate::or(leaves(&["a", "b"]))
+                .check(&info)
+                .distance_to_met(),
+            0
+        );
+        let two_of = crate::n_of(2, leaves(&["a", "b", "c", "d"]));
+        assert_eq!(two_of.check(&info).distance_to_met(), 1);
+        let four_of = crate::n_of(4, leaves(&["a", "b", "c", "d"]));
+        assert_eq!(four_of.check(&info).distance_to_met(), 3);
+
+        // an `Or` takes its cheapest branch
+        let nested = crate::or(vec![
+            crate::and(leaves(&["b", "c"])),
+            crate::and(leaves(&["a", "d"])),
+        ]);
+        assert_eq!(nested.check(&info).distance_to_met(), 1);
+        // unknown leaves count as one each
+        assert_eq!(and.check(&facts(&[])).distance_to_met(), 4);
+    }
+
+    #[test]
+    fn child_met_mask_of_mixed_children() {
+        let rule = crate::or(vec![
+            crate::boolean("a", "a", true),
+            crate::boolean("b", "b", true),
+            crate::boolean("c", "c", true),
+            crate::boolean("d", "d", true),
+        ]);
+        let result = rule.check(&facts(&[("a", "true"), ("b", "false"), ("c", "true")]));
+        assert_eq!(result.child_met_mask(), vec![true, false, true, false]);
+        // leaves have no children
+        assert!(result.children[0].child_met_mask().is_empty());
+    }
+
+    #[test]
+    fn check_traced_records_main_tree_in_evaluation_order() {
+        let tree = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "10")]);
+        let (result, trace) = tree.check_traced(&info);
+        assert_eq!(result.status, Status::Met);
+        let event = |path: &[usize], name: &str, status| TraceEvent {
+            path: path.to_vec(),
+            name: name.into(),
+            status,
+        };
+        assert_eq!(
+            trace,
+            vec![
+                event(&[0], "Name is John Doe", Status::Met),
+                event(&[1, 0], "Favorite number is 10", Status::Met),
+                event(&[1, 1], "Fav number between 11 and 16", Status::NotMet),
+                event(&[1], "Or", Status::Met),
+                event(&[], "And", Status::Met),
+            ]
+        );
+    }
+
+    #[test]
+    fn verbose_check_explains_failing_and() {
+        let rule = crate::and(vec![
+            crate::boolean("a", "a", true),
+            crate::n_of(
+                2,
+                vec![
+                    crate::boolean("b", "b", true),
+                    crate::boolean("c", "c", true),
+                    crate::boolean("d", "d", true),
+                ],
+            ),
+        ]);
+        let info = facts(&[("a", "false"), ("b", "true"), ("c", "true")]);
+        let result = rule.check_verbose(&info);
+        assert_eq!(result.status, Status::NotMet);
+        assert_eq!(
+            result.reason.as_deref(),
+            Some("1 of 2 children met (needs all)")
+        );
+        assert_eq!(
+            result.children[1].reason.as_deref(),
+            Some("2 of 3 children met (needs 2)")
+        );
+        assert_eq!(result.children[0].reason, None);
+        // plain checks skip the reasons
+        assert_eq!(rule.check(&info).reason, None);
+    }
+
+    #[test]
+    fn decisive_fields_names_the_field_that_settles_the_tree() {
+        let rule = crate::and(vec![
+            crate::boolean("a", "a", true),
+            crate::or(vec![
+                crate::boolean("b", "b", true),
+                crate::boolean("c", "c", true),
+            ]),
+        ]);
+        let info = facts(&[("a", "true")]);
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+        let decisive = rule.decisive_fields(&info);
+        assert_eq!(decisive, BTreeSet::from(["b".to_owned()]));
+
+        // supplying that one field settles the tree
+        let settled = facts(&[("a", "true"), ("b", "true")]);
+        assert_eq!(rule.check(&settled).status, Status::Met);
+        assert!(rule.decisive_fields(&settled).is_empty());
+        // a failing `And` child decides it without the others
+        assert!(rule.decisive_fields(&facts(&[("a", "false")])).is_empty());
+        // with nothing known the single field `a` can settle it as `NotMet`
+        assert_eq!(
+            rule.decisive_fields(&facts(&[])),
+            BTreeSet::from(["a".to_owned()])
+        );
+    }
+
+    #[test]
+    fn by_field_groups_leaves_of_two_fields() {
+        let rule = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        let result = rule.check(&facts(&[("name", "John Doe"), ("fav_number", "12")]));
+        assert_eq!(result.field, None);
+        let groups = result.by_field();
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["fav_number", "name"]);
+        let summary = |field: &str| {
+            groups[field]
+                .iter()
+                .map(|r| (r.name.as_str(), r.status))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary("name"), [("Name is John Doe", Status::Met)]);
+        assert_eq!(
+            summary("fav_number"),
+            [
+                ("Favorite number is 10", Status::NotMet),
+                ("Fav number between 11 and 16", Status::Met),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_breadth_rejects_over_wide_node() {
+        let leaves = |n: usize| {
+            (0..n)
+                .map(|i| crate::boolean("flag", &format!("f{}", i), true))
+                .collect::<Vec<_>>()
+        };
+        let rule = crate::and(vec![
+            crate::or(leaves(2)),
+            crate::n_of(1, leaves(4)),
+            crate::boolean("flag", "g", true),
+        ]);
+        assert!(rule.validate_breadth(4).is_ok());
+        let err = rule.validate_breadth(3).unwrap_err().to_string();
+        assert!(err.contains("[1]"), "{}", err);
+        assert!(err.contains("4 children"), "{}", err);
+        assert!(rule.validate_breadth(2).is_err());
+        assert!(crate::boolean("flag", "f", true)
+            .validate_breadth(0)
+            .is_ok());
+    }
+
+    #[test]
+    fn irrelevant_fields_of_satisfied_or_branch() {
+        let rule = crate::and(vec![
+            crate::boolean("verified", "verified", true),
+            crate::or(vec![
+                crate::boolean("has passport", "passport", true),
+                crate::and(vec![
+                    crate::boolean("has license", "license", true),
+                    crate::boolean("has utility bill", "bill", true),
+                ]),
+            ]),
+        ]);
+        let fields = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<BTreeSet<_>>();
+        assert!(rule.irrelevant_fields(&facts(&[])).is_empty());
+        // the passport settles the `Or`, so the other branch no longer matters
+        let info = facts(&[("passport", "true")]);
+        assert_eq!(
+            rule.irrelevant_fields(&info),
+            fields(&["bill", "license", "passport"])
+        );
+        // a failed `And` child makes everything irrelevant
+        let info = facts(&[("verified", "false")]);
+        assert_eq!(
+            rule.irrelevant_fields(&info),
+            fields(&["bill", "license", "passport", "verified"])
+        );
+    }
+
+    #[test]
+    fn batch_summary_counts_mixed_outcomes() {
+        let rule = crate::int_range("adult", "age", 18, 65);
+        let batch = [
+            facts(&[("age", "30")]),
+            facts(&[("age", "12")]),
+            facts(&[("age", "40")]),
+            facts(&[("name", "no age")]),
+            facts(&[("age", "70")]),
+            facts(&[("age", "18")]),
+        ];
+        assert_eq!(
+            rule.check_batch_summary(&batch),
+            BatchSummary {
+                met: 3,
+                not_met: 2,
+                unknown: 1
+            }
+        );
+        assert_eq!(rule.check_batch_summary(&[]), BatchSummary::default());
+    }
+
+    #[test]
+    fn ir_json_round_trips_with_version_envelope() {
+        let rule = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::not(crate::n_of(1, vec![crate::int_range("age", "age", 18, 65)])),
+        ]);
+        let ir = rule.to_ir_json();
+        let value = serde_json::from_str::<serde_json::Value>(&ir).unwrap();
+        assert_eq!(value["version"], IR_VERSION);
+        assert_eq!(value["root"], serde_json::to_value(&rule).unwrap());
+        assert_eq!(Rule::from_ir_json(&ir).unwrap(), rule);
+    }
+
+    #[test]
+    fn ir_json_rejects_future_version() {
+        let rule = crate::boolean("flag", "flag", true);
+        let future = serde_json::json!({ "version": IR_VERSION + 1, "root": rule }).to_string();
+        let err = Rule::from_ir_json(&future).unwrap_err().to_string();
+        assert!(err.contains("unsupported rule IR version 2"), "{}", err);
+        let unversioned = serde_json::json!({ "root": rule }).to_string();
+        assert!(Rule::from_ir_json(&unversioned).is_err());
+        assert!(Rule::from_ir_json(&rule.to_json()).is_err());
+    }
+
+    #[test]
+    fn chain_progress_stops_at_failing_third_step() {
+        let steps = crate::chain(vec![
+            crate::boolean("signed up", "signed_up", true),
+            crate::boolean("verified email", "verified", true),
+            crate::boolean("added avatar", "avatar", true),
+            crate::boolean("invited friend", "invited", true),
+        ]);
+        let info = facts(&[
+            ("signed_up", "true"),
+            ("verified", "true"),
+            ("avatar", "false"),
+            ("invited", "true"),
+        ]);
+        let result = steps.check(&info);
+        assert_eq!(result.status, Status::NotMet);
+        assert_eq!(result.progress, Some(2));
+
+        let done = facts(&[
+            ("signed_up", "true"),
+            ("verified", "true"),
+            ("avatar", "true"),
+            ("invited", "true"),
+        ]);
+        assert_eq!(steps.check(&done).progress, Some(4));
+        assert_eq!(steps.check(&done).status, Status::Met);
+        // an unknown step also ends the streak
+        let result = steps.check(&facts(&[("signed_up", "true")]));
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(result.progress, Some(1));
+        assert_eq!(result.children[0].progress, None);
+    }
+
+    #[test]
+    fn leaf_outcomes_match_leaves_of_check() {
+        let rule = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::not(crate::int_range(
+                    "Fav number between 11 and 16",
+                    "fav_number",
+                    11,
+                    16,
+                )),
+                crate::boolean("flag", "flag", true),
+            ]),
+            Rule::Always,
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "12")]);
+        let expected = rule
+            .check(&info)
+            .leaves()
+            .into_iter()
+            .map(|leaf| (leaf.name.clone(), leaf.status))
+            .collect::<Vec<_>>();
+        assert_eq!(expected.len(), 5);
+        assert_eq!(rule.leaf_outcomes(&info).collect::<Vec<_>>(), expected);
+        assert_eq!(
+            rule.leaf_outcomes(&info).nth(1),
+            Some(("Favorite number is 10".to_owned(), Status::NotMet))
+        );
+    }
+
+    #[test]
+    fn fold_constants_rules_and_equivalence() {
+        let a = || crate::boolean("a", "a", true);
+        let b = || crate::boolean("b", "b", true);
+        let cases = [
+            (crate::and(vec![a(), Rule::Never, b()]), Rule::Never),
+            (
+                crate::and(vec![a(), Rule::Always, b()]),
+                crate::and(vec![a(), b()]),
+            ),
+            (crate::and(vec![Rule::Always, a()]), a()),
+            (crate::or(vec![a(), Rule::Always]), Rule::Always),
+            (
+                crate::or(vec![a(), Rule::Never, b()]),
+                crate::or(vec![a(), b()]),
+            ),
+            (crate::or(vec![Rule::Never, Rule::Never]), Rule::Never),
+            (crate::not(Rule::Always), Rule::Never),
+            (
+                crate::n_of(2, vec![a(), Rule::Always, Rule::Never]),
+                crate::n_of(1, vec![a()]),
+            ),
+            (crate::n_of(2, vec![a(), Rule::Never]), Rule::Never),
+            // folding works bottom-up through nested combinators
+            (
+                crate::and(vec![a(), crate::or(vec![b(), crate::not(Rule::Never)])]),
+                a(),
+            ),
+            (
+                crate::or(vec![a(), crate::and(vec![b(), Rule::Never])]),
+                a(),
+            ),
+        ];
+        let values = [None, Some("true"), Some("false")];
+        for (rule, expected) in cases {
+            let folded = rule.clone().fold_constants();
+            assert_eq!(folded, expected, "{}", rule.to_json());
+            for va in values {
+                for vb in values {
+                    let info = [("a", va), ("b", vb)]
+                        .iter()
+                        .filter_map(|&(k, v)| Some((k.to_owned(), v?.to_owned())))
+                        .collect::<BTreeMap<_, _>>();
+                    assert_eq!(
+                        folded.check(&info).status,
+                        rule.check(&info).status,
+                        "{} with {:?}",
+                        rule.to_json(),
+                        info
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn check_with_metadata_stores_it_on_the_root() {
+        let rule = crate::and(vec![crate::boolean("flag", "flag", true)]);
+        let info = facts(&[("flag", "true")]);
+        let metadata = facts(&[("correlation_id", "req-42")]);
+        let result = rule.check_with_metadata(&info, metadata.clone());
+        assert_eq!(result.status, Status::Met);
+        assert_eq!(result.metadata, Some(metadata));
+        assert_eq!(result.children[0].metadata, None);
+        assert_eq!(rule.check(&info).metadata, None);
+        // it travels with the serialized result
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["metadata"]["correlation_id"], "req-42");
+    }
+
+    #[test]
+    fn coalesce_falls_back_only_on_unknown() {
+        let rule = crate::coalesce(
+            crate::int_range("adult by age", "age", 18, 130),
+            crate::boolean("adult by declaration", "declared_adult", true),
+        );
+        let status = |pairs: &[(&str, &str)]| rule.check(&facts(pairs)).status;
+        // the primary decides whenever it is known
+        assert_eq!(
+            status(&[("age", "30"), ("declared_adult", "false")]),
+            Status::Met
+        );
+        assert_eq!(
+            status(&[("age", "12"), ("declared_adult", "true")]),
+            Status::NotMet
+        );
+        assert_eq!(status(&[("declared_adult", "true")]), Status::Met);
+        assert_eq!(status(&[("declared_adult", "false")]), Status::NotMet);
+        assert_eq!(status(&[]), Status::Unknown);
+        let result = rule.check(&facts(&[("declared_adult", "true")]));
+        assert_eq!(result.children.len(), 2);
+        assert_eq!(result.children[0].status, Status::Unknown);
+    }
+
+    #[test]
+    fn check_fields_leaves_out_of_set_leaves_unknown() {
+        let rule = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "10")]);
+        let only = BTreeSet::from(["fav_number".to_owned()]);
+        let result = rule.check_fields(&info, &only);
+        let leaves = result
+            .leaves()
+            .into_iter()
+            .map(|leaf| leaf.status)
+            .collect::<Vec<_>>();
+        assert_eq!(leaves, [Status::Unknown, Status::Met, Status::NotMet]);
+        assert_eq!(result.status, Status::Unknown);
+        assert_eq!(
+            rule.check_fields(&info, &BTreeSet::new()).leaves()[1].status,
+            Status::Unknown
+        );
+        let all = BTreeSet::from(["name".to_owned(), "fav_number".to_owned()]);
+        assert_eq!(rule.check_fields(&info, &all).status, Status::Met);
+    }
+
+    #[test]
+    fn complexity_of_main_tree() {
+        let tree = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        assert_eq!(
+            tree.complexity(),
+            Complexity {
+                nodes: 5,
+                depth: 3,
+                distinct_fields: 2,
+                distinct_constraint_kinds: 3,
+            }
+        );
+        // the same kind of constraint with other parameters is not a new kind
+        let twice = crate::and(vec![
+            crate::int_equals("a", "a", 1),
+            crate::int_equals("b", "b", 2),
+        ]);
+        assert_eq!(twice.complexity().distinct_constraint_kinds, 1);
+        assert_eq!(Rule::Always.complexity().depth, 1);
+    }
+
+    #[test]
+    fn from_flat_assembles_main_tree_and_detects_cycles() {
+        let rows = vec![
+            FlatRow::And(vec![1, 2]),
+            FlatRow::Leaf(crate::string_equals("Name is John Doe", "name", "John Doe")),
+            FlatRow::Or(vec![3, 4]),
+            FlatRow::Leaf(crate::int_equals("Favorite number is 10", "fav_number", 10)),
+            FlatRow::Leaf(crate::int_range(
+                "Fav number between 11 and 16",
+                "fav_number",
+                11,
+                16,
+            )),
+        ];
+        let main_tree = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        assert_eq!(Rule::from_flat(rows).unwrap(), main_tree);
+
+        // a row may be shared, and rows the root doesn't reach are ignored
+        let shared = vec![
+            FlatRow::NumberOf {
+                n: 2,
+                rules: vec![1, 1],
+            },
+            FlatRow::Leaf(crate::boolean("flag", "flag", true)),
+            FlatRow::Or(vec![2]),
+        ];
+        assert_eq!(
+            Rule::from_flat(shared).unwrap(),
+            crate::n_of(2, vec![crate::boolean("flag", "flag", true); 2])
+        );
+
+        let cycle = vec![
+            FlatRow::And(vec![1]),
+            FlatRow::Or(vec![2]),
+            FlatRow::Majority(vec![0]),
+        ];
+        let err = Rule::from_flat(cycle).unwrap_err().to_string();
+        assert!(err.contains("cycle"), "{}", err);
+        let dangling = vec![FlatRow::And(vec![1, 5]), FlatRow::Leaf(Rule::Always)];
+        let err = Rule::from_flat(dangling).unwrap_err().to_string();
+        assert!(err.contains("row 5"), "{}", err);
+        assert!(Rule::from_flat(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn depends_on_gates_child_on_dependency() {
+        let rule = crate::and(vec![
+            crate::with_id("adult", crate::int_range("adult", "age", 18, 130)),
+            crate::depends_on(
+                "adult",
+                Status::Met,
+                crate::boolean("accepted terms", "terms", true),
+            ),
+        ]);
+        assert!(rule.check_ids().is_ok());
+        let status = |pairs: &[(&str, &str)]| {
+            let result = rule.check(&facts(pairs));
+            (result.status, result.children[1].status)
+        };
+        assert_eq!(
+            status(&[("age", "30"), ("terms", "true")]),
+            (Status::Met, Status::Met)
+        );
+        assert_eq!(
+            status(&[("age", "30"), ("terms", "false")]),
+            (Status::NotMet, Status::NotMet)
+        );
+        // an unmet dependency fails the gated rule whatever its own outcome
+        assert_eq!(
+            status(&[("age", "12"), ("terms", "true")]),
+            (Status::NotMet, Status::NotMet)
+        );
+        assert_eq!(
+            status(&[("terms", "true")]),
+            (Status::Unknown, Status::Unknown)
+        );
+
+        let minors_only = crate::and(vec![
+            crate::with_id("adult", crate::int_range("adult", "age", 18, 130)),
+            crate::depends_on("adult", Status::NotMet, Rule::Always),
+        ]);
+        assert_eq!(
+            minors_only.check(&facts(&[("age", "12")])).children[1].status,
+            Status::Met
+        );
+    }
+
+    #[test]
+    fn check_ids_detects_missing_and_duplicate_ids() {
+        let missing = crate::depends_on("adult", Status::Met, Rule::Always);
+        let err = missing.check_ids().unwrap_err().to_string();
+        assert!(err.contains("unknown rule id \"adult\""), "{}", err);
+        assert_eq!(missing.check(&facts(&[])).status, Status::Unknown);
+        let duplicate = crate::and(vec![
+            crate::with_id("a", Rule::Always),
+            crate::with_id("a", Rule::Never),
+        ]);
+        assert!(duplicate.check_ids().is_err());
+    }
+
+    #[test]
+    fn decision_path_string_of_met_tree() {
+        let tree = crate::and(vec![
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "10")]);
+        assert_eq!(
+            tree.check(&info).decision_path_string(),
+            r#"And -> Or -> "Favorite number is 10"(Met)"#
+        );
+        // a failing `And` is decided by its failing child
+        let info = facts(&[("name", "Jane Doe"), ("fav_number", "10")]);
+        assert_eq!(
+            tree.check(&info).decision_path_string(),
+            r#"And -> "Name is John Doe"(NotMet)"#
+        );
+        assert_eq!(
+            crate::and(Vec::new()).check(&info).decision_path_string(),
+            "And(Met)"
+        );
+    }
+
+    #[test]
+    fn shards_combine_to_the_full_result() {
+        let flag = |f: &str| crate::boolean(f, f, true);
+        let rule = crate::and(vec![
+            flag("a"),
+            crate::or(vec![flag("b"), flag("c")]),
+            crate::not(flag("c")),
+            flag("d"),
+        ]);
+        let shards = rule.shard_by_fields();
+        assert_eq!(
+            shards,
+            [
+                flag("a"),
+                crate::and(vec![
+                    crate::or(vec![flag("b"), flag("c")]),
+                    crate::not(flag("c")),
+                ]),
+                flag("d"),
+            ]
+        );
+        let fields = shards
+            .iter()
+            .map(|s| s.referenced_fields())
+            .collect::<Vec<_>>();
+        for (i, a) in fields.iter().enumerate() {
+            for b in &fields[i + 1..] {
+                assert!(a.is_disjoint(b));
+            }
+        }
+
+        let values = [None, Some("true"), Some("false")];
+        for n in 0..values.len().pow(4) {
+            let info = ["a", "b", "c", "d"]
+                .iter()
+                .enumerate()
+                .filter_map(|(i, &f)| {
+                    let val = values[n / values.len().pow(i as u32) % values.len()]?;
+                    Some((f.to_owned(), val.to_owned()))
+                })
+                .collect::<BTreeMap<_, _>>();
+            let combined = shards.iter().fold(Status::Met, |status, shard| {
+                status & shard.check(&info).status
+            });
+            assert_eq!(combined, rule.check(&info).status, "{:?}", info);
+        }
+
+        let or = crate::or(vec![flag("a"), flag("b")]);
+        assert_eq!(or.shard_by_fields(), vec![or]);
+    }
+
+    /// Fields and parent index of a recorded span
+    #[cfg(feature = "otel")]
+    type RecordedSpan = (BTreeMap<String, String>, Option<usize>);
+
+    /// Spans seen by a `RecordingSubscriber`
+    #[cfg(feature = "otel")]
+    #[derive(Default)]
+    struct Recorded {
+        /// Every span opened so far, its id being its index plus one
+        spans: std::sync::Mutex<Vec<RecordedSpan>>,
+        entered: std::sync::Mutex<Vec<usize>>,
+    }
+
+    /// Span exporter for tests, recording each span's fields and parent
+    #[cfg(feature = "otel")]
+    #[derive(Clone, Default)]
+    struct RecordingSubscriber(std::sync::Arc<Recorded>);
+
+    #[cfg(feature = "otel")]
+    struct FieldRecorder<'a>(&'a mut BTreeMap<String, String>);
+
+    #[cfg(feature = "otel")]
+    impl tracing::field::Visit for FieldRecorder<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_owned(), format!("{:?}", value));
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    impl tracing::Subscriber for RecordingSubscriber {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = BTreeMap::new();
+            attrs.record(&mut FieldRecorder(&mut fields));
+            let parent = self.0.entered.lock().unwrap().last().copied();
+            let mut spans = self.0.spans.lock().unwrap();
+            spans.push((fields, parent));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.0.spans.lock().unwrap();
+            values.record(&mut FieldRecorder(
+                &mut spans[span.into_u64() as usize - 1].0,
+            ));
+        }
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, span: &tracing::span::Id) {
+            self.0
+                .entered
+                .lock()
+                .unwrap()
+                .push(span.into_u64() as usize - 1);
+        }
+
+        fn exit(&self, _: &tracing::span::Id) {
+            self.0.entered.lock().unwrap().pop();
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    #[test]
+    fn check_instrumented_opens_one_span_per_node() {
+        let tree = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "10")]);
+        let exporter = RecordingSubscriber::default();
+        let result =
+            tracing::subscriber::with_default(exporter.clone(), || tree.check_instrumented(&info));
+        assert_eq!(result.status, Status::Met);
+
+        let spans = exporter.0.spans.lock().unwrap();
+        let summary = spans
+            .iter()
+            .map(|(fields, parent)| (fields["name"].as_str(), fields["status"].as_str(), *parent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("And", "Met", None),
+                ("Name is John Doe", "Met", Some(0)),
+                ("Or", "Met", Some(0)),
+                ("Favorite number is 10", "Met", Some(2)),
+                ("Fav number between 11 and 16", "NotMet", Some(2)),
+            ]
+        );
+        assert!(exporter.0.entered.lock().unwrap().is_empty());
+        drop(spans);
+
+        // plain checks open no spans
+        let quiet = RecordingSubscriber::default();
+        tracing::subscriber::with_default(quiet.clone(), || tree.check(&info));
+        assert!(quiet.0.spans.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn named_comparator_registered_and_unregistered() {
+        fn is_even(val: &str) -> Status {
+            match val.parse::<i64>() {
+                Ok(n) if n % 2 == 0 => Status::Met,
+                Ok(_) => Status::NotMet,
+                Err(_) => Status::Unknown,
+            }
+        }
+        let mut comparators = BTreeMap::new();
+        comparators.insert("even".to_owned(), is_even as Comparator);
+        let rule = crate::named("even number", "n", "even");
+        let check = |n: &str| {
+            rule.check_with_comparators(&facts(&[("n", n)]), &comparators)
+                .status
+        };
+        assert_eq!(check("4"), Status::Met);
+        assert_eq!(check("7"), Status::NotMet);
+        assert_eq!(check("four"), Status::Unknown);
+        // only the name is serialized
+        assert_eq!(
+            serde_json::to_value(&rule).unwrap()["Rule"]["constraint"],
+            serde_json::json!({ "Named": "even" })
+        );
+
+        let info = facts(&[("n", "4")]);
+        let unregistered = crate::named("odd number", "n", "odd");
+        assert_eq!(
+            unregistered
+                .check_with_comparators(&info, &comparators)
+                .status,
+            Status::Unknown
+        );
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+    }
+
+    #[test]
+    fn example_facts_make_the_tree_met() {
+        let tree = crate::and(vec![
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+            crate::or(vec![
+                crate::int_equals("Favorite number is 10", "fav_number", 10),
+                crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            ]),
+            crate::boolean("verified", "verified", true),
+        ]);
+        let example = tree.example_facts().unwrap();
+        assert_eq!(example["name"], "John Doe");
+        assert_eq!(tree.check(&example).status, Status::Met);
+
+        // values suggested for one leaf may be needed to satisfy another
+        let overlapping = crate::and(vec![
+            crate::int_range("in range", "n", 11, 16),
+            crate::compare(
+                "at least 12",
+                "n",
+                crate::Op::Ge,
+                crate::ValueType::Int,
+                "12",
+            ),
+        ]);
+        let example = overlapping.example_facts().unwrap();
+        assert_eq!(overlapping.check(&example).status, Status::Met);
+
+        assert_eq!(Rule::Never.example_facts(), None);
+        let contradiction = crate::and(vec![
+            crate::string_equals("a", "name", "a"),
+            crate::string_equals("b", "name", "b"),
+        ]);
+        assert_eq!(contradiction.example_facts(), None);
+        // nothing can be derived for context lookups
+        let rising = crate::greater_than_previous("rising", "reading", "last_reading");
+        assert_eq!(rising.example_facts(), None);
+    }
+
+    #[test]
+    fn decisive_path_dot_of_short_circuited_or() {
+        let rule = crate::or(vec![
+            crate::int_equals("Favorite number is 10", "fav_number", 10),
+            crate::int_range("Fav number between 11 and 16", "fav_number", 11, 16),
+            crate::string_equals("Name is John Doe", "name", "John Doe"),
+        ]);
+        let info = facts(&[("name", "John Doe"), ("fav_number", "10")]);
+        assert_eq!(
+            rule.check(&info).decisive_path_dot(),
+            "digraph {\n    node [style=filled];\n    \
+             n0 [label=\"Or\", fillcolor=palegreen];\n    \
+             n0 -> n1;\n    \
+             n1 [label=\"Favorite number is 10\", fillcolor=palegreen];\n}\n"
+        );
+        // a failing `Or` is explained by all of its children
+        let dot = rule
+            .check(&facts(&[("fav_number", "20")]))
+            .decisive_path_dot();
+        assert!(dot.contains("fillcolor=lightcoral"), "{}", dot);
+        assert!(dot.contains("fillcolor=lightgrey"), "{}", dot);
+        assert_eq!(dot.matches(" -> ").count(), 3);
+    }
+
+    #[test]
+    fn unless_desugars_and_exception_suppresses_met_base() {
+        let base = crate::int_range("adult", "age", 18, 130);
+        let exception = crate::boolean("banned", "banned", true);
+        let rule = base.clone().unless(exception.clone());
+        assert_eq!(rule, crate::and(vec![base, crate::not(exception)]));
+
+        let status = |pairs: &[(&str, &str)]| rule.check(&facts(pairs)).status;
+        assert_eq!(status(&[("age", "30"), ("banned", "false")]), Status::Met);
+        assert_eq!(status(&[("age", "30"), ("banned", "true")]), Status::NotMet);
+        assert_eq!(
+            status(&[("age", "12"), ("banned", "false")]),
+            Status::NotMet
+        );
+        assert_eq!(status(&[("age", "30")]), Status::Unknown);
+        let result = rule.check(&facts(&[("age", "30"), ("banned", "false")]));
+        assert_eq!(result.shortest_met_path(), Some(vec![0]));
+    }
+
+    #[test]
+    fn satisfaction_ratios_of_mixed_and_or_tree() {
+        let flag = |f: &str| crate::boolean(f, f, true);
+        let rule = crate::and(vec![
+            crate::or(vec![flag("a"), flag("b"), flag("c"), flag("d")]),
+            flag("e"),
+            crate::and(vec![flag("a"), flag("e")]),
+        ]);
+        let info = facts(&[
+            ("a", "true"),
+            ("b", "false"),
+            ("c", "false"),
+            ("d", "false"),
+            ("e", "false"),
+        ]);
+        assert_eq!(
+            rule.check(&info).satisfaction_ratios(),
+            [
+                ("And".to_owned(), 1.0 / 3.0),
+                ("Or".to_owned(), 0.25),
+                ("And".to_owned(), 0.5),
+            ]
+        );
+        assert!(flag("a").check(&info).satisfaction_ratios().is_empty());
+        let empty = crate::or(Vec::new()).check(&info).satisfaction_ratios();
+        assert!(empty[0].1.is_nan());
+    }
+
+    #[test]
+    fn risk_score_rises_with_unknowns() {
+        let signals = crate::or(vec![
+            crate::boolean("vpn", "vpn", true),
+            crate::boolean("new device", "new_device", true),
+            crate::boolean("foreign ip", "foreign_ip", true),
+            crate::boolean("failed logins", "failed_logins", true),
+        ]);
+        let known = facts(&[
+            ("vpn", "true"),
+            ("new_device", "false"),
+            ("foreign_ip", "true"),
+            ("failed_logins", "false"),
+        ]);
+        assert_eq!(signals.risk_score(&known, 10.0, 4.0), 20.0);
+        let partial = facts(&[("vpn", "true"), ("new_device", "false")]);
+        assert_eq!(signals.risk_score(&partial, 10.0, 4.0), 18.0);
+        assert_eq!(signals.risk_score(&partial, 10.0, 0.0), 10.0);
+        assert_eq!(signals.risk_score(&facts(&[]), 10.0, 4.0), 16.0);
+    }
+
+    #[test]
+    fn first_satisfied_index_at_third_snapshot() {
+        let compliant = crate::and(vec![
+            crate::boolean("mfa enabled", "mfa", true),
+            crate::int_range("password age", "password_age_days", 0, 90),
+        ]);
+        let snapshots = [
+            facts(&[("mfa", "false"), ("password_age_days", "200")]),
+            facts(&[("mfa", "true")]),
+            facts(&[("mfa", "true"), ("password_age_days", "1")]),
+            facts(&[("mfa", "false"), ("password_age_days", "2")]),
+            facts(&[("mfa", "true"), ("password_age_days", "3")]),
+        ];
+        assert_eq!(compliant.first_satisfied_index(&snapshots), Some(2));
+        assert_eq!(compliant.first_satisfied_index(&snapshots[..2]), None);
+        assert_eq!(compliant.first_satisfied_index(&[]), None);
+    }
+
+    #[test]
+    fn pack_leaves_round_trips() {
+        let flag = |f: &str| crate::boolean(f, f, true);
+        let rule = crate::and(vec![
+            flag("a"),
+            crate::or(vec![flag("b"), flag("c"), crate::not(flag("d"))]),
+            flag("e"),
+            Rule::Never,
+        ]);
+        let info = facts(&[("a", "true"), ("b", "false"), ("d", "false"), ("e", "true")]);
+        let result = rule.check(&info);
+        let packed = result.pack_leaves();
+        // six leaves in two bytes: Met, NotMet, Unknown, NotMet | Met, NotMet
+        assert_eq!(packed, [0b10_00_10_01, 0b10_01]);
+        let expected = result
+            .leaves()
+            .into_iter()
+            .map(|leaf| (leaf.name.clone(), leaf.status))
+            .collect::<Vec<_>>();
+        assert_eq!(rule.unpack_leaves(&packed).unwrap(), expected);
+
+        assert!(rule.unpack_leaves(&packed[..1]).is_err());
+        assert!(rule.unpack_leaves(&[0b11, 0]).is_err());
+        assert!(Rule::And { rules: Vec::new() }
+            .check(&info)
+            .pack_leaves()
+            .is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn check_streaming_ends_with_the_aggregate_of_check() {
+        use tokio_stream::StreamExt;
+
+        let rule = crate::and(vec![
+            crate::or(vec![
+                crate::string_equals("name", "name", "alice"),
+                crate::boolean("verified", "verified", true),
+            ]),
+            crate::int_range("age", "age", 18, 65),
+            crate::not(crate::string_equals("banned", "status", "banned")),
+        ]);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for info in [
+            facts(&[("name", "bob"), ("verified", "true"), ("age", "30")]),
+            facts(&[("name", "alice"), ("age", "70"), ("status", "ok")]),
+            facts(&[("verified", "false")]),
+        ] {
+            let events =
+                runtime.block_on(async { rule.check_streaming(&info).collect::<Vec<_>>().await });
+            let (_, trace) = rule.check_traced(&info);
+            assert_eq!(events.len(), trace.len());
+            assert_eq!(events.last(), Some(&(Vec::new(), rule.check(&info).status)));
+            for event in trace {
+                assert!(events.contains(&(event.path, event.status)));
+            }
+        }
+    }
+
+    #[test]
+    fn fields_to_resolve_settles_the_tree_once_supplied() {
+        let rule = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::or(vec![
+                crate::int_range("age", "age", 18, 65),
+                crate::boolean("verified", "verified", true),
+            ]),
+        ]);
+        let info = facts(&[("name", "alice"), ("verified", "false")]);
+        assert_eq!(rule.check(&info).status, Status::Unknown);
+        let needed = rule.fields_to_resolve(&info);
+        assert_eq!(needed, BTreeSet::from(["age".to_owned()]));
+
+        for age in ["30", "12"] {
+            let mut supplied = info.clone();
+            supplied.insert("age".into(), age.into());
+            assert_ne!(rule.check(&supplied).status, Status::Unknown);
+            assert!(rule.fields_to_resolve(&supplied).is_empty());
+        }
+        let mut unrelated = info.clone();
+        unrelated.insert("country".into(), "NL".into());
+        assert_eq!(rule.check(&unrelated).status, Status::Unknown);
+        assert_eq!(rule.fields_to_resolve(&unrelated), needed);
+
+        assert!(rule
+            .fields_to_resolve(&facts(&[("name", "bob")]))
+            .is_empty());
+    }
+
+    #[test]
+    fn leaf_pass_rates_over_a_small_batch() {
+        let rule = crate::and(vec![
+            crate::string_equals("name", "name", "alice"),
+            crate::int_range("age", "age", 18, 65),
+            crate::boolean("verified", "verified", true),
+        ]);
+        let batch = [
+            facts(&[("name", "alice"), ("age", "30"), ("verified", "true")]),
+            facts(&[("name", "alice"), ("age", "12"), ("verified", "true")]),
+            facts(&[("name", "alice"), ("age", "40"), ("verified", "false")]),
+            facts(&[("name", "alice"), ("verified", "true")]),
+        ];
+        let rates = rule.leaf_pass_rates(&batch);
+        assert_eq!(
+            rates,
+            BTreeMap::from([
+                ("name".to_owned(), 1.0),
+                ("age".to_owned(), 0.5),
+                ("verified".to_owned(), 0.75),
+            ])
+        );
+        assert!(rule.leaf_pass_rates(&[]).is_empty());
+    }
 }
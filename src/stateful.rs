@@ -0,0 +1,306 @@
+use crate::constraint::Constraint;
+use crate::rule::{Env, Rule, RuleResult};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Evaluator remembering the most recent values of each field across checks.
+///
+/// This is what gives `Constraint::NotRecentlySeen` its meaning: every check
+/// first evaluates the tree against the values recorded so far, then records the
+/// current value of each field referenced by such a constraint, or the elements
+/// and captures such a constraint nested in `ArrayAll`, `ArrayAny` or
+/// `RegexCapture` sees. At most `capacity` values are kept per field, so windows
+/// larger than that are capped.
+///
+/// Checking needs `&mut self`, the evaluator does no locking of its own. To share
+/// one between threads, wrap it in a `Mutex`.
+#[derive(Debug)]
+pub struct HistoryEvaluator {
+    capacity: usize,
+    history: BTreeMap<String, VecDeque<String>>,
+}
+
+impl HistoryEvaluator {
+    pub fn new(capacity: usize) -> Self {
+        HistoryEvaluator {
+            capacity,
+            history: BTreeMap::new(),
+        }
+    }
+
+    /// Checks `rule` against the recorded history, then records the current facts
+    pub fn check(&mut self, rule: &Rule, info: &BTreeMap<String, String>) -> RuleResult {
+        let env = Env {
+            history: Some(&self.history),
+            ..Env::new(rule, info)
+        };
+        let result = rule.eval(&env);
+
+        let mut seen_now = Vec::new();
+        rule.visit_leaves(&mut |field, constraint| {
+            if let Some(val) = info.get(field) {
+                constraint.visit_values(val, &mut |c, v| {
+                    if let Constraint::NotRecentlySeen(_) = *c {
+                        seen_now.push((field, v));
+                    }
+                });
+            }
+        });
+        let mut recorded = BTreeSet::new();
+        for (field, val) in seen_now {
+            if recorded.insert((field, val)) {
+                let seen = self.history.entry(field.to_owned()).or_default();
+                seen.push_back(val.to_owned());
+                while seen.len() > self.capacity {
+                    seen.pop_front();
+                }
+            }
+        }
+        result
+    }
+
+    /// Forgets all recorded values
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+}
+
+/// Evaluator counting occurrences of each value in fixed time windows across
+/// checks, for `Constraint::UnderRateLimit`.
+///
+/// Time is cut into consecutive buckets of the constraint's `window`, counted from
+/// the Unix epoch, and every check first evaluates the tree against the counts of
+/// the current bucket, then counts the current value of each field referenced by
+/// such a constraint, or the elements and captures such a constraint nested in
+/// `ArrayAll`, `ArrayAny` or `RegexCapture` sees. A new bucket starts from zero,
+/// and the counts of past buckets are dropped once it does.
+///
+/// Checking needs `&mut self`, the evaluator does no locking of its own. To share
+/// one between threads, wrap it in a `Mutex`.
+#[derive(Debug, Default)]
+pub struct RateLimitEvaluator {
+    counters: RateCounters,
+}
+
+impl RateLimitEvaluator {
+    pub fn new() -> Self {
+        RateLimitEvaluator::default()
+    }
+
+    /// Checks `rule` against the counts so far, then counts the current facts
+    pub fn check(&mut self, rule: &Rule, info: &BTreeMap<String, String>) -> RuleResult {
+        self.check_at(rule, info, SystemTime::now())
+    }
+
+    /// Like `check()`, with `now` in place of the system clock
+    pub fn check_at(
+        &mut self,
+        rule: &Rule,
+        info: &BTreeMap<String, String>,
+        now: SystemTime,
+    ) -> RuleResult {
+        self.counters.now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.counters.evict_stale();
+        let env = Env {
+            rate_counters: Some(&self.counters),
+            ..Env::new(rule, info)
+        };
+        let result = rule.eval(&env);
+
+        let mut occurrences = BTreeSet::new();
+        rule.visit_leaves(&mut |field, constraint| {
+            if let Some(val) = info.get(field) {
+                constraint.visit_values(val, &mut |c, v| {
+                    if let Constraint::UnderRateLimit { window, .. } = *c {
+                        occurrences.insert((field, window, v));
+                    }
+                });
+            }
+        });
+        for (field, window, val) in occurrences {
+            let bucket = self.counters.bucket(window);
+            let key = (field.to_owned(), window, val.to_owned());
+            self.counters.current.insert(window, bucket);
+            let count = self.counters.counts.entry(key).or_insert((bucket, 0));
+            if count.0 != bucket {
+                *count = (bucket, 0);
+            }
+            count.1 += 1;
+        }
+        result
+    }
+
+    /// Forgets all counts
+    pub fn clear(&mut self) {
+        self.counters.counts.clear();
+        self.counters.current.clear();
+    }
+}
+
+/// Occurrence counts kept by a `RateLimitEvaluator`
+#[derive(Debug, Default)]
+pub(crate) struct RateCounters {
+    /// Time of the check in progress, since the Unix epoch
+    now: Duration,
+    /// Bucket and count per field, window and value
+    counts: HashMap<(String, Duration, String), (u128, usize)>,
+    /// Bucket each window was in at the last check
+    current: BTreeMap<Duration, u128>,
+}
+
+impl RateCounters {
+    /// Index of the bucket of length `window` containing the check in progress
+    fn bucket(&self, window: Duration) -> u128 {
+        bucket_at(self.now, window)
+    }
+
+    /// Drops the counts of past buckets, sweeping only when some window moved on
+    /// to a new bucket since the last check
+    fn evict_stale(&mut self) {
+        let now = self.now;
+        let mut rolled_over = false;
+        for (&window, current) in &mut self.current {
+            rolled_over |= *current != bucket_at(now, window);
+            *current = bucket_at(now, window);
+        }
+        if rolled_over {
+            self.counts
+                .retain(|&(_, window, _), &mut (bucket, _)| bucket == bucket_at(now, window));
+        }
+    }
+
+    /// Occurrences of `val` in `field` during the current bucket of length `window`
+    pub(crate) fn count(&self, field: &str, window: Duration, val: &str) -> usize {
+        let key = (field.to_owned(), window, val.to_owned());
+        match self.counts.get(&key) {
+            Some(&(bucket, count)) if bucket == self.bucket(window) => count,
+            _ => 0,
+        }
+    }
+}
+
+/// Index of the bucket of length `window` containing `now`. A zero window, which
+/// `Constraint::validate()` rejects, never starts a new bucket
+fn bucket_at(now: Duration, window: Duration) -> u128 {
+    now.as_nanos().checked_div(window.as_nanos()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+
+    fn facts(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn not_recently_seen_within_window_and_after_aging_out() {
+        let rule = crate::not_recently_seen("new user", "user", 2);
+        let mut evaluator = HistoryEvaluator::new(10);
+        let check =
+            |e: &mut HistoryEvaluator, user| e.check(&rule, &facts(&[("user", user)])).status;
+
+        assert_eq!(check(&mut evaluator, "a"), Status::Met);
+        assert_eq!(check(&mut evaluator, "a"), Status::NotMet);
+        assert_eq!(check(&mut evaluator, "b"), Status::Met);
+        assert_eq!(check(&mut evaluator, "c"), Status::Met);
+        // the last two values are "b" and "c", so "a" aged out
+        assert_eq!(check(&mut evaluator, "a"), Status::Met);
+    }
+
+    #[test]
+    fn capacity_caps_the_window() {
+        let rule = crate::not_recently_seen("new user", "user", 5);
+        let mut evaluator = HistoryEvaluator::new(1);
+        evaluator.check(&rule, &facts(&[("user", "a")]));
+        evaluator.check(&rule, &facts(&[("user", "b")]));
+        let result = evaluator.check(&rule, &facts(&[("user", "a")]));
+        assert_eq!(result.status, Status::Met);
+    }
+
+    #[test]
+    fn records_elements_of_nested_array_constraints() {
+        let rule = crate::array_all("new tags", "tags", Constraint::NotRecentlySeen(3));
+        let mut evaluator = HistoryEvaluator::new(10);
+        let result = evaluator.check(&rule, &facts(&[("tags", "x,y")]));
+        assert_eq!(result.status, Status::Met);
+        let result = evaluator.check(&rule, &facts(&[("tags", "y,z")]));
+        assert_eq!(result.status, Status::NotMet);
+
+        let rule = crate::array_any("some new tag", "tags", Constraint::NotRecentlySeen(10));
+        let mut evaluator = HistoryEvaluator::new(10);
+        evaluator.check(&rule, &facts(&[("tags", "x,y")]));
+        let result = evaluator.check(&rule, &facts(&[("tags", "y,z")]));
+        assert_eq!(result.status, Status::Met);
+        let result = evaluator.check(&rule, &facts(&[("tags", "x,z")]));
+        assert_eq!(result.status, Status::NotMet);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn records_captures_of_nested_regex_constraints() {
+        let rule = crate::regex_capture(
+            "new domain",
+            "email",
+            r"@(?<domain>.+)$",
+            "domain",
+            Constraint::NotRecentlySeen(3),
+        )
+        .unwrap();
+        let mut evaluator = HistoryEvaluator::new(10);
+        let result = evaluator.check(&rule, &facts(&[("email", "a@x.org")]));
+        assert_eq!(result.status, Status::Met);
+        let result = evaluator.check(&rule, &facts(&[("email", "b@x.org")]));
+        assert_eq!(result.status, Status::NotMet);
+        let result = evaluator.check(&rule, &facts(&[("email", "b@y.org")]));
+        assert_eq!(result.status, Status::Met);
+    }
+
+    #[test]
+    fn rate_limit_across_bucket_boundaries() {
+        let rule = crate::under_rate_limit("throttle", "ip", 2, Duration::from_secs(60)).unwrap();
+        let mut evaluator = RateLimitEvaluator::new();
+        let at = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut check = |ip, secs| {
+            evaluator
+                .check_at(&rule, &facts(&[("ip", ip)]), at(secs))
+                .status
+        };
+
+        assert_eq!(check("1.2.3.4", 0), Status::Met);
+        assert_eq!(check("1.2.3.4", 10), Status::Met);
+        assert_eq!(check("1.2.3.4", 59), Status::NotMet);
+        // other keys have their own counters
+        assert_eq!(check("5.6.7.8", 59), Status::Met);
+        // the next bucket starts from zero
+        assert_eq!(check("1.2.3.4", 60), Status::Met);
+        assert_eq!(check("1.2.3.4", 119), Status::Met);
+        assert_eq!(check("1.2.3.4", 119), Status::NotMet);
+    }
+
+    #[test]
+    fn rate_limit_drops_counts_of_past_buckets() {
+        let rule = crate::under_rate_limit("throttle", "ip", 2, Duration::from_secs(60)).unwrap();
+        let mut evaluator = RateLimitEvaluator::new();
+        for i in 0..100 {
+            let ip = format!("10.0.0.{}", i);
+            evaluator.check_at(&rule, &facts(&[("ip", &ip)]), UNIX_EPOCH);
+        }
+        assert_eq!(evaluator.counters.counts.len(), 100);
+        let later = UNIX_EPOCH + Duration::from_secs(60);
+        evaluator.check_at(&rule, &facts(&[("ip", "10.0.0.1")]), later);
+        assert_eq!(evaluator.counters.counts.len(), 1);
+    }
+
+    #[test]
+    fn rate_limit_rejects_zero_window() {
+        assert!(crate::under_rate_limit("throttle", "ip", 2, Duration::ZERO).is_err());
+        let json = r#"{"Rule":{"desc":"throttle","field":"ip","constraint":
+            {"UnderRateLimit":{"limit":2,"window":{"secs":0,"nanos":0}}}}}"#;
+        assert!(serde_json::from_str::<crate::Rule>(json).is_err());
+    }
+}
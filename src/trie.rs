@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Prefix tree over `char`s, used for matching against large sets of prefixes
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trie {
+    terminal: bool,
+    children: BTreeMap<char, Trie>,
+}
+
+impl Trie {
+    pub fn new() -> Self {
+        Trie::default()
+    }
+
+    /// Adds `prefix` to the set of stored prefixes
+    pub fn insert(&mut self, prefix: &str) {
+        let node = prefix
+            .chars()
+            .fold(self, |node, c| node.children.entry(c).or_default());
+        node.terminal = true;
+    }
+
+    /// Longest stored prefix of `val`, if any
+    pub fn longest_prefix_of<'a>(&self, val: &'a str) -> Option<&'a str> {
+        let mut node = self;
+        let mut longest = if node.terminal { Some("") } else { None };
+        for (i, c) in val.char_indices() {
+            node = match node.children.get(&c) {
+                Some(child) => child,
+                None => break,
+            };
+            if node.terminal {
+                longest = Some(&val[..i + c.len_utf8()]);
+            }
+        }
+        longest
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for Trie {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        let mut trie = Trie::new();
+        iter.into_iter().for_each(|p| trie.insert(p.as_ref()));
+        trie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::Constraint;
+    use crate::status::Status;
+
+    /// Longest of `prefixes` that `val` starts with, by brute force
+    fn longest_by_scan<'a>(prefixes: &[String], val: &'a str) -> Option<&'a str> {
+        prefixes
+            .iter()
+            .filter(|p| val.starts_with(p.as_str()))
+            .map(|p| &val[..p.len()])
+            .max_by_key(|p| p.len())
+    }
+
+    #[test]
+    fn longest_prefix_agrees_with_scanning_many_prefixes() {
+        let prefixes = (0..50)
+            .flat_map(|a| (0..20).map(move |b| format!("10.{}.{}.", a, b * 7)))
+            .chain((0..50).map(|a| format!("10.{}.", a * 3)))
+            .collect::<Vec<_>>();
+        let trie = prefixes.iter().collect::<Trie>();
+        for a in 0..60 {
+            for b in 0..150 {
+                let val = format!("10.{}.{}.1", a, b);
+                assert_eq!(
+                    trie.longest_prefix_of(&val),
+                    longest_by_scan(&prefixes, &val),
+                    "{}",
+                    val
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn longest_prefix_edge_cases() {
+        let trie = ["ab", "abcd", "é"].iter().collect::<Trie>();
+        assert_eq!(trie.longest_prefix_of("abc"), Some("ab"));
+        assert_eq!(trie.longest_prefix_of("abcd"), Some("abcd"));
+        assert_eq!(trie.longest_prefix_of("abcde"), Some("abcd"));
+        assert_eq!(trie.longest_prefix_of("a"), None);
+        assert_eq!(trie.longest_prefix_of(""), None);
+        assert_eq!(trie.longest_prefix_of("étude"), Some("é"));
+        assert_eq!(trie.longest_prefix_of("e"), None);
+
+        assert_eq!(Trie::new().longest_prefix_of("abc"), None);
+        let empty_prefix = [""].iter().collect::<Trie>();
+        assert_eq!(empty_prefix.longest_prefix_of("abc"), Some(""));
+        assert_eq!(empty_prefix.longest_prefix_of(""), Some(""));
+    }
+
+    #[test]
+    fn prefix_in_trie_constraint() {
+        let constraint = Constraint::PrefixInTrie(["192.168.", "10."].iter().collect());
+        assert_eq!(constraint.check("192.168.1.1"), Status::Met);
+        assert_eq!(constraint.check("10.0.0.1"), Status::Met);
+        assert_eq!(constraint.check("172.16.0.1"), Status::NotMet);
+        assert_eq!(constraint.check("192.16"), Status::NotMet);
+    }
+}
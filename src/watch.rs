@@ -0,0 +1,192 @@
+use crate::rule::Rule;
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Parses a rule tree from a file, as YAML for `.yaml`/`.yml` files and as JSON
+/// otherwise
+pub fn load_rule_file(path: &Path) -> anyhow::Result<Rule> {
+    let text = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml");
+    let rule = if is_yaml {
+        serde_yaml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?
+    } else {
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?
+    };
+    Ok(rule)
+}
+
+/// Serves the rule tree stored in a file, reloading it whenever the file changes.
+///
+/// The parent directory is watched rather than the file itself, so editors that
+/// save by replacing the file are picked up too. If the new contents fail to
+/// parse, the previous rule keeps being served and the error is available from
+/// `last_error()` until the next successful reload.
+///
+/// `rule()` is lock-free, so checking rules on a hot path does not contend with
+/// reloads
+pub struct RuleWatcher {
+    shared: Arc<Shared>,
+    _watcher: RecommendedWatcher,
+}
+
+struct Shared {
+    path: PathBuf,
+    rule: ArcSwap<Rule>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Shared {
+    fn reload(&self) -> anyhow::Result<()> {
+        let result = load_rule_file(&self.path);
+        *self.last_error.lock().unwrap() = result.as_ref().err().map(|e| format!("{:#}", e));
+        self.rule.store(Arc::new(result?));
+        Ok(())
+    }
+}
+
+impl RuleWatcher {
+    /// Loads the rule from `path` and starts watching it. Fails if the file can't
+    /// be read or parsed initially, or watching is not possible
+    pub fn new(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let rule = load_rule_file(&path)?;
+        let shared = Arc::new(Shared {
+            rule: ArcSwap::from_pointee(rule),
+            last_error: Mutex::new(None),
+            path,
+        });
+        let handler = {
+            let shared = Arc::clone(&shared);
+            move |event: notify::Result<notify::Event>| {
+                let touches_file = event.is_ok_and(|event| {
+                    !matches!(event.kind, EventKind::Access(_))
+                        && event
+                            .paths
+                            .iter()
+                            .any(|p| p.file_name() == shared.path.file_name())
+                });
+                if touches_file {
+                    // a failed reload is recorded in `last_error`
+                    let _ = shared.reload();
+                }
+            }
+        };
+        let mut watcher = notify::recommended_watcher(handler)?;
+        let dir = match shared.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(RuleWatcher {
+            shared,
+            _watcher: watcher,
+        })
+    }
+
+    /// The most recently loaded rule
+    pub fn rule(&self) -> Arc<Rule> {
+        self.shared.rule.load_full()
+    }
+
+    /// Reloads the file right away instead of waiting for a change notification.
+    ///
+    /// On error the previous rule is kept
+    pub fn reload(&self) -> anyhow::Result<()> {
+        self.shared.reload()
+    }
+
+    /// Why the latest reload failed, `None` if it succeeded
+    pub fn last_error(&self) -> Option<String> {
+        self.shared.last_error.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Fresh directory for one test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("rule_engine_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Waits up to a few seconds for `done`
+    fn eventually(done: impl Fn() -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if done() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        false
+    }
+
+    #[test]
+    fn serves_updated_rule_and_keeps_it_on_bad_edit() {
+        let dir = TempDir::new("watch");
+        let path = dir.0.join("rule.json");
+        let first = crate::string_equals("name", "name", "alice");
+        let second = crate::string_equals("name", "name", "bob");
+        fs::write(&path, first.to_json()).unwrap();
+
+        let watcher = RuleWatcher::new(&path).unwrap();
+        assert_eq!(*watcher.rule(), first);
+
+        fs::write(&path, second.to_json()).unwrap();
+        assert!(eventually(|| *watcher.rule() == second));
+        assert_eq!(watcher.last_error(), None);
+
+        fs::write(&path, "{ not json").unwrap();
+        assert!(eventually(|| watcher.last_error().is_some()));
+        assert_eq!(*watcher.rule(), second);
+
+        // rules failing validation are rejected like malformed ones
+        let bad_range = r#"{"Rule":{"desc":"r","field":"x","constraint":
+            {"Range":{"start":2.0,"start_incl":true,"end":1.0,"end_incl":true}}}}"#;
+        fs::write(&path, bad_range).unwrap();
+        assert!(watcher.reload().is_err());
+        assert_eq!(*watcher.rule(), second);
+
+        fs::write(&path, first.to_json()).unwrap();
+        watcher.reload().unwrap();
+        assert_eq!(*watcher.rule(), first);
+        assert_eq!(watcher.last_error(), None);
+    }
+
+    #[test]
+    fn loads_yaml_and_json_by_extension() {
+        let dir = TempDir::new("load");
+        let rule = crate::and(vec![crate::int_range("age", "age", 18, 65)]);
+        let yaml = dir.0.join("rule.yaml");
+        fs::write(&yaml, serde_yaml::to_string(&rule).unwrap()).unwrap();
+        assert_eq!(load_rule_file(&yaml).unwrap(), rule);
+        let json = dir.0.join("rule.json");
+        fs::write(&json, rule.to_json()).unwrap();
+        assert_eq!(load_rule_file(&json).unwrap(), rule);
+        assert!(load_rule_file(&dir.0.join("missing.json")).is_err());
+        assert!(RuleWatcher::new(dir.0.join("missing.json")).is_err());
+    }
+}